@@ -0,0 +1,206 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::commands::endpoints::{parse_endpoint_list, EndpointPool};
+use crate::commands::http::HttpClient;
+use crate::commands::transport::{self, Transport};
+
+// Эндпоинты manga-translator-api, которыми пользуется приложение
+pub const KNOWN_ENDPOINTS: &[&str] = &[
+    "detect_text_areas",
+    "detect_panels",
+    "recognize_images_batch",
+    "inpaint",
+    "inpaint_lama",
+    "inpaint_manual",
+    "inpaint_auto_text",
+];
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelCatalog {
+    pub detection: Vec<String>,
+    pub ocr: Vec<String>,
+    pub inpaint: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    pub api_url: String,
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub version: Option<String>,
+    // имя эндпоинта -> есть ли он на сервере
+    pub endpoints: BTreeMap<String, bool>,
+    pub models: ModelCatalog,
+    // false — сервер не сообщил список моделей, UI не должен их ограничивать
+    pub models_known: bool,
+    // эндпоинт -> как слать ему картинки (json, multipart, raw)
+    pub transports: BTreeMap<String, Transport>,
+    // что пошло не так при опросе (не фатально)
+    pub warnings: Vec<String>,
+}
+
+fn string_list(v: Option<&Value>) -> Vec<String> {
+    v.and_then(|v| v.as_array())
+        .map(|a| {
+            a.iter()
+                .filter_map(|m| {
+                    m.as_str()
+                        .map(str::to_string)
+                        .or_else(|| m.get("id").and_then(|id| id.as_str()).map(str::to_string))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn get_json(client: &HttpClient, url: &str) -> Result<Value, String> {
+    let resp = client.get(url).timeout(PROBE_TIMEOUT).send().await.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("{} -> HTTP {}", url, resp.status()));
+    }
+    resp.json::<Value>().await.map_err(|e| format!("{}: {}", url, e))
+}
+
+// POST-эндпоинт на GET отвечает 405, отсутствующий — 404
+async fn endpoint_exists(client: &HttpClient, base: &str, name: &str) -> bool {
+    match client.get(&format!("{}/{}", base, name)).timeout(PROBE_TIMEOUT).send().await {
+        Ok(resp) => resp.status() != reqwest::StatusCode::NOT_FOUND,
+        Err(_) => false,
+    }
+}
+
+pub async fn probe(client: &HttpClient, api_url: &str) -> Capabilities {
+    let base = api_url.trim_end_matches('/');
+    let mut caps = Capabilities {
+        api_url: base.to_string(),
+        ..Default::default()
+    };
+
+    // 1. Доступность и задержка
+    let started = Instant::now();
+    match client.get(base).timeout(PROBE_TIMEOUT).send().await {
+        Ok(_) => {
+            caps.reachable = true;
+            caps.latency_ms = Some(started.elapsed().as_millis() as u64);
+        }
+        Err(e) => {
+            caps.warnings.push(format!("Backend is not reachable: {}", e));
+            for name in KNOWN_ENDPOINTS {
+                caps.endpoints.insert(name.to_string(), false);
+            }
+            return caps;
+        }
+    }
+
+    // тела запросов, которые сервер объявил для каждого эндпоинта
+    let mut offered: BTreeMap<String, Vec<Transport>> = BTreeMap::new();
+
+    // 2. Явный документ возможностей, если сервер его отдаёт
+    if let Ok(doc) = get_json(client, &format!("{}/capabilities", base)).await {
+        offered.extend(transport::from_capabilities(&doc));
+        caps.version = doc.get("version").and_then(|v| v.as_str()).map(str::to_string);
+        if let Some(models) = doc.get("models") {
+            caps.models = ModelCatalog {
+                detection: string_list(models.get("detection")),
+                ocr: string_list(models.get("ocr")),
+                inpaint: string_list(models.get("inpaint")),
+            };
+            caps.models_known = true;
+        }
+        if let Some(list) = doc.get("endpoints").and_then(|e| e.as_array()) {
+            for e in list.iter().filter_map(|e| e.as_str()) {
+                caps.endpoints.insert(e.trim_start_matches('/').to_string(), true);
+            }
+        }
+    }
+
+    // 3. FastAPI: версия и список путей из openapi.json
+    match get_json(client, &format!("{}/openapi.json", base)).await {
+        Ok(spec) => {
+            if caps.version.is_none() {
+                caps.version = spec
+                    .pointer("/info/version")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string);
+            }
+            if let Some(paths) = spec.get("paths").and_then(|p| p.as_object()) {
+                for path in paths.keys() {
+                    caps.endpoints.insert(path.trim_start_matches('/').to_string(), true);
+                }
+            }
+            // /capabilities главнее: там транспорт объявлен явно
+            for (name, list) in transport::from_openapi(&spec) {
+                offered.entry(name).or_insert(list);
+            }
+        }
+        Err(e) => caps.warnings.push(format!("No OpenAPI spec: {}", e)),
+    }
+
+    // 4. Что не нашли в документах — проверяем запросом
+    for name in KNOWN_ENDPOINTS {
+        if !caps.endpoints.contains_key(*name) {
+            let exists = endpoint_exists(client, base, name).await;
+            caps.endpoints.insert(name.to_string(), exists);
+        }
+    }
+
+    for name in KNOWN_ENDPOINTS {
+        let t = offered.get(*name).map(|o| transport::negotiate(o)).unwrap_or_default();
+        caps.transports.insert(name.to_string(), t);
+    }
+
+    // 5. Список моделей, если его не было в /capabilities
+    if !caps.models_known {
+        if let Ok(models) = get_json(client, &format!("{}/models", base)).await {
+            caps.models = ModelCatalog {
+                detection: string_list(models.get("detection")),
+                ocr: string_list(models.get("ocr")),
+                inpaint: string_list(models.get("inpaint")),
+            };
+            caps.models_known = true;
+        }
+    }
+
+    caps
+}
+
+// Опрашиваем все узлы списка сразу и запоминаем транспорт каждого — пул при failover шлёт
+// каждому так, как тот умеет. Возвращаем возможности первого доступного узла.
+#[command]
+pub async fn probe_backend(
+    app: AppHandle,
+    pool: State<'_, EndpointPool>,
+    http: State<'_, HttpClient>,
+    api_url: String,
+) -> Result<Capabilities, String> {
+    let nodes = parse_endpoint_list(&api_url);
+    if nodes.is_empty() {
+        return Ok(probe(&http, &api_url).await);
+    }
+    let handles: Vec<_> = nodes
+        .into_iter()
+        .map(|(url, _)| {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let http = app.state::<HttpClient>();
+                probe(&http, &url).await
+            })
+        })
+        .collect();
+    let mut all = Vec::with_capacity(handles.len());
+    for handle in handles {
+        all.push(handle.await.map_err(|e| e.to_string())?);
+    }
+    for caps in &all {
+        pool.transports.set(&caps.api_url, caps.transports.clone());
+    }
+    let first = all.iter().position(|c| c.reachable).unwrap_or(0);
+    Ok(all.swap_remove(first))
+}
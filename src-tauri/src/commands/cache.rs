@@ -0,0 +1,474 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tauri::{command, State};
+
+use crate::commands::endpoints::EndpointPool;
+use crate::commands::http::HttpClient;
+
+pub const DEFAULT_MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+struct Entry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+#[derive(Default)]
+struct Index {
+    dir: Option<PathBuf>,
+    entries: HashMap<String, Entry>,
+    total: u64,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    index: Mutex<Index>,
+    max_bytes: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    // имена временных файлов параллельных записей
+    writes: AtomicU64,
+}
+
+// Дисковый кэш ответов manga-translator-api: детекция, OCR, инпейнт.
+// Ключ — SHA-256 от эндпоинта, хэшей самих картинок (декодированных байт, а не base64 с обёрткой)
+// и остальных параметров, так что одинаковые входы на любом узле пула дают один и тот же ответ.
+// Под замком только индекс; файлы читаются и пишутся без него (из post_cached — в spawn_blocking).
+#[derive(Clone)]
+pub struct ResultCache(Arc<CacheInner>);
+
+impl Default for ResultCache {
+    fn default() -> Self {
+        let inner = CacheInner { max_bytes: AtomicU64::new(DEFAULT_MAX_BYTES), ..Default::default() };
+        ResultCache(Arc::new(inner))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// Поля запросов с картинками: base64 (или data URL) либо их список
+const IMAGE_FIELDS: [&str; 3] = ["image_data", "mask_data", "images_data"];
+
+fn image_digest(data: &str) -> String {
+    let b64 = data.split_once(',').map(|(_, b)| b).unwrap_or(data).trim();
+    let digest = match STANDARD.decode(b64) {
+        Ok(bytes) => Sha256::digest(&bytes),
+        // не base64 — хэшируем как есть
+        Err(_) => Sha256::digest(b64.as_bytes()),
+    };
+    format!("{:x}", digest)
+}
+
+pub fn cache_key(endpoint: &str, payload: &Value) -> String {
+    let mut params = payload.clone();
+    let mut images = Vec::new();
+    if let Some(map) = params.as_object_mut() {
+        for field in IMAGE_FIELDS {
+            let digests = match map.remove(field) {
+                Some(Value::String(s)) => vec![image_digest(&s)],
+                Some(Value::Array(list)) => list.iter().map(|v| v.as_str().map(image_digest).unwrap_or_default()).collect(),
+                Some(other) => vec![other.to_string()],
+                None => continue,
+            };
+            images.push((field, digests));
+        }
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(endpoint.as_bytes());
+    hasher.update([0u8]);
+    for (field, digests) in images {
+        hasher.update(field.as_bytes());
+        hasher.update((digests.len() as u64).to_le_bytes());
+        for d in digests {
+            hasher.update(d.as_bytes());
+        }
+    }
+    hasher.update([0u8]);
+    // serde_json::Map без preserve_order сортирует ключи — сериализация детерминирована
+    hasher.update(params.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+// Ключ — SHA-256 в hex: только такие файлы и считаются записями кэша
+fn is_key(key: &str) -> bool {
+    key.len() == 64 && key.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn entry_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(&key[..2]).join(format!("{}.json", key))
+}
+
+impl ResultCache {
+    // Поднимает индекс с диска; время последнего доступа — mtime файла.
+    // Недописанные *.tmp после падения и посторонние файлы удаляем — в индекс они не входят.
+    pub fn init(&self, dir: PathBuf) {
+        let mut entries = HashMap::new();
+        let mut total = 0;
+        if let Ok(shards) = std::fs::read_dir(&dir) {
+            for file in shards.flatten().filter_map(|s| std::fs::read_dir(s.path()).ok()).flatten().flatten() {
+                let path = file.path();
+                // недописанные *.tmp и всё чужое (не <ключ>.json в своей папке) удаляем
+                let key = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .filter(|k| is_key(k) && path.extension().and_then(|e| e.to_str()) == Some("json"))
+                    .filter(|k| entry_path(&dir, k) == path)
+                    .map(str::to_string);
+                let Some(key) = key else {
+                    let _ = std::fs::remove_file(&path);
+                    continue;
+                };
+                if let Ok(meta) = file.metadata() {
+                    total += meta.len();
+                    let last_used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    entries.insert(key, Entry { size: meta.len(), last_used });
+                }
+            }
+        }
+        *self.0.index.lock().unwrap() = Index { dir: Some(dir), entries, total };
+        self.evict();
+    }
+
+    // Блокирующее чтение с диска
+    pub fn get(&self, key: &str) -> Option<Value> {
+        if self.0.max_bytes.load(Ordering::Relaxed) == 0 || !is_key(key) {
+            return None;
+        }
+        let path = {
+            let index = self.0.index.lock().unwrap();
+            let dir = index.dir.as_ref()?;
+            index.entries.get(key)?;
+            entry_path(dir, key)
+        };
+        let value = std::fs::read(&path).ok().and_then(|b| serde_json::from_slice::<Value>(&b).ok());
+
+        let mut index = self.0.index.lock().unwrap();
+        match value {
+            Some(value) => {
+                let now = SystemTime::now();
+                if let Some(entry) = index.entries.get_mut(key) {
+                    entry.last_used = now;
+                }
+                drop(index);
+                // переживает перезапуск: LRU по mtime
+                if let Ok(f) = std::fs::File::options().write(true).open(&path) {
+                    let _ = f.set_modified(now);
+                }
+                self.0.hits.fetch_add(1, Ordering::Relaxed);
+                Some(value)
+            }
+            None => {
+                // файл удалили или он битый — забываем
+                if let Some(entry) = index.entries.remove(key) {
+                    index.total = index.total.saturating_sub(entry.size);
+                }
+                drop(index);
+                let _ = std::fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    // Блокирующая запись на диск
+    pub fn put(&self, key: &str, value: &Value) {
+        let max = self.0.max_bytes.load(Ordering::Relaxed);
+        let bytes = value.to_string().into_bytes();
+        if max == 0 || bytes.len() as u64 > max || !is_key(key) {
+            return;
+        }
+        let Some(dir) = self.0.index.lock().unwrap().dir.clone() else {
+            return;
+        };
+        let path = entry_path(&dir, key);
+        let tmp = path.with_extension(format!("{}.tmp", self.0.writes.fetch_add(1, Ordering::Relaxed)));
+        let written = path
+            .parent()
+            .map(std::fs::create_dir_all)
+            .unwrap_or(Ok(()))
+            .and_then(|_| std::fs::write(&tmp, &bytes))
+            .and_then(|_| std::fs::rename(&tmp, &path));
+        if written.is_err() {
+            // кэш — не данные пользователя: не записали, значит в следующий раз спросим бэкенд
+            let _ = std::fs::remove_file(&tmp);
+            return;
+        }
+        {
+            let mut index = self.0.index.lock().unwrap();
+            let size = bytes.len() as u64;
+            if let Some(old) = index.entries.insert(key.to_string(), Entry { size, last_used: SystemTime::now() }) {
+                index.total = index.total.saturating_sub(old.size);
+            }
+            index.total += size;
+        }
+        self.evict();
+    }
+
+    // Удаляет самые давно использованные записи, пока кэш больше лимита; файлы — уже без замка
+    fn evict(&self) {
+        let max = self.0.max_bytes.load(Ordering::Relaxed);
+        let (dir, victims) = {
+            let mut index = self.0.index.lock().unwrap();
+            if index.total <= max {
+                return;
+            }
+            let Some(dir) = index.dir.clone() else {
+                return;
+            };
+            let mut by_age: Vec<(String, SystemTime)> =
+                index.entries.iter().map(|(k, e)| (k.clone(), e.last_used)).collect();
+            by_age.sort_by_key(|(_, t)| *t);
+            let mut victims = Vec::new();
+            for (key, _) in by_age {
+                if index.total <= max {
+                    break;
+                }
+                if let Some(e) = index.entries.remove(&key) {
+                    index.total = index.total.saturating_sub(e.size);
+                    victims.push(key);
+                }
+            }
+            (dir, victims)
+        };
+        for key in victims {
+            let _ = std::fs::remove_file(entry_path(&dir, &key));
+        }
+    }
+
+    pub fn set_max_bytes(&self, max: u64) {
+        self.0.max_bytes.store(max, Ordering::Relaxed);
+        self.evict();
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        let dir = {
+            let mut index = self.0.index.lock().unwrap();
+            index.entries.clear();
+            index.total = 0;
+            index.dir.clone()
+        };
+        self.0.hits.store(0, Ordering::Relaxed);
+        self.0.misses.store(0, Ordering::Relaxed);
+        match dir.filter(|d| d.exists()) {
+            Some(dir) => std::fs::remove_dir_all(dir).map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let index = self.0.index.lock().unwrap();
+        CacheStats {
+            entries: index.entries.len(),
+            bytes: index.total,
+            max_bytes: self.0.max_bytes.load(Ordering::Relaxed),
+            hits: self.0.hits.load(Ordering::Relaxed),
+            misses: self.0.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// POST в бэкенд через пул узлов, но сначала — в кэш. Ключ (декодирование картинок) и файлы —
+// в spawn_blocking, чтобы не держать async-рантайм
+pub async fn post_cached(
+    pool: &EndpointPool,
+    http: &HttpClient,
+    cache: &ResultCache,
+    api_url: &str,
+    path: &str,
+    payload: &Value,
+) -> Result<Value, String> {
+    post_cached_checked(pool, http, cache, api_url, path, payload, |_| Ok(())).await
+}
+
+// Как post_cached, но ответ сначала проверяется: негодный не кэшируется и уходит в Err,
+// негодная запись из кэша считается промахом
+pub async fn post_cached_checked(
+    pool: &EndpointPool,
+    http: &HttpClient,
+    cache: &ResultCache,
+    api_url: &str,
+    path: &str,
+    payload: &Value,
+    check: impl Fn(&Value) -> Result<(), String>,
+) -> Result<Value, String> {
+    let (reader, endpoint, request) = (cache.clone(), path.to_string(), payload.clone());
+    let (key, hit) = tauri::async_runtime::spawn_blocking(move || {
+        let key = cache_key(&endpoint, &request);
+        let hit = reader.get(&key);
+        (key, hit)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    if let Some(hit) = hit.filter(|h| check(h).is_ok()) {
+        return Ok(hit);
+    }
+    cache.0.misses.fetch_add(1, Ordering::Relaxed);
+    let value = pool.post_json(http, api_url, path, payload).await?;
+    check(&value)?;
+    let (writer, stored) = (cache.clone(), value.clone());
+    tauri::async_runtime::spawn_blocking(move || writer.put(&key, &stored));
+    Ok(value)
+}
+
+#[command]
+pub async fn get_cache_stats(cache: State<'_, ResultCache>) -> Result<CacheStats, String> {
+    Ok(cache.stats())
+}
+
+// 0 — кэш выключен
+#[command]
+pub async fn set_cache_limit(cache: State<'_, ResultCache>, max_mb: u64) -> Result<CacheStats, String> {
+    cache.set_max_bytes(max_mb * 1024 * 1024);
+    Ok(cache.stats())
+}
+
+#[command]
+pub async fn clear_cache(cache: State<'_, ResultCache>) -> Result<CacheStats, String> {
+    cache.clear()?;
+    Ok(cache.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(c: char) -> String {
+        c.to_string().repeat(64)
+    }
+
+    fn temp_cache(name: &str, max: u64) -> (ResultCache, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("result-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = ResultCache::default();
+        cache.set_max_bytes(max);
+        cache.init(dir.clone());
+        (cache, dir)
+    }
+
+    #[test]
+    fn same_image_under_different_wrappers_shares_key() {
+        let b64 = STANDARD.encode([1u8, 2, 3, 4]);
+        let plain = json!({ "image_data": b64, "detector": "ctd" });
+        let png = json!({ "image_data": format!("data:image/png;base64,{}", b64), "detector": "ctd" });
+        let octet = json!({ "image_data": format!("data:application/octet-stream;base64,{}", b64), "detector": "ctd" });
+        assert_eq!(cache_key("/detect", &plain), cache_key("/detect", &png));
+        assert_eq!(cache_key("/detect", &plain), cache_key("/detect", &octet));
+    }
+
+    #[test]
+    fn params_endpoint_and_images_change_key() {
+        let a = json!({ "image_data": STANDARD.encode([1u8, 2]), "detector": "ctd" });
+        let b = json!({ "image_data": STANDARD.encode([1u8, 2]), "detector": "yolo" });
+        let c = json!({ "image_data": STANDARD.encode([1u8, 3]), "detector": "ctd" });
+        let mask = json!({ "mask_data": STANDARD.encode([1u8, 2]), "detector": "ctd" });
+        assert_ne!(cache_key("/detect", &a), cache_key("/detect", &b));
+        assert_ne!(cache_key("/detect", &a), cache_key("/detect", &c));
+        assert_ne!(cache_key("/detect", &a), cache_key("/inpaint", &a));
+        assert_ne!(cache_key("/detect", &a), cache_key("/detect", &mask));
+    }
+
+    #[test]
+    fn image_lists_are_hashed_in_order() {
+        let (x, y) = (STANDARD.encode([1u8]), STANDARD.encode([2u8]));
+        let xy = json!({ "images_data": [x.clone(), y.clone()] });
+        let yx = json!({ "images_data": [y, format!("data:image/png;base64,{}", x)] });
+        let xy_wrapped = json!({ "images_data": [format!("data:image/png;base64,{}", STANDARD.encode([1u8])), STANDARD.encode([2u8])] });
+        assert_ne!(cache_key("/ocr", &xy), cache_key("/ocr", &yx));
+        assert_eq!(cache_key("/ocr", &xy), cache_key("/ocr", &xy_wrapped));
+    }
+
+    #[test]
+    fn put_then_get_roundtrip() {
+        let (cache, dir) = temp_cache("roundtrip", DEFAULT_MAX_BYTES);
+        let value = json!({ "boxes": [[1, 2, 3, 4]] });
+        assert_eq!(cache.get(&key('a')), None);
+        cache.put(&key('a'), &value);
+        assert_eq!(cache.get(&key('a')), Some(value.clone()));
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.stats().bytes, value.to_string().len() as u64);
+
+        // индекс переживает перезапуск
+        let reopened = ResultCache::default();
+        reopened.init(dir.clone());
+        assert_eq!(reopened.get(&key('a')), Some(value));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let entry = json!({ "data": "x".repeat(90) });
+        let size = entry.to_string().len() as u64;
+        let (cache, dir) = temp_cache("evict", size * 2);
+        cache.put(&key('b'), &entry);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        cache.put(&key('c'), &entry);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        // aa01 свежее bb02
+        assert!(cache.get(&key('b')).is_some());
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        cache.put(&key('d'), &entry);
+        assert!(cache.get(&key('c')).is_none());
+        assert!(cache.get(&key('b')).is_some());
+        assert!(cache.get(&key('d')).is_some());
+        assert!(!entry_path(&dir, &key('c')).exists());
+        assert!(cache.stats().bytes <= size * 2);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn init_skips_and_removes_tmp_files() {
+        let (cache, dir) = temp_cache("tmp", DEFAULT_MAX_BYTES);
+        cache.put(&key('a'), &json!({ "ok": true }));
+        let tmp = dir.join("aa").join(format!("{}.7.tmp", key('a')));
+        std::fs::write(&tmp, "x".repeat(1000)).unwrap();
+
+        let reopened = ResultCache::default();
+        reopened.init(dir.clone());
+        assert_eq!(reopened.stats().entries, 1);
+        assert_eq!(reopened.stats().bytes, json!({ "ok": true }).to_string().len() as u64);
+        assert!(!tmp.exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn init_removes_foreign_files() {
+        let (cache, dir) = temp_cache("foreign", DEFAULT_MAX_BYTES);
+        cache.put(&key('a'), &json!({ "ok": true }));
+        let stray = [
+            dir.join("aa").join("a.json"),
+            dir.join("aa").join(format!("{}.json", key('a').to_uppercase())),
+            dir.join("aa").join("ÿ.json"),
+            // правильный ключ, но не в своей папке
+            dir.join("aa").join(format!("{}.json", key('b'))),
+            dir.join("zz").join("notes.txt"),
+        ];
+        std::fs::create_dir_all(dir.join("zz")).unwrap();
+        for f in &stray {
+            std::fs::write(f, "{}").unwrap();
+        }
+
+        let reopened = ResultCache::default();
+        reopened.init(dir.clone());
+        assert_eq!(reopened.stats().entries, 1);
+        assert!(stray.iter().all(|f| !f.exists()));
+        // вытеснение и чтение не спотыкаются
+        reopened.set_max_bytes(1);
+        assert_eq!(reopened.stats().entries, 0);
+        assert_eq!(reopened.get("a"), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,213 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::imageops::FilterType;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::commands::cache::{self, ResultCache};
+use crate::commands::endpoints::EndpointPool;
+use crate::commands::http::HttpClient;
+use crate::commands::tiling::{self, TileOptions};
+
+// Размеры по заголовку, без декодирования пикселей
+pub fn dimensions(bytes: &[u8]) -> Result<(u32, u32), String> {
+    image::io::Reader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .into_dimensions()
+        .map_err(|e| e.to_string())
+}
+
+// Уменьшает страницу перед детекцией, чтобы большая сторона была не больше max_side.
+// Возвращает base64 для отправки и коэффициенты "оригинал / отправленное" по x и y (1.0 — не уменьшали):
+// у длинной полосы узкая сторона сильно округляется, и один общий коэффициент сдвигает низ страницы.
+pub fn downscale_b64(image_b64: &str, max_side: u32) -> Result<(String, Scale), String> {
+    let b64 = image_b64.split_once(',').map(|(_, b)| b).unwrap_or(image_b64);
+    if max_side == 0 {
+        return Ok((b64.to_string(), Scale::ONE));
+    }
+    let bytes = STANDARD.decode(b64).map_err(|e| e.to_string())?;
+    let (w, h) = dimensions(&bytes)?;
+    if w.max(h) <= max_side {
+        return Ok((b64.to_string(), Scale::ONE));
+    }
+
+    let img = image::load_from_memory(&bytes).map_err(|e| e.to_string())?;
+    let small = img.resize(max_side, max_side, FilterType::Triangle);
+    // боксам пиксельная точность не нужна — JPEG заметно меньше PNG
+    let mut buf = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgb8(small.to_rgb8())
+        .write_to(&mut buf, image::ImageOutputFormat::Jpeg(90))
+        .map_err(|e| e.to_string())?;
+    let k = Scale { x: w as f64 / small.width() as f64, y: h as f64 / small.height() as f64 };
+    Ok((STANDARD.encode(buf.into_inner()), k))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Scale {
+    pub const ONE: Scale = Scale { x: 1.0, y: 1.0 };
+}
+
+fn scale_coords(v: &mut Value, k: Scale) {
+    match v {
+        // {x1, y1, x2, y2, ...} — прочие поля (score, class) не трогаем
+        Value::Object(map) => {
+            for (key, k) in [("x1", k.x), ("y1", k.y), ("x2", k.x), ("y2", k.y)] {
+                if let Some(n) = map.get(key).and_then(|n| n.as_f64()) {
+                    map.insert(key.to_string(), Value::from(n * k));
+                }
+            }
+        }
+        // [x1, y1, x2, y2]: чётные — x, нечётные — y
+        Value::Array(nums) => {
+            for (i, n) in nums.iter_mut().enumerate() {
+                if let Some(v) = n.as_f64() {
+                    *n = Value::from(v * if i % 2 == 0 { k.x } else { k.y });
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Переводит boxes/panels ответа детекции обратно в координаты оригинала
+pub fn remap(resp: &mut Value, k: Scale) {
+    if k == Scale::ONE {
+        return;
+    }
+    for key in ["boxes", "panels"] {
+        if let Some(list) = resp.get_mut(key).and_then(|l| l.as_array_mut()) {
+            list.iter_mut().for_each(|b| scale_coords(b, k));
+        }
+    }
+}
+
+// Как готовить страницу к детекции
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectPrep {
+    // уменьшать до этой большей стороны (0/None — как есть)
+    pub max_side: Option<u32>,
+    // длинные страницы резать на тайлы (None — целиком)
+    pub tiling: Option<TileOptions>,
+}
+
+// POST детекции с уменьшенной страницей; координаты в ответе — оригинала
+async fn post_scaled(
+    pool: &EndpointPool,
+    http: &HttpClient,
+    cache: &ResultCache,
+    api_url: &str,
+    path: &str,
+    mut payload: Value,
+    max_side: Option<u32>,
+) -> Result<Value, String> {
+    let max_side = max_side.unwrap_or(0);
+    let image = payload.get("image_data").and_then(|d| d.as_str()).map(str::to_string);
+    let k = match image {
+        Some(image) if max_side > 0 => {
+            let (small, k) = tauri::async_runtime::spawn_blocking(move || downscale_b64(&image, max_side))
+                .await
+                .map_err(|e| e.to_string())??;
+            payload["image_data"] = Value::String(small);
+            k
+        }
+        _ => Scale::ONE,
+    };
+    let mut resp = cache::post_cached(pool, http, cache, api_url, path, &payload).await?;
+    remap(&mut resp, k);
+    Ok(resp)
+}
+
+// Детекция страницы: длинную режем на тайлы (каждый ещё и уменьшается),
+// боксы/панели сдвигаем обратно и склеиваем дубли на швах
+pub async fn post_detect(
+    pool: &EndpointPool,
+    http: &HttpClient,
+    cache: &ResultCache,
+    api_url: &str,
+    path: &str,
+    mut payload: Value,
+    prep: &DetectPrep,
+) -> Result<Value, String> {
+    let Some(opts) = prep.tiling.clone() else {
+        return post_scaled(pool, http, cache, api_url, path, payload, prep.max_side).await;
+    };
+    // вынимаем страницу, чтобы не копировать её в запрос каждого тайла
+    let Some(Value::String(image)) = payload.as_object_mut().and_then(|m| m.remove("image_data")) else {
+        return post_scaled(pool, http, cache, api_url, path, payload, prep.max_side).await;
+    };
+    let split_opts = opts.clone();
+    let (image, tiles) = tauri::async_runtime::spawn_blocking(move || {
+        let tiles = tiling::split_tiles(&image, &split_opts)?;
+        Ok::<_, String>((image, tiles))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    let Some(tiles) = tiles else {
+        payload["image_data"] = Value::String(image);
+        return post_scaled(pool, http, cache, api_url, path, payload, prep.max_side).await;
+    };
+
+    let spans: Vec<(u32, u32)> = tiles.iter().map(|t| (t.y, t.height)).collect();
+    let (mut boxes, mut panels) = (Vec::new(), Vec::new());
+    let mut merged = Value::Null;
+    for tile in tiles {
+        let mut tile_payload = payload.clone();
+        tile_payload["image_data"] = Value::String(tile.image_data);
+        let mut resp = post_scaled(pool, http, cache, api_url, path, tile_payload, prep.max_side).await?;
+        boxes.push(tiling::offset(&mut resp, "boxes", tile.y as f64));
+        panels.push(tiling::offset(&mut resp, "panels", tile.y as f64));
+        merged = resp;
+    }
+    for (key, lists) in [("boxes", boxes), ("panels", panels)] {
+        if merged.get(key).is_some() {
+            merged[key] = Value::Array(tiling::merge_tiles(lists, &spans, &opts));
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+
+    fn png_b64(w: u32, h: u32) -> String {
+        let mut buf = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::new(w, h)).write_to(&mut buf, image::ImageOutputFormat::Png).unwrap();
+        STANDARD.encode(buf.into_inner())
+    }
+
+    #[test]
+    fn small_page_is_sent_as_is() {
+        let (_, k) = downscale_b64(&png_b64(300, 400), 1024).unwrap();
+        assert_eq!(k, Scale::ONE);
+    }
+
+    #[test]
+    fn tall_strip_scales_axes_separately() {
+        // 100x3000 -> 17x512: ширина округляется сильно, высота точно
+        let (small, k) = downscale_b64(&png_b64(100, 3000), 512).unwrap();
+        let (w, h) = dimensions(&STANDARD.decode(small).unwrap()).unwrap();
+        assert_eq!((w, h), (17, 512));
+        assert!((k.x - 100.0 / 17.0).abs() < 1e-9);
+        assert!((k.y - 3000.0 / 512.0).abs() < 1e-9);
+
+        // бокс у нижнего края уменьшенной страницы возвращается к нижнему краю оригинала
+        let mut resp = serde_json::json!({
+            "boxes": [{ "x1": 0.0, "y1": 500.0, "x2": 17.0, "y2": 512.0, "score": 0.9 }],
+            "panels": [[0.0, 0.0, 17.0, 512.0]],
+        });
+        remap(&mut resp, k);
+        assert!((resp["boxes"][0]["y2"].as_f64().unwrap() - 3000.0).abs() < 1e-6);
+        assert!((resp["boxes"][0]["x2"].as_f64().unwrap() - 100.0).abs() < 1e-6);
+        assert_eq!(resp["boxes"][0]["score"], 0.9);
+        let panel: Vec<f64> = resp["panels"][0].as_array().unwrap().iter().map(|v| v.as_f64().unwrap()).collect();
+        assert!((panel[2] - 100.0).abs() < 1e-6 && (panel[3] - 3000.0).abs() < 1e-6);
+    }
+}
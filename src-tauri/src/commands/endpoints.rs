@@ -0,0 +1,327 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{command, State};
+
+use crate::commands::http::HttpClient;
+use crate::commands::transport::{self, Transport, TransportTable};
+
+// После стольких ошибок подряд узел считается упавшим
+const FAILURE_THRESHOLD: u32 = 3;
+// Упавший узел не выбираем до повторной проверки (или пока не истечёт пауза)
+const DOWN_COOLDOWN: Duration = Duration::from_secs(30);
+pub const REPROBE_INTERVAL: Duration = Duration::from_secs(15);
+
+// Строка настроек "http://gpu1:8000*2, http://gpu2:8000" -> [(url, вес)]
+pub fn parse_endpoint_list(spec: &str) -> Vec<(String, u32)> {
+    spec.split([',', ';', '\n'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| match s.rsplit_once('*') {
+            Some((url, w)) if w.trim().parse::<u32>().is_ok() => {
+                (url.trim().trim_end_matches('/').to_string(), w.trim().parse::<u32>().unwrap().max(1))
+            }
+            _ => (s.trim_end_matches('/').to_string(), 1),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeStatus {
+    pub url: String,
+    pub weight: u32,
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    pub total_requests: u64,
+    pub total_failures: u64,
+    pub last_error: Option<String>,
+}
+
+struct Node {
+    url: String,
+    weight: u32,
+    // smooth weighted round-robin (как в nginx)
+    current: i64,
+    failures: u32,
+    down_until: Option<Instant>,
+    total_requests: u64,
+    total_failures: u64,
+    last_error: Option<String>,
+}
+
+impl Node {
+    fn new(url: String, weight: u32) -> Self {
+        Node {
+            url,
+            weight,
+            current: 0,
+            failures: 0,
+            down_until: None,
+            total_requests: 0,
+            total_failures: 0,
+            last_error: None,
+        }
+    }
+
+    fn is_up(&self, now: Instant) -> bool {
+        self.down_until.is_none_or(|t| now >= t)
+    }
+
+    fn status(&self, now: Instant) -> NodeStatus {
+        NodeStatus {
+            url: self.url.clone(),
+            weight: self.weight,
+            healthy: self.is_up(now),
+            consecutive_failures: self.failures,
+            total_requests: self.total_requests,
+            total_failures: self.total_failures,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+// Пул узлов manga-translator-api. Состояние здоровья живёт между вызовами команд,
+// а список узлов берётся из той же строки api_url, что приходит в каждую команду.
+#[derive(Default)]
+pub struct EndpointPool {
+    nodes: Mutex<Vec<Node>>,
+    // как слать картинки на каждый узел (заполняется при опросе возможностей)
+    pub transports: TransportTable,
+}
+
+impl EndpointPool {
+    // Синхронизирует узлы со строкой настроек, сохраняя статистику уже известных
+    fn sync(nodes: &mut Vec<Node>, spec: &[(String, u32)]) {
+        nodes.retain(|n| spec.iter().any(|(u, _)| *u == n.url));
+        for (url, weight) in spec {
+            match nodes.iter_mut().find(|n| n.url == *url) {
+                Some(n) => n.weight = *weight,
+                None => nodes.push(Node::new(url.clone(), *weight)),
+            }
+        }
+        // порядок как в настройках — чтобы выбор был предсказуемым
+        nodes.sort_by_key(|n| spec.iter().position(|(u, _)| *u == n.url));
+    }
+
+    // Порядок попыток: взвешенно выбранный живой узел, остальные живые, затем упавшие
+    pub fn candidates(&self, api_url: &str) -> Vec<String> {
+        let spec = parse_endpoint_list(api_url);
+        if spec.len() <= 1 {
+            let url = spec.into_iter().next().map(|(u, _)| u);
+            return vec![url.unwrap_or_else(|| api_url.trim_end_matches('/').to_string())];
+        }
+
+        let mut nodes = self.nodes.lock().unwrap();
+        Self::sync(&mut nodes, &spec);
+        let now = Instant::now();
+
+        let total: i64 = nodes.iter().filter(|n| n.is_up(now)).map(|n| n.weight as i64).sum();
+        let mut up: Vec<usize> = (0..nodes.len()).filter(|&i| nodes[i].is_up(now)).collect();
+        if total > 0 {
+            for &i in &up {
+                nodes[i].current += nodes[i].weight as i64;
+            }
+            up.sort_by_key(|&i| std::cmp::Reverse(nodes[i].current));
+            nodes[up[0]].current -= total;
+        }
+
+        let down = (0..nodes.len()).filter(|&i| !nodes[i].is_up(now));
+        up.into_iter().chain(down).map(|i| nodes[i].url.clone()).collect()
+    }
+
+    pub fn mark_ok(&self, url: &str) {
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(n) = nodes.iter_mut().find(|n| n.url == url) {
+            n.total_requests += 1;
+            n.failures = 0;
+            n.down_until = None;
+        }
+    }
+
+    pub fn mark_failed(&self, url: &str, error: &str) {
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(n) = nodes.iter_mut().find(|n| n.url == url) {
+            n.total_requests += 1;
+            n.total_failures += 1;
+            n.failures += 1;
+            n.last_error = Some(error.to_string());
+            if n.failures >= FAILURE_THRESHOLD {
+                n.down_until = Some(Instant::now() + DOWN_COOLDOWN);
+            }
+        }
+    }
+
+    // Узлы, которые ещё не оправились после серии ошибок
+    pub fn down_nodes(&self) -> Vec<String> {
+        let nodes = self.nodes.lock().unwrap();
+        nodes.iter().filter(|n| n.failures >= FAILURE_THRESHOLD).map(|n| n.url.clone()).collect()
+    }
+
+    pub fn status(&self) -> Vec<NodeStatus> {
+        let now = Instant::now();
+        self.nodes.lock().unwrap().iter().map(|n| n.status(now)).collect()
+    }
+
+    // POST на {узел}/{path} (JSON или бинарно — как договорились с узлом);
+    // при сетевой ошибке или 5xx повторяем на следующем узле
+    pub async fn post_json(&self, http: &HttpClient, api_url: &str, path: &str, payload: &Value) -> Result<Value, String> {
+        let mut last_err = String::from("No backend endpoints configured");
+        for base in self.candidates(api_url) {
+            let url = format!("{}/{}", base, path);
+            let mut wanted = self.transports.get(&base, path);
+            // повтор JSON-ом — такая же попытка: через лимитер и с той же обработкой 5xx
+            let (_permit, sent) = loop {
+                let permit = http.limit(&url, 0).await;
+                let (req, used) = transport::apply(http.post(&url), wanted, payload);
+                match req.send().await {
+                    // 415/422 на бинарный запрос — сервер его всё-таки не понимает, повторяем JSON-ом
+                    Ok(response) if used != Transport::Json && matches!(response.status().as_u16(), 415 | 422) => {
+                        self.transports.downgrade(&base, path);
+                        wanted = Transport::Json;
+                    }
+                    other => break (permit, other),
+                }
+            };
+            match sent {
+                Ok(response) if response.status().is_server_error() => {
+                    let status = response.status();
+                    let body = response.text().await.unwrap_or_default();
+                    last_err = format!("API Error: Status {}, Body: {}", status, body);
+                    self.mark_failed(&base, &last_err);
+                }
+                Ok(response) => {
+                    // 4xx — ошибка запроса, на другом узле будет то же самое
+                    self.mark_ok(&base);
+                    return transport::read_response(response).await;
+                }
+                Err(e) => {
+                    last_err = format!("{}: {}", base, e);
+                    self.mark_failed(&base, &last_err);
+                }
+            }
+        }
+        Err(last_err)
+    }
+}
+
+// Фоновая перепроверка упавших узлов: любой HTTP-ответ возвращает узел в пул
+pub async fn reprobe_loop(app: tauri::AppHandle) {
+    use tauri::Manager;
+    loop {
+        tokio::time::sleep(REPROBE_INTERVAL).await;
+        let pool = app.state::<EndpointPool>();
+        let http = app.state::<HttpClient>();
+        for url in pool.down_nodes() {
+            if http.get(&url).timeout(Duration::from_secs(5)).send().await.is_ok() {
+                pool.mark_ok(&url);
+            }
+        }
+    }
+}
+
+#[command]
+pub async fn get_endpoint_status(pool: State<'_, EndpointPool>) -> Result<Vec<NodeStatus>, String> {
+    Ok(pool.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(nodes: &[(&str, u32)]) -> EndpointPool {
+        let pool = EndpointPool::default();
+        let spec: Vec<(String, u32)> = nodes.iter().map(|(u, w)| (u.to_string(), *w)).collect();
+        EndpointPool::sync(&mut pool.nodes.lock().unwrap(), &spec);
+        pool
+    }
+
+    fn first(pool: &EndpointPool, spec: &str, rounds: usize) -> Vec<String> {
+        (0..rounds).map(|_| pool.candidates(spec)[0].clone()).collect()
+    }
+
+    #[test]
+    fn parses_weights_separators_and_trailing_slashes() {
+        let spec = "http://a:8000/*2, http://b:8000;\nhttp://c:8000 * 3 ,, http://d:8000*x";
+        assert_eq!(
+            parse_endpoint_list(spec),
+            vec![
+                ("http://a:8000".to_string(), 2),
+                ("http://b:8000".to_string(), 1),
+                ("http://c:8000".to_string(), 3),
+                // не число после * — это часть адреса
+                ("http://d:8000*x".to_string(), 1),
+            ]
+        );
+        // вес 0 поднимаем до 1
+        assert_eq!(parse_endpoint_list("http://a/*0"), vec![("http://a".to_string(), 1)]);
+        assert!(parse_endpoint_list(" , ;").is_empty());
+    }
+
+    #[test]
+    fn single_endpoint_bypasses_the_pool() {
+        let pool = EndpointPool::default();
+        assert_eq!(pool.candidates("http://a:8000/"), vec!["http://a:8000".to_string()]);
+        assert!(pool.status().is_empty());
+    }
+
+    #[test]
+    fn smooth_weighted_round_robin_order() {
+        let spec = "http://a*5, http://b, http://c";
+        let pool = EndpointPool::default();
+        // как в nginx: a a b a c a a, без серий из пяти a подряд
+        let picks = first(&pool, spec, 7);
+        let expected = ["http://a", "http://a", "http://b", "http://a", "http://c", "http://a", "http://a"];
+        assert_eq!(picks, expected);
+        // остальные живые узлы идут следом как запасные
+        let all = pool.candidates(spec);
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0], "http://a");
+    }
+
+    #[test]
+    fn node_goes_down_after_threshold_and_recovers() {
+        let spec = "http://a, http://b";
+        let pool = pool(&[("http://a", 1), ("http://b", 1)]);
+        for _ in 0..FAILURE_THRESHOLD - 1 {
+            pool.mark_failed("http://a", "boom");
+        }
+        assert!(pool.down_nodes().is_empty());
+        assert!(pool.status()[0].healthy);
+
+        pool.mark_failed("http://a", "boom");
+        assert_eq!(pool.down_nodes(), vec!["http://a".to_string()]);
+        // упавший узел — только в конце списка, первым всегда живой
+        for _ in 0..4 {
+            assert_eq!(pool.candidates(spec), vec!["http://b".to_string(), "http://a".to_string()]);
+        }
+        let status = &pool.status()[0];
+        assert!(!status.healthy);
+        assert_eq!((status.consecutive_failures, status.total_failures), (FAILURE_THRESHOLD, FAILURE_THRESHOLD as u64));
+        assert_eq!(status.last_error.as_deref(), Some("boom"));
+
+        // пауза истекла — узел снова выбирается, но ещё ждёт перепроверки
+        pool.nodes.lock().unwrap()[0].down_until = Some(Instant::now() - Duration::from_secs(1));
+        assert!(pool.status()[0].healthy);
+        assert!(first(&pool, spec, 2).contains(&"http://a".to_string()));
+        assert_eq!(pool.down_nodes(), vec!["http://a".to_string()]);
+
+        // успешный ответ сбрасывает серию ошибок
+        pool.mark_ok("http://a");
+        assert!(pool.down_nodes().is_empty());
+        let status = &pool.status()[0];
+        assert_eq!((status.consecutive_failures, status.total_failures), (0, FAILURE_THRESHOLD as u64));
+    }
+
+    #[test]
+    fn sync_keeps_stats_and_follows_settings_order() {
+        let pool = pool(&[("http://a", 1), ("http://b", 1)]);
+        pool.mark_failed("http://b", "boom");
+        pool.candidates("http://c, http://b*4");
+        let status = pool.status();
+        let urls: Vec<&str> = status.iter().map(|n| n.url.as_str()).collect();
+        assert_eq!(urls, ["http://c", "http://b"]);
+        assert_eq!((status[1].weight, status[1].total_failures), (4, 1));
+    }
+}
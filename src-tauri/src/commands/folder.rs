@@ -1,80 +1,335 @@
+use crate::commands::formats::{self, ImportDirs, ImportReport, RejectedFile};
 use crate::commands::ImageInfo;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs;
-use tauri::command;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{command, AppHandle, Emitter, Manager, State};
+
+const IMAGE_EXTENSIONS: [&str; 11] = ["png", "jpg", "jpeg", "webp", "bmp", "gif", "tif", "tiff", "avif", "heic", "heif"];
+
+// Сравнение с учётом чисел: "2.png" < "10.png", "Ch 9" < "Ch 10"; регистр не важен
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take = |it: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = it.peek().copied().filter(char::is_ascii_digit) {
+                        digits.push(c);
+                        it.next();
+                    }
+                    digits
+                };
+                let (da, db) = (take(&mut a), take(&mut b));
+                let (ta, tb) = (da.trim_start_matches('0'), db.trim_start_matches('0'));
+                // сначала по длине без ведущих нулей, потом по цифрам, потом "01" после "1"
+                let ord = ta.len().cmp(&tb.len()).then_with(|| ta.cmp(tb)).then_with(|| da.len().cmp(&db.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn by_name(paths: &mut [PathBuf]) {
+    paths.sort_by(|a, b| {
+        let name = |p: &PathBuf| p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        natural_cmp(&name(a), &name(b))
+    });
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FolderImportOptions {
+    // заходить в подпапки (каждая папка с картинками — отдельная глава)
+    pub recursive: bool,
+    // glob-шаблоны относительно выбранной папки, например "Vol */**" или "**/*.png"; пусто — всё
+    pub include: Vec<String>,
+    // например "**/raw/**", "**/*_thumb.*"
+    pub exclude: Vec<String>,
+}
+
+impl Default for FolderImportOptions {
+    fn default() -> Self {
+        FolderImportOptions { recursive: true, include: Vec::new(), exclude: Vec::new() }
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>, String> {
+    let patterns: Vec<&String> = patterns.iter().filter(|p| !p.trim().is_empty()).collect();
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for p in patterns {
+        let glob = Glob::new(p.trim()).map_err(|e| format!("Bad pattern {:?}: {}", p, e))?;
+        builder.add(glob);
+    }
+    builder.build().map(Some).map_err(|e| e.to_string())
+}
+
+struct Filter {
+    recursive: bool,
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl Filter {
+    fn new(opts: &FolderImportOptions) -> Result<Self, String> {
+        Ok(Filter { recursive: opts.recursive, include: glob_set(&opts.include)?, exclude: glob_set(&opts.exclude)? })
+    }
+}
+
+// Обход в естественном порядке: записи каждой папки сортируются, подпапки обходятся на месте —
+// порядок тот же, что у сортировки полных путей, но дерево не читается целиком заранее.
+// visit получает каждый просмотренный файл (Some — если это страница); скрытое пропускаем,
+// по симлинкам на папки не ходим; stop() прерывает обход
+fn walk(root: &Path, dir: &Path, filter: &Filter, stop: &dyn Fn() -> bool, visit: &mut dyn FnMut(Option<PathBuf>)) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<(String, fs::DirEntry)> =
+        entries.flatten().map(|e| (e.file_name().to_string_lossy().to_string(), e)).collect();
+    entries.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+    for (name, entry) in entries {
+        if stop() {
+            return;
+        }
+        let path = entry.path();
+        let rel = path.strip_prefix(root).unwrap_or(&path);
+        if name.starts_with('.') || filter.exclude.as_ref().is_some_and(|g| g.is_match(rel)) {
+            continue;
+        }
+        let Ok(kind) = entry.file_type() else {
+            continue;
+        };
+        if kind.is_dir() {
+            if filter.recursive {
+                walk(root, &path, filter, stop, visit);
+            }
+            continue;
+        }
+        if !path.is_file() || filter.include.as_ref().is_some_and(|g| !g.is_match(rel)) {
+            visit(None);
+            continue;
+        }
+        let ext_str = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        // по расширению — в отчёт, если не прочитается; без него — только если это картинка по содержимому
+        if IMAGE_EXTENSIONS.contains(&ext_str.as_str()) || matches!(formats::sniff_file(&path), Ok(Some(_))) {
+            visit(Some(path));
+        } else {
+            visit(None);
+        }
+    }
+}
 
 #[command]
-pub async fn import_images() -> Result<Vec<ImageInfo>, String> {
+pub async fn import_images(app: AppHandle) -> Result<ImportReport, String> {
     let dialog = FileDialog::new()
         .set_title("Select images")
-        .add_filter(
-            "Images",
-            &[
-                "png", "jpg", "jpeg", "webp", "bmp", "gif", "tif", "tiff", "avif", "heic", "heif",
-            ],
-        )
+        .add_filter("Images", &IMAGE_EXTENSIONS)
         .pick_files();
 
     let Some(files) = dialog else {
-        return Ok(Vec::new());
+        return Ok(ImportReport::default());
     };
 
-    let mut images = Vec::new();
-    for path in files {
-        if !path.is_file() {
-            continue;
+    let mut paths: Vec<PathBuf> = files.into_iter().filter(|p| p.is_file()).collect();
+    by_name(&mut paths);
+    formats::import(&app, paths, None).await
+}
+
+// ---------- Потоковый импорт ----------
+// Большие папки и сетевые диски: страницы уходят во фронт пачками (import-batch) по мере обхода,
+// прогресс — import-progress; сканирование можно отменить
+
+// Идущие сканирования: id -> флаг отмены
+#[derive(Default)]
+pub struct ImportScans {
+    scans: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+// первая пачка маленькая — список появляется сразу; дальше крупнее
+const FIRST_BATCH: usize = 8;
+const BATCH: usize = 48;
+// прогресс без новых страниц (папки с тысячами посторонних файлов) — не чаще
+const PROGRESS_EVERY: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportBatch {
+    pub scan_id: String,
+    pub images: Vec<ImageInfo>,
+    pub rejected: Vec<RejectedFile>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportProgress {
+    pub scan_id: String,
+    pub folder: String,
+    // просмотрено файлов
+    pub scanned: usize,
+    // из них похожих на страницы
+    pub found: usize,
+    pub imported: usize,
+    pub rejected: usize,
+    pub done: bool,
+    pub cancelled: bool,
+}
+
+fn flush(
+    app: &AppHandle,
+    dirs: &ImportDirs,
+    folder: &Path,
+    cancel: &AtomicBool,
+    pending: &mut Vec<PathBuf>,
+    progress: &mut ImportProgress,
+) {
+    let report = formats::import_blocking(dirs, std::mem::take(pending), Some(folder), Some(cancel));
+    progress.imported += report.images.len();
+    progress.rejected += report.rejected.len();
+    let _ = app.emit(
+        "import-batch",
+        ImportBatch { scan_id: progress.scan_id.clone(), images: report.images, rejected: report.rejected },
+    );
+    let _ = app.emit("import-progress", progress.clone());
+}
+
+fn stream_folder(app: &AppHandle, dirs: &ImportDirs, scan_id: &str, folder: &Path, filter: &Filter, cancel: &AtomicBool) {
+    let mut progress = ImportProgress {
+        scan_id: scan_id.to_string(),
+        folder: folder.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    let mut pending: Vec<PathBuf> = Vec::new();
+    let mut last_emit = Instant::now();
+    let _ = app.emit("import-progress", progress.clone());
+
+    let stop = || cancel.load(AtomicOrdering::Relaxed);
+    walk(folder, folder, filter, &stop, &mut |entry| {
+        progress.scanned += 1;
+        if let Some(path) = entry {
+            progress.found += 1;
+            pending.push(path);
         }
-        let name = path.file_name().unwrap().to_string_lossy().to_string();
-        let path_str = path.to_string_lossy().to_string();
-        images.push(ImageInfo {
-            name,
-            path: path_str,
-            data_url: String::new(),  // лениво грузим позже
-            thumbnail: String::new(), // лениво грузим позже
-        });
+        let limit = if progress.imported + progress.rejected == 0 { FIRST_BATCH } else { BATCH };
+        if pending.len() >= limit {
+            flush(app, dirs, folder, cancel, &mut pending, &mut progress);
+            last_emit = Instant::now();
+        } else if last_emit.elapsed() >= PROGRESS_EVERY {
+            let _ = app.emit("import-progress", progress.clone());
+            last_emit = Instant::now();
+        }
+    });
+
+    progress.cancelled = stop();
+    // после отмены недоделанную пачку не импортируем
+    if !progress.cancelled && !pending.is_empty() {
+        flush(app, dirs, folder, cancel, &mut pending, &mut progress);
     }
-    images.sort_by(|a, b| a.name.cmp(&b.name));
-    Ok(images)
+    progress.done = true;
+    let _ = app.emit("import-progress", progress);
 }
 
+// Выбор папки и запуск фонового сканирования; scan_id задаёт фронт, чтобы подписаться на события
+// заранее. false — папку не выбрали
 #[command]
-pub async fn import_folder() -> Result<Vec<ImageInfo>, String> {
+pub async fn start_folder_import(
+    app: AppHandle,
+    scans: State<'_, ImportScans>,
+    scan_id: String,
+    options: Option<FolderImportOptions>,
+) -> Result<bool, String> {
+    let filter = Filter::new(&options.unwrap_or_default())?;
+
     let dialog = FileDialog::new()
         .set_title("Select folder with images")
         .pick_folder();
 
     let Some(folder) = dialog else {
-        return Ok(Vec::new());
+        return Ok(false);
     };
 
-    let mut images = Vec::new();
-    let image_extensions = [
-        "png", "jpg", "jpeg", "webp", "bmp", "gif", "tif", "tiff", "avif", "heic", "heif",
-    ];
-
-    if let Ok(entries) = fs::read_dir(&folder) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(extension) = path.extension() {
-                        let ext_str = extension.to_string_lossy().to_lowercase();
-                        if image_extensions.contains(&ext_str.as_str()) {
-                            let name = path.file_name().unwrap().to_string_lossy().to_string();
-                            let path_str = path.to_string_lossy().to_string();
-                            images.push(ImageInfo {
-                                name,
-                                path: path_str,
-                                data_url: String::new(),  // lazy load later
-                                thumbnail: String::new(), // lazy load later
-                            });
-                        }
-                    }
-                }
-            }
-        }
+    let cancel = Arc::new(AtomicBool::new(false));
+    scans.scans.lock().unwrap().insert(scan_id.clone(), cancel.clone());
+    let dirs = ImportDirs::new(&app);
+    tauri::async_runtime::spawn_blocking(move || {
+        stream_folder(&app, &dirs, &scan_id, &folder, &filter, &cancel);
+        app.state::<ImportScans>().scans.lock().unwrap().remove(&scan_id);
+    });
+    Ok(true)
+}
+
+#[command]
+pub fn cancel_folder_import(scans: State<'_, ImportScans>, scan_id: String) {
+    if let Some(cancel) = scans.scans.lock().unwrap().get(&scan_id) {
+        cancel.store(true, AtomicOrdering::Relaxed);
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    images.sort_by(|a, b| a.name.cmp(&b.name));
-    Ok(images)
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut v: Vec<String> = names.iter().map(|s| s.to_string()).collect();
+        v.sort_by(|a, b| natural_cmp(a, b));
+        v
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(sorted(&["10.png", "2.png", "1.png"]), ["1.png", "2.png", "10.png"]);
+        assert_eq!(sorted(&["Ch 10", "Ch 9", "Ch 100"]), ["Ch 9", "Ch 10", "Ch 100"]);
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(natural_cmp("007.png", "7.png"), Ordering::Greater);
+        assert_eq!(natural_cmp("007.png", "8.png"), Ordering::Less);
+        assert_eq!(natural_cmp("010.png", "9.png"), Ordering::Greater);
+        assert_eq!(sorted(&["02", "1", "001", "2"]), ["1", "001", "2", "02"]);
+        assert_eq!(natural_cmp("0", "00"), Ordering::Less);
+    }
+
+    #[test]
+    fn case_is_folded() {
+        assert_eq!(natural_cmp("Page.png", "page.png"), Ordering::Equal);
+        assert_eq!(natural_cmp("a.png", "B.png"), Ordering::Less);
+        assert_eq!(sorted(&["b", "A", "C"]), ["A", "b", "C"]);
+        assert_eq!(natural_cmp("Глава 2", "глава 10"), Ordering::Less);
+    }
+
+    #[test]
+    fn mixed_digit_runs() {
+        assert_eq!(
+            sorted(&["Vol 2 Ch 1", "Vol 1 Ch 10", "Vol 1 Ch 2", "Vol 10 Ch 1"]),
+            ["Vol 1 Ch 2", "Vol 1 Ch 10", "Vol 2 Ch 1", "Vol 10 Ch 1"]
+        );
+        assert_eq!(sorted(&["p1-10", "p1-9", "p1-10a", "p1"]), ["p1", "p1-9", "p1-10", "p1-10a"]);
+        // цифры против букв — по коду символа
+        assert_eq!(natural_cmp("1a", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("12345678901234567890", "9"), Ordering::Greater);
+    }
 }
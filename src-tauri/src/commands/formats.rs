@@ -0,0 +1,343 @@
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{AppHandle, Manager};
+
+use crate::commands::normalize::{self, Normalization};
+use crate::commands::{thumbs, ImageInfo};
+
+// Формат по содержимому файла, а не по расширению
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Bmp,
+    Tiff,
+    Avif,
+    Heic,
+}
+
+impl Format {
+    // webview показывает сам и image 0.24 читает — файл берём как есть
+    pub fn webview_safe(self) -> bool {
+        matches!(self, Format::Png | Format::Jpeg | Format::Gif | Format::Webp | Format::Bmp)
+    }
+
+    // читает ли image 0.24 (остальное — через системные утилиты, они сами учитывают поворот)
+    pub fn decodable(self) -> bool {
+        self.webview_safe() || self == Format::Tiff
+    }
+}
+
+// бренды ISO BMFF (ftyp), по которым узнаём AVIF и HEIC/HEIF
+const AVIF_BRANDS: [&[u8; 4]; 2] = [b"avif", b"avis"];
+const HEIC_BRANDS: [&[u8; 4]; 8] = [b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1"];
+
+pub fn sniff(head: &[u8]) -> Option<Format> {
+    if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(Format::Png);
+    }
+    if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(Format::Jpeg);
+    }
+    if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        return Some(Format::Gif);
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Some(Format::Webp);
+    }
+    if head.starts_with(b"BM") && head.len() >= 14 {
+        return Some(Format::Bmp);
+    }
+    if head.starts_with(b"II*\0") || head.starts_with(b"MM\0*") {
+        return Some(Format::Tiff);
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        // major brand + совместимые бренды в пределах бокса ftyp; avif проверяем первым — mif1 есть у обоих
+        let size = u32::from_be_bytes([head[0], head[1], head[2], head[3]]) as usize;
+        let end = size.clamp(12, head.len());
+        let brands: Vec<&[u8]> = std::iter::once(&head[8..12]).chain(head[16.min(end)..end].chunks_exact(4)).collect();
+        if brands.iter().any(|b| AVIF_BRANDS.iter().any(|a| a.as_slice() == *b)) {
+            return Some(Format::Avif);
+        }
+        if brands.iter().any(|b| HEIC_BRANDS.iter().any(|h| h.as_slice() == *b)) {
+            return Some(Format::Heic);
+        }
+    }
+    None
+}
+
+pub fn sniff_file(path: &Path) -> Result<Option<Format>, String> {
+    let mut head = Vec::with_capacity(64);
+    std::fs::File::open(path)
+        .and_then(|f| f.take(64).read_to_end(&mut head))
+        .map_err(|e| e.to_string())?;
+    Ok(sniff(&head))
+}
+
+// Сконвертированные страницы: <app data>/converted; оригиналы не трогаем
+pub fn workspace_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_data_dir().ok().map(|d| d.join("converted"))
+}
+
+// Файл, с которым дальше работает приложение
+pub struct Normalized {
+    pub path: PathBuf,
+    // исходный файл и что с ним сделано, если страница пересохранена
+    pub original: Option<PathBuf>,
+    pub normalization: Option<Normalization>,
+}
+
+// Системные декодеры для того, что не читает image 0.24 (HEIC, AVIF, экзотические TIFF)
+const TOOLS: [(&str, &[&str]); 3] = [
+    ("magick", &[]),
+    ("heif-convert", &[]),
+    ("ffmpeg", &["-y", "-loglevel", "error", "-i"]),
+];
+
+fn convert_external(src: &Path, dst: &Path) -> Result<(), String> {
+    for (tool, args) in TOOLS {
+        let ok = Command::new(tool)
+            .args(args)
+            .arg(src)
+            .arg(dst)
+            .output()
+            .map(|out| out.status.success())
+            .unwrap_or(false);
+        if ok && dst.is_file() {
+            return Ok(());
+        }
+    }
+    Err("no decoder for this format (install ImageMagick, libheif or ffmpeg)".to_string())
+}
+
+// В PNG: сначала своими декодерами (с поворотом и переводом в sRGB), потом системными;
+// пишем во временный файл, чтобы не оставить битый
+fn convert(src: &Path, dst: &Path, orientation: u16, icc: Option<&[u8]>) -> Result<(), String> {
+    let tmp = dst.with_extension("part.png");
+    let decoded = image::io::Reader::open(src)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .decode();
+    match decoded {
+        Ok(img) => {
+            let img = match icc {
+                Some(icc) => normalize::to_srgb(&img, icc)?,
+                None => img,
+            };
+            normalize::orient(img, orientation)
+                .save_with_format(&tmp, ImageFormat::Png)
+                .map_err(|e| e.to_string())?
+        }
+        Err(e) => convert_external(src, &tmp).map_err(|ext| format!("{}; {}", e, ext))?,
+    }
+    std::fs::rename(&tmp, dst).map_err(|e| e.to_string())
+}
+
+pub fn normalize(workspace: Option<&Path>, path: &Path) -> Result<Normalized, String> {
+    let format = sniff_file(path)?.ok_or("not a supported image")?;
+    let mut fix = Normalization { converted_from: (!format.webview_safe()).then_some(format), ..Default::default() };
+    let mut icc = None;
+    if format.decodable() {
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        fix.orientation = normalize::exif_orientation(&bytes);
+        icc = normalize::icc_profile(&bytes, format).filter(|p| normalize::needs_srgb(p));
+        fix.icc_profile = icc.as_deref().map(|p| normalize::profile_name(p).unwrap_or_else(|| "unnamed".to_string()));
+    }
+    if fix.is_noop() {
+        // битый заголовок отсеиваем сразу, а не посреди пакетной обработки
+        image::io::Reader::open(path)
+            .map_err(|e| e.to_string())?
+            .with_guessed_format()
+            .map_err(|e| e.to_string())?
+            .into_dimensions()
+            .map_err(|e| e.to_string())?;
+        return Ok(Normalized { path: path.to_path_buf(), original: None, normalization: None });
+    }
+    let dir = workspace.ok_or("no workspace directory for converted images")?;
+    let key = thumbs::file_key(path).ok_or("cannot read file metadata")?;
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    // ключ от пути + mtime + размера: повторный импорт берёт готовую копию
+    let target = dir.join(format!("{}-{}.png", stem, &key[..12]));
+    if !target.is_file() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        convert(path, &target, fix.orientation, icc.as_deref())
+            .map_err(|e| format!("{:?} normalization failed: {}", format, e))?;
+    }
+    Ok(Normalized { path: target, original: Some(path.to_path_buf()), normalization: Some(fix) })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub images: Vec<ImageInfo>,
+    // сколько страниц пересохранено в PNG (конвертация, поворот, sRGB)
+    pub converted: usize,
+    pub rejected: Vec<RejectedFile>,
+}
+
+// Папка страницы относительно корня импорта; файлы в самом корне — без главы
+fn chapter_of(root: &Path, path: &Path) -> Option<String> {
+    let rel = path.parent()?.strip_prefix(root).ok()?;
+    let parts: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+// Каталоги импорта: сконвертированные страницы и кэш превью
+#[derive(Clone)]
+pub struct ImportDirs {
+    pub workspace: Option<PathBuf>,
+    pub thumbs: Option<PathBuf>,
+}
+
+impl ImportDirs {
+    pub fn new(app: &AppHandle) -> Self {
+        ImportDirs { workspace: workspace_dir(app), thumbs: thumbs::cache_dir(app) }
+    }
+}
+
+// Импорт: определение формата, конвертация, превью; нечитаемые файлы — в отчёт.
+// Порядок paths сохраняется — это и есть порядок страниц; с root у страниц подпапок проставляется глава
+pub async fn import(app: &AppHandle, paths: Vec<PathBuf>, root: Option<PathBuf>) -> Result<ImportReport, String> {
+    let dirs = ImportDirs::new(app);
+    tauri::async_runtime::spawn_blocking(move || import_blocking(&dirs, paths, root.as_deref(), None))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// То же синхронно — для потокового импорта пачками (commands/folder.rs).
+// cancel проверяется перед каждым файлом: после отмены оставшиеся файлы пачки пропускаются
+pub fn import_blocking(
+    dirs: &ImportDirs,
+    paths: Vec<PathBuf>,
+    root: Option<&Path>,
+    cancel: Option<&AtomicBool>,
+) -> ImportReport {
+    let (workspace, thumbs_dir) = (&dirs.workspace, &dirs.thumbs);
+    let stop = || cancel.is_some_and(|c| c.load(Ordering::Relaxed));
+    let mut report = ImportReport::default();
+    let normalized = thumbs::parallel_map(&paths, |p| (!stop()).then(|| normalize(workspace.as_deref(), p)));
+    let mut ready = Vec::new();
+    // паника декодера — отказ только этого файла (thumbs::parallel_map)
+    for (src, n) in paths.iter().zip(normalized) {
+        match n.and_then(Option::transpose) {
+            Ok(Some(n)) => ready.push(n),
+            Err(reason) => report.rejected.push(RejectedFile { path: src.to_string_lossy().to_string(), reason }),
+            Ok(None) => {}
+        }
+    }
+
+    let thumbs = thumbs::parallel_map(&ready, |n| (!stop()).then(|| thumbs::thumb_cached(thumbs_dir.as_deref(), &n.path)));
+    for (n, thumb) in ready.into_iter().zip(thumbs) {
+        let source = n.original.as_deref().unwrap_or(&n.path);
+        let thumb = match thumb.and_then(Option::transpose) {
+            Ok(Some(thumb)) => thumb,
+            Ok(None) => continue,
+            Err(reason) => {
+                report.rejected.push(RejectedFile { path: source.to_string_lossy().to_string(), reason });
+                continue;
+            }
+        };
+        if n.original.is_some() {
+            report.converted += 1;
+        }
+        report.images.push(ImageInfo {
+            // имя исходного файла — по нему сортируется список страниц
+            name: source.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default(),
+            path: n.path.to_string_lossy().to_string(),
+            chapter: root.and_then(|r| chapter_of(r, source)),
+            original_path: n.original.map(|p| p.to_string_lossy().to_string()),
+            normalization: n.normalization,
+            data_url: String::new(), // полная страница грузится при открытии
+            thumbnail: thumb.thumbnail,
+            width: Some(thumb.width),
+            height: Some(thumb.height),
+        });
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // бокс ftyp: размер, "ftyp", major brand, версия, совместимые бренды
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + 4 * compatible.len() as u32;
+        let mut b = size.to_be_bytes().to_vec();
+        b.extend_from_slice(b"ftyp");
+        b.extend_from_slice(major);
+        b.extend_from_slice(&[0, 0, 0, 0]);
+        for c in compatible {
+            b.extend_from_slice(*c);
+        }
+        // дальше идёт следующий бокс
+        b.extend_from_slice(b"\0\0\0\x08meta");
+        b
+    }
+
+    #[test]
+    fn sniffs_raster_formats() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(Format::Png));
+        assert_eq!(sniff(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10, b'J', b'F', b'I', b'F']), Some(Format::Jpeg));
+        assert_eq!(sniff(b"GIF87a\x01\0\x01\0"), Some(Format::Gif));
+        assert_eq!(sniff(b"GIF89a\x01\0\x01\0"), Some(Format::Gif));
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WEBPVP8 "), Some(Format::Webp));
+        assert_eq!(sniff(b"BM\x36\0\0\0\0\0\0\0\x36\0\0\0"), Some(Format::Bmp));
+        assert_eq!(sniff(b"II*\0\x08\0\0\0"), Some(Format::Tiff));
+        assert_eq!(sniff(b"MM\0*\0\0\0\x08"), Some(Format::Tiff));
+    }
+
+    #[test]
+    fn rejects_lookalikes() {
+        assert_eq!(sniff(b""), None);
+        // RIFF, но не WebP
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        // "BM" в начале короткого текстового файла
+        assert_eq!(sniff(b"BMP"), None);
+        assert_eq!(sniff(b"GIF90a"), None);
+        assert_eq!(sniff(&ftyp(b"isom", &[b"isom", b"mp41"])), None);
+    }
+
+    #[test]
+    fn sniffs_avif_and_heic_by_brand() {
+        assert_eq!(sniff(&ftyp(b"avif", &[b"mif1", b"miaf"])), Some(Format::Avif));
+        assert_eq!(sniff(&ftyp(b"avis", &[b"msf1"])), Some(Format::Avif));
+        assert_eq!(sniff(&ftyp(b"heic", &[b"mif1", b"heic"])), Some(Format::Heic));
+        assert_eq!(sniff(&ftyp(b"heix", &[])), Some(Format::Heic));
+        // HEIF без HEVC-бренда — только mif1
+        assert_eq!(sniff(&ftyp(b"mif1", &[b"heic"])), Some(Format::Heic));
+    }
+
+    #[test]
+    fn avif_wins_over_shared_mif1() {
+        // mif1 major brand есть и у AVIF, и у HEIF — решает совместимый avif
+        assert_eq!(sniff(&ftyp(b"mif1", &[b"avif", b"miaf"])), Some(Format::Avif));
+        assert_eq!(sniff(&ftyp(b"mif1", &[b"miaf", b"avif"])), Some(Format::Avif));
+        assert_eq!(sniff(&ftyp(b"msf1", &[b"avis"])), Some(Format::Avif));
+        assert_eq!(sniff(&ftyp(b"mif1", &[b"miaf"])), Some(Format::Heic));
+    }
+
+    #[test]
+    fn brands_outside_ftyp_box_are_ignored() {
+        // "avif" в следующем боксе, а не среди брендов ftyp
+        let mut head = ftyp(b"mif1", &[b"heic"]);
+        head.extend_from_slice(b"avif");
+        assert_eq!(sniff(&head), Some(Format::Heic));
+    }
+}
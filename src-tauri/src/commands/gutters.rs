@@ -0,0 +1,360 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+use serde::{Deserialize, Serialize};
+use tauri::command;
+
+// Панели без бэкенда: рекурсивные разрезы по белым/чёрным гуттерам (проекции строк и столбцов)
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GutterOptions {
+    // страница уменьшается до этой меньшей стороны (0 — как есть)
+    pub work_side: u32,
+    // пиксель гуттера отличается от цвета фона не больше чем на tolerance
+    pub tolerance: u8,
+    // строка/столбец — гуттер, если такая доля пикселей в нём — фон
+    pub line_fill: f64,
+    // минимальная ширина гуттера, доля меньшей стороны страницы
+    pub min_gutter: f64,
+    // панель меньше такой доли меньшей стороны — мусор (номер страницы, сноска)
+    pub min_panel: f64,
+    // в панели должно быть хоть столько "не фона"
+    pub min_content: f64,
+}
+
+impl Default for GutterOptions {
+    fn default() -> Self {
+        GutterOptions {
+            work_side: 1000,
+            tolerance: 24,
+            line_fill: 0.98,
+            min_gutter: 0.006,
+            min_panel: 0.06,
+            min_content: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    x1: u32,
+    y1: u32,
+    x2: u32,
+    y2: u32,
+}
+
+struct Page<'a> {
+    gray: &'a GrayImage,
+    background: u8,
+    opts: &'a GutterOptions,
+    min_gutter: u32,
+}
+
+impl Page<'_> {
+    fn is_bg(&self, x: u32, y: u32) -> bool {
+        self.gray.get_pixel(x, y).0[0].abs_diff(self.background) <= self.opts.tolerance
+    }
+
+    // Для каждой строки (horizontal) или столбца региона: гуттер ли это
+    fn profile(&self, r: Region, horizontal: bool) -> Vec<bool> {
+        let (outer, inner) = if horizontal { (r.y1..r.y2, r.x1..r.x2) } else { (r.x1..r.x2, r.y1..r.y2) };
+        let len = inner.len().max(1) as f64;
+        outer
+            .map(|o| {
+                let bg = inner
+                    .clone()
+                    .filter(|&i| if horizontal { self.is_bg(i, o) } else { self.is_bg(o, i) })
+                    .count();
+                bg as f64 / len >= self.opts.line_fill
+            })
+            .collect()
+    }
+
+    // Срезает поля по краям; None — регион целиком фон
+    fn trim(&self, r: Region) -> Option<Region> {
+        let rows = self.profile(r, true);
+        let cols = self.profile(r, false);
+        let top = rows.iter().position(|g| !g)? as u32;
+        let bottom = rows.iter().rposition(|g| !g)? as u32;
+        let left = cols.iter().position(|g| !g)? as u32;
+        let right = cols.iter().rposition(|g| !g)? as u32;
+        Some(Region { x1: r.x1 + left, y1: r.y1 + top, x2: r.x1 + right + 1, y2: r.y1 + bottom + 1 })
+    }
+
+    // Разрезы по внутренним гуттерам (регион уже обрезан, края — не фон)
+    fn split(&self, r: Region, horizontal: bool) -> Vec<Region> {
+        let profile = self.profile(r, horizontal);
+        let origin = if horizontal { r.y1 } else { r.x1 };
+        let mut parts = Vec::new();
+        let (mut start, mut run) = (0u32, 0u32);
+        for (i, &g) in profile.iter().enumerate() {
+            let i = i as u32;
+            if g {
+                run += 1;
+                continue;
+            }
+            if run >= self.min_gutter && i > run {
+                parts.push((start, i - run));
+                start = i;
+            }
+            run = 0;
+        }
+        parts.push((start, profile.len() as u32));
+        parts
+            .into_iter()
+            .map(|(a, b)| {
+                if horizontal {
+                    Region { y1: origin + a, y2: origin + b, ..r }
+                } else {
+                    Region { x1: origin + a, x2: origin + b, ..r }
+                }
+            })
+            .collect()
+    }
+
+    fn content(&self, r: Region) -> f64 {
+        let total = ((r.x2 - r.x1) as u64 * (r.y2 - r.y1) as u64).max(1);
+        let fg = (r.y1..r.y2)
+            .flat_map(|y| (r.x1..r.x2).map(move |x| (x, y)))
+            .filter(|&(x, y)| !self.is_bg(x, y))
+            .count();
+        fg as f64 / total as f64
+    }
+
+    // XY-cut: сначала строки, потом колонки; порядок здесь не важен — его задаёт reading_order
+    fn cut(&self, r: Region, out: &mut Vec<Region>) {
+        let Some(r) = self.trim(r) else {
+            return;
+        };
+        for horizontal in [true, false] {
+            let parts = self.split(r, horizontal);
+            if parts.len() > 1 {
+                for p in parts {
+                    self.cut(p, out);
+                }
+                return;
+            }
+        }
+        out.push(r);
+    }
+}
+
+// Цвет фона — медиана рамки страницы (белые поля у манги, чёрные у некоторых глав)
+fn background(gray: &GrayImage) -> u8 {
+    let (w, h) = gray.dimensions();
+    let mut border: Vec<u8> = (0..w)
+        .flat_map(|x| [gray.get_pixel(x, 0).0[0], gray.get_pixel(x, h - 1).0[0]])
+        .chain((0..h).flat_map(|y| [gray.get_pixel(0, y).0[0], gray.get_pixel(w - 1, y).0[0]]))
+        .collect();
+    border.sort_unstable();
+    border[border.len() / 2]
+}
+
+// Панели [x1, y1, x2, y2] в координатах переданной картинки
+pub fn detect(img: &DynamicImage, opts: &GutterOptions) -> Vec<[f64; 4]> {
+    let (ow, oh) = (img.width(), img.height());
+    if ow == 0 || oh == 0 {
+        return Vec::new();
+    }
+    let work = if opts.work_side > 0 && ow.min(oh) > opts.work_side {
+        let k = opts.work_side as f64 / ow.min(oh) as f64;
+        img.resize_exact(((ow as f64 * k) as u32).max(1), ((oh as f64 * k) as u32).max(1), FilterType::Triangle)
+    } else {
+        img.clone()
+    };
+    let gray = work.to_luma8();
+    let (w, h) = gray.dimensions();
+    let short = w.min(h) as f64;
+    let page = Page {
+        gray: &gray,
+        background: background(&gray),
+        opts,
+        min_gutter: ((short * opts.min_gutter).round() as u32).max(2),
+    };
+
+    let mut regions = Vec::new();
+    page.cut(Region { x1: 0, y1: 0, x2: w, y2: h }, &mut regions);
+
+    let min_side = short * opts.min_panel;
+    let (kx, ky) = (ow as f64 / w as f64, oh as f64 / h as f64);
+    regions
+        .into_iter()
+        .filter(|r| ((r.x2 - r.x1) as f64) >= min_side && ((r.y2 - r.y1) as f64) >= min_side)
+        .filter(|r| page.content(*r) >= opts.min_content)
+        .map(|r| [r.x1 as f64 * kx, r.y1 as f64 * ky, (r.x2 as f64 * kx).min(ow as f64), (r.y2 as f64 * ky).min(oh as f64)])
+        .collect()
+}
+
+pub fn detect_b64(image_b64: &str, opts: &GutterOptions) -> Result<Vec<[f64; 4]>, String> {
+    let b64 = image_b64.split_once(',').map(|(_, b)| b).unwrap_or(image_b64);
+    let bytes = STANDARD.decode(b64).map_err(|e| e.to_string())?;
+    let img = image::load_from_memory(&bytes).map_err(|e| e.to_string())?;
+    Ok(detect(&img, opts))
+}
+
+pub async fn detect_async(image_b64: String, opts: GutterOptions) -> Result<Vec<[f64; 4]>, String> {
+    tauri::async_runtime::spawn_blocking(move || detect_b64(&image_b64, &opts))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Сверка панелей модели с гуттерами: что совпало, что модель пропустила или придумала
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PanelCheck {
+    pub matched: usize,
+    // нашлись по гуттерам, у модели нет
+    pub missing: Vec<[f64; 4]>,
+    // есть у модели, по гуттерам не подтвердились
+    pub unconfirmed: Vec<[f64; 4]>,
+    // matched / max(модель, гуттеры); 1.0 — полное согласие
+    pub agreement: f64,
+}
+
+fn iou(a: &[f64; 4], b: &[f64; 4]) -> f64 {
+    let w = a[2].min(b[2]) - a[0].max(b[0]);
+    let h = a[3].min(b[3]) - a[1].max(b[1]);
+    if w <= 0.0 || h <= 0.0 {
+        return 0.0;
+    }
+    let inter = w * h;
+    let area = |p: &[f64; 4]| (p[2] - p[0]) * (p[3] - p[1]);
+    inter / (area(a) + area(b) - inter)
+}
+
+pub fn check(model: &[[f64; 4]], local: &[[f64; 4]], iou_threshold: f64) -> PanelCheck {
+    let mut used = vec![false; model.len()];
+    let mut result = PanelCheck::default();
+    for l in local {
+        let best = model
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !used[*i])
+            .map(|(i, m)| (i, iou(m, l)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match best {
+            Some((i, v)) if v >= iou_threshold => {
+                used[i] = true;
+                result.matched += 1;
+            }
+            _ => result.missing.push(*l),
+        }
+    }
+    result.unconfirmed = model.iter().zip(&used).filter(|(_, u)| !**u).map(|(m, _)| *m).collect();
+    let total = model.len().max(local.len());
+    result.agreement = if total == 0 { 1.0 } else { result.matched as f64 / total as f64 };
+    result
+}
+
+// Тот же ответ, что у /detect_panels
+#[command]
+pub async fn detect_panels_offline(image_data: String, options: Option<GutterOptions>) -> Result<serde_json::Value, String> {
+    let panels = detect_async(image_data, options.unwrap_or_default()).await?;
+    Ok(serde_json::json!({ "panels": panels }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    // Синтетическая страница: фон-гуттер, панели с рамкой и "рисунком" внутри
+    fn page(w: u32, h: u32, bg: u8, panels: &[[u32; 4]]) -> DynamicImage {
+        let fg = 255 - bg;
+        let img = GrayImage::from_fn(w, h, |x, y| {
+            for p in panels {
+                if x >= p[0] && x < p[2] && y >= p[1] && y < p[3] {
+                    let frame = x < p[0] + 3 || x >= p[2] - 3 || y < p[1] + 3 || y >= p[3] - 3;
+                    // рисунок: штриховка, местами касается рамки
+                    let art = (x * 7 + y * 3) % 23 < 6;
+                    return Luma([if frame || art { fg } else { bg }]);
+                }
+            }
+            Luma([bg])
+        });
+        DynamicImage::ImageLuma8(img)
+    }
+
+    fn assert_panels(found: &[[f64; 4]], expected: &[[u32; 4]], tol: f64) {
+        assert_eq!(found.len(), expected.len(), "found {:?}", found);
+        for e in expected {
+            let e = [e[0] as f64, e[1] as f64, e[2] as f64, e[3] as f64];
+            assert!(
+                found.iter().any(|f| f.iter().zip(&e).all(|(a, b)| (a - b).abs() <= tol)),
+                "no panel near {:?} in {:?}",
+                e,
+                found
+            );
+        }
+    }
+
+    fn run(img: &DynamicImage) -> Vec<[f64; 4]> {
+        detect(img, &GutterOptions::default())
+    }
+
+    #[test]
+    fn grid_with_white_gutters() {
+        let panels = [[40, 40, 380, 500], [420, 40, 760, 500], [40, 540, 380, 1060], [420, 540, 760, 1060]];
+        assert_panels(&run(&page(800, 1100, 255, &panels)), &panels, 2.0);
+    }
+
+    #[test]
+    fn grid_with_black_gutters() {
+        let panels = [[30, 30, 770, 400], [30, 430, 390, 1070], [410, 430, 770, 1070]];
+        assert_panels(&run(&page(800, 1100, 0, &panels)), &panels, 2.0);
+    }
+
+    #[test]
+    fn tall_panel_beside_stacked_panels() {
+        // высокий кадр справа и два слева: горизонтального гуттера через всю страницу нет
+        let panels = [[40, 40, 380, 520], [40, 560, 380, 1060], [420, 40, 760, 1060]];
+        assert_panels(&run(&page(800, 1100, 255, &panels)), &panels, 2.0);
+    }
+
+    #[test]
+    fn nested_rows_and_columns() {
+        // ряд из трёх кадров, под ним широкий, под ним два
+        let panels = [
+            [30, 30, 270, 350],
+            [290, 30, 510, 350],
+            [530, 30, 770, 350],
+            [30, 370, 770, 700],
+            [30, 720, 400, 1070],
+            [420, 720, 770, 1070],
+        ];
+        assert_panels(&run(&page(800, 1100, 255, &panels)), &panels, 2.0);
+    }
+
+    #[test]
+    fn borderless_page_is_one_panel() {
+        // рисунок в обрез, без рамок и гуттеров — одна панель во всю страницу
+        let img = GrayImage::from_fn(800, 1100, |x, y| Luma([if (x * 7 + y * 3) % 23 < 6 { 0 } else { 255 }]));
+        assert_panels(&run(&DynamicImage::ImageLuma8(img)), &[[0, 0, 800, 1100]], 2.0);
+    }
+
+    #[test]
+    fn page_number_is_not_a_panel() {
+        let mut panels = vec![[40, 40, 760, 1000]];
+        // номер страницы внизу
+        panels.push([390, 1040, 410, 1060]);
+        assert_panels(&run(&page(800, 1100, 255, &panels)), &panels[..1], 2.0);
+    }
+
+    #[test]
+    fn large_pages_map_back_to_original_coordinates() {
+        let panels = [[80, 80, 1560, 1500], [80, 1580, 1560, 3120]];
+        let opts = GutterOptions { work_side: 600, ..Default::default() };
+        assert_panels(&detect(&page(1640, 3200, 255, &panels), &opts), &panels, 6.0);
+    }
+
+    #[test]
+    fn check_reports_missing_and_unconfirmed_panels() {
+        let local = [[0.0, 0.0, 100.0, 100.0], [0.0, 120.0, 100.0, 220.0]];
+        let model = [[2.0, 1.0, 99.0, 101.0], [300.0, 300.0, 400.0, 400.0]];
+        let c = check(&model, &local, 0.5);
+        assert_eq!(c.matched, 1);
+        assert_eq!(c.missing, vec![[0.0, 120.0, 100.0, 220.0]]);
+        assert_eq!(c.unconfirmed, vec![[300.0, 300.0, 400.0, 400.0]]);
+        assert!((c.agreement - 0.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,363 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Детектор баблов без бэкенда: классическое CV на крейте image.
+// Бабл — замкнутая светлая область с тёмными "буквами" внутри.
+
+// Имя "модели" детекции: с ним detect_text_areas отрабатывает локально
+pub const MODEL: &str = "offline_heuristic";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HeuristicOptions {
+    // страница уменьшается до этой меньшей стороны (0 — как есть)
+    pub work_side: u32,
+    // светлее — "белое" (фон бабла)
+    pub white: u8,
+    // темнее — "чернила" (буквы)
+    pub dark: u8,
+    // площадь области, доля страницы
+    pub min_area: f64,
+    pub max_area: f64,
+    // длинная сторона / короткая
+    pub max_aspect: f64,
+    // область / её прямоугольник: эллипс ~0.79, прямоугольник ~1
+    pub min_fill: f64,
+    // доля чернил внутри относительно площади бабла
+    pub min_ink: f64,
+    pub max_ink: f64,
+    // буквы должны идти хотя бы в стольких строках (px рабочего масштаба)
+    pub min_ink_rows: usize,
+    pub min_score: f64,
+}
+
+impl Default for HeuristicOptions {
+    fn default() -> Self {
+        HeuristicOptions {
+            work_side: 1200,
+            white: 200,
+            dark: 110,
+            min_area: 0.0005,
+            max_area: 0.2,
+            max_aspect: 6.0,
+            min_fill: 0.45,
+            min_ink: 0.01,
+            max_ink: 0.45,
+            min_ink_rows: 3,
+            min_score: 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub x1: u32,
+    pub y1: u32,
+    pub x2: u32,
+    pub y2: u32,
+    pub score: f64,
+}
+
+struct Component {
+    id: u32,
+    x1: u32,
+    y1: u32,
+    x2: u32,
+    y2: u32,
+    area: u64,
+    touches_border: bool,
+}
+
+// Связные светлые области (4-связность). labels[i] — номер области, 0 — не белое.
+fn components(gray: &GrayImage, white: u8) -> (Vec<u32>, Vec<Component>) {
+    let (w, h) = gray.dimensions();
+    let mut labels = vec![0u32; (w * h) as usize];
+    let mut comps = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..(w * h) {
+        if labels[start as usize] != 0 || gray.as_raw()[start as usize] < white {
+            continue;
+        }
+        let id = comps.len() as u32 + 1;
+        let mut c = Component {
+            id,
+            x1: u32::MAX,
+            y1: u32::MAX,
+            x2: 0,
+            y2: 0,
+            area: 0,
+            touches_border: false,
+        };
+        labels[start as usize] = id;
+        stack.push(start);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % w, i / w);
+            c.x1 = c.x1.min(x);
+            c.y1 = c.y1.min(y);
+            c.x2 = c.x2.max(x);
+            c.y2 = c.y2.max(y);
+            c.area += 1;
+            if x == 0 || y == 0 || x == w - 1 || y == h - 1 {
+                c.touches_border = true;
+            }
+            let mut visit = |j: u32| {
+                if labels[j as usize] == 0 && gray.as_raw()[j as usize] >= white {
+                    labels[j as usize] = id;
+                    stack.push(j);
+                }
+            };
+            if x > 0 {
+                visit(i - 1);
+            }
+            if x + 1 < w {
+                visit(i + 1);
+            }
+            if y > 0 {
+                visit(i - w);
+            }
+            if y + 1 < h {
+                visit(i + w);
+            }
+        }
+        comps.push(c);
+    }
+    (labels, comps)
+}
+
+// Чернила внутри области: тёмные пиксели между крайними точками области в каждой строке
+fn ink(gray: &GrayImage, labels: &[u32], c: &Component, dark: u8) -> (u64, usize) {
+    let w = gray.width();
+    let (mut total, mut rows) = (0u64, 0usize);
+    for y in c.y1..=c.y2 {
+        let row = (y * w) as usize;
+        let mut span = (c.x1..=c.x2).filter(|&x| labels[row + x as usize] == c.id);
+        let (Some(left), Some(right)) = (span.clone().next(), span.next_back()) else {
+            continue;
+        };
+        let n = (left..=right)
+            .filter(|&x| labels[row + x as usize] != c.id && gray.as_raw()[row + x as usize] < dark)
+            .count() as u64;
+        if n > 0 {
+            total += n;
+            rows += 1;
+        }
+    }
+    (total, rows)
+}
+
+// Кандидаты в координатах переданной картинки
+pub fn detect(img: &DynamicImage, opts: &HeuristicOptions) -> Vec<Candidate> {
+    let (ow, oh) = (img.width(), img.height());
+    // ограничиваем меньшую сторону: длинная лента вебтуна не должна сжиматься в нитку
+    let work = if opts.work_side > 0 && ow.min(oh) > opts.work_side {
+        let k = opts.work_side as f64 / ow.min(oh) as f64;
+        let (w, h) = (((ow as f64 * k) as u32).max(1), ((oh as f64 * k) as u32).max(1));
+        img.resize_exact(w, h, FilterType::Triangle)
+    } else {
+        img.clone()
+    };
+    let gray = work.to_luma8();
+    let (w, h) = gray.dimensions();
+    let k = ow as f64 / w as f64;
+    let page = (w as f64 * h as f64).max(1.0);
+
+    let (labels, comps) = components(&gray, opts.white);
+    let mut out = Vec::new();
+    for c in &comps {
+        if c.touches_border {
+            continue;
+        }
+        let (bw, bh) = ((c.x2 - c.x1 + 1) as f64, (c.y2 - c.y1 + 1) as f64);
+        let rect = bw * bh;
+        if rect / page < opts.min_area || rect / page > opts.max_area {
+            continue;
+        }
+        if bw.max(bh) / bw.min(bh) > opts.max_aspect {
+            continue;
+        }
+        let fill = c.area as f64 / rect;
+        if fill < opts.min_fill {
+            continue;
+        }
+        let (ink_px, ink_rows) = ink(&gray, &labels, c, opts.dark);
+        let ink_ratio = ink_px as f64 / c.area as f64;
+        if ink_rows < opts.min_ink_rows || ink_ratio < opts.min_ink || ink_ratio > opts.max_ink {
+            continue;
+        }
+        // форма (заполненность) + насколько текст "размазан" по высоте бабла
+        let spread = (ink_rows as f64 / bh).min(1.0);
+        let score = (fill.min(1.0) + spread) / 2.0;
+        if score < opts.min_score {
+            continue;
+        }
+        out.push(Candidate {
+            x1: (c.x1 as f64 * k) as u32,
+            y1: (c.y1 as f64 * k) as u32,
+            x2: (((c.x2 + 1) as f64 * k) as u32).min(ow),
+            y2: (((c.y2 + 1) as f64 * k) as u32).min(oh),
+            score,
+        });
+    }
+    out
+}
+
+// Тот же ответ, что у /detect_text_areas: { boxes: [{x1, y1, x2, y2, score}] }
+pub fn detect_b64(image_b64: &str, opts: &HeuristicOptions) -> Result<Value, String> {
+    let b64 = image_b64.split_once(',').map(|(_, b)| b).unwrap_or(image_b64);
+    let bytes = STANDARD.decode(b64).map_err(|e| e.to_string())?;
+    let img = image::load_from_memory(&bytes).map_err(|e| e.to_string())?;
+    let boxes: Vec<Value> = detect(&img, opts)
+        .into_iter()
+        .map(|c| serde_json::json!({ "x1": c.x1, "y1": c.y1, "x2": c.x2, "y2": c.y2, "score": c.score }))
+        .collect();
+    Ok(serde_json::json!({ "boxes": boxes }))
+}
+
+pub fn is_selected(payload: &Value) -> bool {
+    payload.get("detection_model").and_then(|m| m.as_str()) == Some(MODEL)
+}
+
+// Детекция локально в blocking-потоке; payload как у бэкенда
+pub async fn detect_payload(payload: &Value) -> Result<Value, String> {
+    let image = payload
+        .get("image_data")
+        .and_then(|d| d.as_str())
+        .ok_or("No image_data in payload")?
+        .to_string();
+    tauri::async_runtime::spawn_blocking(move || detect_b64(&image, &HeuristicOptions::default()))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, RgbImage};
+
+    // Синтетическая страница: серый скринтон, белые эллипсы-баблы с "буквами" внутри
+    struct Page {
+        img: GrayImage,
+    }
+
+    impl Page {
+        fn new(w: u32, h: u32) -> Page {
+            // скринтон: точки через 4px
+            let img = GrayImage::from_fn(w, h, |x, y| if x % 4 == 0 && y % 4 == 0 { Luma([40]) } else { Luma([150]) });
+            Page { img }
+        }
+
+        fn ellipse(&mut self, cx: f64, cy: f64, rx: f64, ry: f64, outline: bool) {
+            for (x, y, p) in self.img.enumerate_pixels_mut() {
+                let d = ((x as f64 - cx) / rx).powi(2) + ((y as f64 - cy) / ry).powi(2);
+                if d <= 1.0 {
+                    *p = Luma([255]);
+                } else if outline && d <= 1.08 {
+                    *p = Luma([0]);
+                }
+            }
+        }
+
+        // строки "текста": короткие тёмные штрихи
+        fn text(&mut self, x1: u32, y1: u32, lines: u32, chars: u32) {
+            for l in 0..lines {
+                for c in 0..chars {
+                    let (x0, y0) = (x1 + c * 14, y1 + l * 22);
+                    for y in y0..y0 + 14 {
+                        for x in x0..x0 + 9 {
+                            if (x + y) % 3 != 0 {
+                                self.img.put_pixel(x, y, Luma([10]));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        fn rect(&mut self, x1: u32, y1: u32, x2: u32, y2: u32, v: u8) {
+            for y in y1..y2 {
+                for x in x1..x2 {
+                    self.img.put_pixel(x, y, Luma([v]));
+                }
+            }
+        }
+
+        fn detect(&self) -> Vec<Candidate> {
+            detect(&DynamicImage::ImageLuma8(self.img.clone()), &HeuristicOptions::default())
+        }
+    }
+
+    fn contains(c: &Candidate, x: u32, y: u32) -> bool {
+        c.x1 <= x && x <= c.x2 && c.y1 <= y && y <= c.y2
+    }
+
+    #[test]
+    fn finds_bubbles_with_text() {
+        let mut page = Page::new(800, 1200);
+        page.ellipse(200.0, 250.0, 140.0, 100.0, true);
+        page.text(130, 200, 4, 9);
+        page.ellipse(580.0, 800.0, 120.0, 160.0, true);
+        page.text(530, 700, 8, 6);
+
+        let found = page.detect();
+        assert_eq!(found.len(), 2, "{:?}", found);
+        assert!(found.iter().any(|c| contains(c, 200, 250)));
+        assert!(found.iter().any(|c| contains(c, 580, 800)));
+        // бокс охватывает бабл целиком, а не только текст
+        let b = found.iter().find(|c| contains(c, 200, 250)).unwrap();
+        assert!(b.x1 <= 65 && b.x2 >= 335 && b.y1 <= 155 && b.y2 >= 345, "{:?}", b);
+    }
+
+    #[test]
+    fn ignores_empty_white_regions_and_noise() {
+        let mut page = Page::new(800, 1200);
+        // пустой бабл без текста
+        page.ellipse(300.0, 300.0, 120.0, 90.0, true);
+        // мелкий блик на скринтоне
+        page.ellipse(600.0, 200.0, 6.0, 5.0, false);
+        // белая полоса-рамка
+        page.rect(100, 900, 700, 910, 255);
+        assert!(page.detect().is_empty());
+    }
+
+    #[test]
+    fn ignores_page_background() {
+        // белые поля страницы касаются края — это не бабл, даже с текстом (титры, номера страниц)
+        let mut page = Page::new(800, 1200);
+        page.rect(0, 0, 800, 120, 255);
+        page.text(300, 40, 2, 10);
+        assert!(page.detect().is_empty());
+    }
+
+    #[test]
+    fn large_pages_are_mapped_back_to_original_coordinates() {
+        let mut page = Page::new(1600, 3200);
+        page.ellipse(800.0, 1600.0, 260.0, 200.0, true);
+        page.text(660, 1500, 6, 14);
+        let opts = HeuristicOptions { work_side: 800, ..Default::default() };
+        let found = detect(&DynamicImage::ImageLuma8(page.img.clone()), &opts);
+        assert_eq!(found.len(), 1, "{:?}", found);
+        let b = found[0];
+        assert!(b.x1.abs_diff(540) < 12 && b.x2.abs_diff(1060) < 12, "{:?}", b);
+        assert!(b.y1.abs_diff(1400) < 12 && b.y2.abs_diff(1800) < 12, "{:?}", b);
+    }
+
+    #[test]
+    fn response_has_backend_shape() {
+        let mut page = Page::new(400, 600);
+        page.ellipse(200.0, 300.0, 120.0, 90.0, true);
+        page.text(140, 260, 3, 8);
+        let rgb: RgbImage = DynamicImage::ImageLuma8(page.img.clone()).to_rgb8();
+        let mut buf = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(rgb).write_to(&mut buf, image::ImageOutputFormat::Png).unwrap();
+        let b64 = format!("data:image/png;base64,{}", STANDARD.encode(buf.into_inner()));
+
+        let resp = detect_b64(&b64, &HeuristicOptions::default()).unwrap();
+        let boxes = resp["boxes"].as_array().unwrap();
+        assert_eq!(boxes.len(), 1);
+        for key in ["x1", "y1", "x2", "y2", "score"] {
+            assert!(boxes[0][key].is_number(), "{}", key);
+        }
+    }
+}
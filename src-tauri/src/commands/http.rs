@@ -0,0 +1,378 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use tauri::{command, AppHandle, Emitter, State};
+
+use crate::commands::ratelimit::{LimitPermit, LimitWait, RateLimit, RateLimiter};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+// Авторизация для всех URL, начинающихся с url_prefix (берётся самый длинный префикс)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointAuth {
+    pub url_prefix: String,
+    pub bearer: Option<String>,
+    pub basic: Option<BasicAuth>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+// http://, https:// или socks5:// (socks5h:// — DNS через прокси)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyConfig {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // через запятую: "localhost,127.0.0.1,.lan"
+    pub no_proxy: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpConfig {
+    pub proxy: Option<ProxyConfig>,
+    // пути к PEM/DER сертификатам, которые добавляются к корневым
+    #[serde(default)]
+    pub ca_certificates: Vec<String>,
+    #[serde(default)]
+    pub auth: Vec<EndpointAuth>,
+    // лимиты запросов/токенов/параллельности по провайдерам
+    #[serde(default)]
+    pub limits: Vec<RateLimit>,
+}
+
+fn load_certificate(path: &str) -> Result<reqwest::Certificate, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read CA certificate {}: {}", path, e))?;
+    reqwest::Certificate::from_pem(&bytes)
+        .or_else(|_| reqwest::Certificate::from_der(&bytes))
+        .map_err(|e| format!("Invalid CA certificate {}: {}", path, e))
+}
+
+fn build_client(config: &HttpConfig) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder();
+
+    if let Some(p) = config.proxy.as_ref().filter(|p| !p.url.trim().is_empty()) {
+        let mut proxy = reqwest::Proxy::all(p.url.trim()).map_err(|e| format!("Invalid proxy URL: {}", e))?;
+        if let Some(user) = p.username.as_deref().filter(|u| !u.is_empty()) {
+            proxy = proxy.basic_auth(user, p.password.as_deref().unwrap_or_default());
+        }
+        if let Some(no_proxy) = p.no_proxy.as_deref() {
+            proxy = proxy.no_proxy(reqwest::NoProxy::from_string(no_proxy));
+        }
+        builder = builder.proxy(proxy);
+    }
+
+    for path in config.ca_certificates.iter().filter(|p| !p.trim().is_empty()) {
+        builder = builder.add_root_certificate(load_certificate(path.trim())?);
+    }
+
+    builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+// Относится ли url к префиксу из настроек (авторизация, лимиты): та же схема, хост и порт,
+// путь — с границы сегмента. Простое starts_with отдало бы токен для api.example.com
+// на api.example.com.evil.net или на другой порт
+pub fn url_has_prefix(url: &str, prefix: &str) -> bool {
+    let (Ok(url), Ok(prefix)) = (reqwest::Url::parse(url), reqwest::Url::parse(prefix.trim())) else {
+        return false;
+    };
+    if url.scheme() != prefix.scheme()
+        || url.host_str() != prefix.host_str()
+        || url.port_or_known_default() != prefix.port_or_known_default()
+    {
+        return false;
+    }
+    let base = prefix.path().trim_end_matches('/');
+    base.is_empty() || url.path().strip_prefix(base).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+// Префиксы должны быть URL, заголовки — валидными: иначе правило молча не сработает
+fn validate_auth(auth: &[EndpointAuth]) -> Result<(), String> {
+    for a in auth.iter().filter(|a| !a.url_prefix.trim().is_empty()) {
+        reqwest::Url::parse(a.url_prefix.trim()).map_err(|e| format!("Invalid URL prefix {:?}: {}", a.url_prefix, e))?;
+        for (k, v) in &a.headers {
+            HeaderName::from_bytes(k.as_bytes())
+                .map_err(|e| format!("Invalid header name {:?} for {}: {}", k, a.url_prefix, e))?;
+            HeaderValue::from_str(v).map_err(|e| format!("Invalid value of header {} for {}: {}", k, a.url_prefix, e))?;
+        }
+    }
+    Ok(())
+}
+
+// Кому сообщать об ожидании лимита: пакетная задача (commands/jobs.rs) запускает работу страницы
+// в with_wait_handler, и ожидание попадает в её прогресс. None — запрос дождался очереди
+pub type WaitHandler = Arc<dyn Fn(Option<LimitWait>) + Send + Sync>;
+
+tokio::task_local! {
+    static ON_WAIT: WaitHandler;
+}
+
+pub async fn with_wait_handler<T>(handler: WaitHandler, work: impl Future<Output = T>) -> T {
+    ON_WAIT.scope(handler, work).await
+}
+
+// Общий HTTP-клиент всех команд: прокси, доп. сертификаты и авторизация по эндпоинтам
+pub struct HttpClient {
+    config: RwLock<HttpConfig>,
+    client: RwLock<reqwest::Client>,
+    limiter: RateLimiter,
+    // для событий ожидания лимита; выставляется в setup
+    app: OnceLock<AppHandle>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        HttpClient {
+            config: RwLock::new(HttpConfig::default()),
+            client: RwLock::new(reqwest::Client::new()),
+            limiter: RateLimiter::default(),
+            app: OnceLock::new(),
+        }
+    }
+}
+
+impl HttpClient {
+    pub fn configure(&self, config: HttpConfig) -> Result<(), String> {
+        validate_auth(&config.auth)?;
+        for limit in config.limits.iter().filter(|l| !l.url_prefix.trim().is_empty()) {
+            reqwest::Url::parse(limit.url_prefix.trim())
+                .map_err(|e| format!("Invalid URL prefix {:?}: {}", limit.url_prefix, e))?;
+        }
+        let client = build_client(&config)?;
+        self.limiter.configure(&config.limits);
+        *self.client.write().unwrap() = client;
+        *self.config.write().unwrap() = config;
+        Ok(())
+    }
+
+    pub fn attach(&self, app: AppHandle) {
+        let _ = self.app.set(app);
+    }
+
+    // Очередь к провайдеру: держать permit до конца запроса (для потоков — до конца чтения)
+    // Ожидание уходит владельцу запроса, если он есть, иначе — общим событием rate-limit-wait
+    pub async fn limit(&self, url: &str, tokens: u64) -> LimitPermit {
+        let owner = ON_WAIT.try_with(Arc::clone).ok();
+        let waited = AtomicBool::new(false);
+        let permit = self
+            .limiter
+            .acquire(url, tokens, |wait| {
+                waited.store(true, Ordering::Relaxed);
+                match &owner {
+                    Some(handler) => handler(Some(wait)),
+                    None => {
+                        if let Some(app) = self.app.get() {
+                            let _ = app.emit("rate-limit-wait", wait);
+                        }
+                    }
+                }
+            })
+            .await;
+        if let (Some(handler), true) = (&owner, waited.load(Ordering::Relaxed)) {
+            handler(None);
+        }
+        permit
+    }
+
+    pub fn config(&self) -> HttpConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    // reqwest::Client внутри — Arc, клонирование дешёвое
+    pub fn client(&self) -> reqwest::Client {
+        self.client.read().unwrap().clone()
+    }
+
+    pub fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.apply_auth(self.client().get(url), url)
+    }
+
+    pub fn post(&self, url: &str) -> reqwest::RequestBuilder {
+        self.apply_auth(self.client().post(url), url)
+    }
+
+    // POST с явным ключом (DeepLX): ключ заменяет авторизацию эндпоинта, а не добавляет
+    // второй Authorization — bearer_auth у reqwest дописывает заголовок
+    pub fn post_with_key(&self, url: &str, key: Option<&str>) -> reqwest::RequestBuilder {
+        let builder = self.post(url);
+        let Some(key) = key.filter(|k| !k.is_empty()) else {
+            return builder;
+        };
+        let Ok(mut value) = HeaderValue::from_str(&format!("Bearer {}", key)) else {
+            return builder;
+        };
+        value.set_sensitive(true);
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, value);
+        // headers() заменяет одноимённые заголовки
+        builder.headers(headers)
+    }
+
+    fn apply_auth(&self, mut builder: reqwest::RequestBuilder, url: &str) -> reqwest::RequestBuilder {
+        let config = self.config.read().unwrap();
+        let Some(auth) = config
+            .auth
+            .iter()
+            .filter(|a| !a.url_prefix.trim().is_empty() && url_has_prefix(url, &a.url_prefix))
+            .max_by_key(|a| a.url_prefix.trim().len())
+        else {
+            return builder;
+        };
+
+        // заголовки проверены в configure
+        let mut headers = HeaderMap::new();
+        for (k, v) in &auth.headers {
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(k.as_bytes()), HeaderValue::from_str(v)) {
+                headers.insert(name, value);
+            }
+        }
+        builder = builder.headers(headers);
+
+        if let Some(token) = auth.bearer.as_deref().filter(|t| !t.is_empty()) {
+            builder = builder.bearer_auth(token);
+        } else if let Some(basic) = &auth.basic {
+            builder = builder.basic_auth(&basic.username, basic.password.as_deref());
+        }
+        builder
+    }
+}
+
+#[command]
+pub async fn set_http_config(http: State<'_, HttpClient>, config: HttpConfig) -> Result<(), String> {
+    http.configure(config)
+}
+
+#[command]
+pub async fn get_http_config(http: State<'_, HttpClient>) -> Result<HttpConfig, String> {
+    Ok(http.config())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(auth: EndpointAuth) -> HttpClient {
+        let http = HttpClient::default();
+        http.configure(HttpConfig { auth: vec![auth], ..Default::default() }).unwrap();
+        http
+    }
+
+    fn authorization(builder: reqwest::RequestBuilder) -> Vec<String> {
+        let request = builder.build().unwrap();
+        request.headers().get_all(AUTHORIZATION).iter().map(|v| v.to_str().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn explicit_key_replaces_endpoint_bearer() {
+        let http = client(EndpointAuth {
+            url_prefix: "http://deeplx.lan".to_string(),
+            bearer: Some("endpoint".to_string()),
+            ..Default::default()
+        });
+        let sent = authorization(http.post_with_key("http://deeplx.lan/translate", Some("key")));
+        assert_eq!(sent, vec!["Bearer key".to_string()]);
+    }
+
+    #[test]
+    fn explicit_key_replaces_endpoint_basic() {
+        let http = client(EndpointAuth {
+            url_prefix: "http://deeplx.lan".to_string(),
+            basic: Some(BasicAuth { username: "user".to_string(), password: Some("pass".to_string()) }),
+            ..Default::default()
+        });
+        let sent = authorization(http.post_with_key("http://deeplx.lan/translate", Some("key")));
+        assert_eq!(sent, vec!["Bearer key".to_string()]);
+    }
+
+    #[test]
+    fn endpoint_auth_without_key() {
+        let http = client(EndpointAuth {
+            url_prefix: "http://deeplx.lan".to_string(),
+            bearer: Some("endpoint".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(authorization(http.post_with_key("http://deeplx.lan/translate", None)), vec!["Bearer endpoint"]);
+        assert_eq!(authorization(http.post_with_key("http://deeplx.lan/translate", Some(""))), vec!["Bearer endpoint"]);
+        assert!(authorization(http.post_with_key("http://other.lan/translate", None)).is_empty());
+    }
+
+    #[test]
+    fn prefix_matches_same_origin_on_segment_boundary() {
+        let prefix = "https://api.example.com/v1/";
+        assert!(url_has_prefix("https://api.example.com/v1/chat/completions", prefix));
+        assert!(url_has_prefix("https://api.example.com/v1", prefix));
+        assert!(url_has_prefix("https://api.example.com:443/v1/models", prefix));
+        assert!(url_has_prefix("https://API.example.com/v1/models", prefix));
+        assert!(!url_has_prefix("https://api.example.com/v12/models", prefix));
+        assert!(!url_has_prefix("https://api.example.com/", prefix));
+    }
+
+    #[test]
+    fn prefix_rejects_other_hosts_ports_and_schemes() {
+        let prefix = "https://api.example.com";
+        assert!(url_has_prefix("https://api.example.com/anything", prefix));
+        assert!(!url_has_prefix("https://api.example.com.attacker.net/v1", prefix));
+        assert!(!url_has_prefix("https://api.example.com:8443/v1", prefix));
+        assert!(!url_has_prefix("http://api.example.com/v1", prefix));
+        assert!(!url_has_prefix("https://evil.net/?u=https://api.example.com", prefix));
+        assert!(!url_has_prefix("not a url", prefix));
+        assert!(!url_has_prefix("https://api.example.com/v1", "api.example.com"));
+    }
+
+    #[test]
+    fn auth_is_not_sent_to_lookalike_host() {
+        let http = client(EndpointAuth {
+            url_prefix: "https://api.example.com".to_string(),
+            bearer: Some("secret".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(authorization(http.post("https://api.example.com/v1/chat")), vec!["Bearer secret"]);
+        assert!(authorization(http.post("https://api.example.com.attacker.net/v1/chat")).is_empty());
+        assert!(authorization(http.post("https://api.example.com:8443/v1/chat")).is_empty());
+    }
+
+    #[test]
+    fn invalid_headers_and_prefixes_are_rejected() {
+        let http = HttpClient::default();
+        let mut auth = EndpointAuth { url_prefix: "https://api.example.com".to_string(), ..Default::default() };
+        auth.headers.insert("Bad Header".to_string(), "x".to_string());
+        assert!(http.configure(HttpConfig { auth: vec![auth.clone()], ..Default::default() }).is_err());
+        auth.headers = BTreeMap::from([("X-Key".to_string(), "line\nbreak".to_string())]);
+        assert!(http.configure(HttpConfig { auth: vec![auth.clone()], ..Default::default() }).is_err());
+        auth.headers = BTreeMap::from([("X-Key".to_string(), "ok".to_string())]);
+        assert!(http.configure(HttpConfig { auth: vec![auth.clone()], ..Default::default() }).is_ok());
+        auth.url_prefix = "api.example.com".to_string();
+        assert!(http.configure(HttpConfig { auth: vec![auth], ..Default::default() }).is_err());
+    }
+
+    #[tokio::test]
+    async fn limit_wait_goes_to_request_owner() {
+        let http = HttpClient::default();
+        let limit = RateLimit { url_prefix: "http://llm.lan".to_string(), max_concurrent: Some(1), ..Default::default() };
+        http.configure(HttpConfig { limits: vec![limit], ..Default::default() }).unwrap();
+        let first = http.limit("http://llm.lan/v1/chat", 0).await;
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let on_wait: WaitHandler = {
+            let seen = seen.clone();
+            Arc::new(move |wait: Option<LimitWait>| seen.lock().unwrap().push(wait.map(|w| w.url_prefix)))
+        };
+        let second = with_wait_handler(on_wait, http.limit("http://llm.lan/v1/chat", 0));
+        let release = async {
+            tokio::task::yield_now().await;
+            drop(first);
+        };
+        let (_permit, ()) = tokio::join!(second, release);
+        // ожидание и его конец — владельцу, без общего события
+        assert_eq!(*seen.lock().unwrap(), vec![Some("http://llm.lan".to_string()), None]);
+    }
+}
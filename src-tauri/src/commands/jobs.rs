@@ -0,0 +1,1083 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle, Emitter, Manager, State};
+use tokio::sync::{mpsc, watch, Semaphore};
+
+use crate::commands::cache::ResultCache;
+use crate::commands::downscale::DetectPrep;
+use crate::commands::gutters::PanelCheck;
+use crate::commands::endpoints::EndpointPool;
+use crate::commands::http::{self, HttpClient, WaitHandler};
+use crate::commands::ratelimit::LimitWait;
+use crate::commands::regions::{self, CropOptions};
+use crate::commands::stages::{self, StageHashes};
+use crate::commands::tiling::TileOptions;
+use crate::commands::usage::{UsageContext, UsageLedger};
+use crate::commands::postprocess::{PostprocessOptions, PostprocessReport};
+use crate::commands::reading_order::{self, PageDetectOptions, PanelSource, ReadingOptions};
+use crate::commands::{chat_completion, deeplx_request, BoundingBox};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchPage {
+    pub path: String,
+    pub name: String,
+}
+
+// Сколько страниц одновременно на каждом этапе
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StageLimits {
+    pub detect: usize,
+    pub ocr: usize,
+    pub translate: usize,
+}
+
+impl Default for StageLimits {
+    fn default() -> Self {
+        StageLimits { detect: 2, ocr: 2, translate: 1 }
+    }
+}
+
+// Второй шаг перевода (EN -> целевой язык) через DeepLX
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeeplxStep {
+    pub url: String,
+    // в журнал не пишется — при продолжении задачи ключ снова передаёт фронт
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<String>,
+    pub target_lang: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchOptions {
+    pub api_url: String,
+    pub detection_model: Option<String>,
+    // детекция на уменьшенной копии: большая сторона не больше (0/None — как есть)
+    #[serde(default)]
+    pub detect_max_side: Option<u32>,
+    // длинные страницы (вебтуны) детектим по тайлам
+    #[serde(default)]
+    pub tiling: Option<TileOptions>,
+    #[serde(default)]
+    pub use_panel_detection: bool,
+    #[serde(default)]
+    pub panel_source: PanelSource,
+    #[serde(default)]
+    pub reading: ReadingOptions,
+    #[serde(default)]
+    pub postprocess: Option<PostprocessOptions>,
+    pub ocr_engine: Option<String>,
+    // базовый URL переводчика, /v1/chat/completions добавляется здесь
+    pub translation_url: String,
+    pub model: String,
+    pub system_prompt: String,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+    pub deeplx: Option<DeeplxStep>,
+    pub project_id: Option<String>,
+    #[serde(default)]
+    pub limits: StageLimits,
+    // подготовка кропов для OCR
+    #[serde(default)]
+    pub crop: CropOptions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Detect,
+    Ocr,
+    Translate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Paused,
+    Cancelled,
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+// Совпадает по полям с DetectedTextItem на фронте (без id)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobItem {
+    #[serde(rename = "box")]
+    pub bbox: BoundingBox,
+    pub ocr_text: Option<String>,
+    pub translation: Option<String>,
+    pub cached_intermediate_text: Option<String>,
+    pub cached_intermediate_lang: Option<String>,
+    #[serde(default)]
+    pub stage_hashes: StageHashes,
+}
+
+impl JobItem {
+    fn new(bbox: BoundingBox) -> Self {
+        JobItem {
+            bbox,
+            ocr_text: None,
+            translation: None,
+            cached_intermediate_text: None,
+            cached_intermediate_lang: None,
+            stage_hashes: StageHashes::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageState {
+    pub path: String,
+    pub name: String,
+    pub status: PageStatus,
+    // последний успешно завершённый этап
+    pub completed: Option<Stage>,
+    pub running: Option<Stage>,
+    pub error: Option<String>,
+    pub items: Vec<JobItem>,
+    // что чистка боксов поменяла при детекции
+    #[serde(default)]
+    pub detect_report: Option<PostprocessReport>,
+    // сверка панелей с гуттерами и причина отката на гуттеры — как в DetectedPage
+    #[serde(default)]
+    pub panel_check: Option<PanelCheck>,
+    #[serde(default)]
+    pub panel_fallback: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    pub job_id: String,
+    pub state: JobState,
+    pub total: usize,
+    pub detected: usize,
+    pub recognized: usize,
+    pub translated: usize,
+    pub failed: usize,
+    pub elapsed_ms: u64,
+    pub eta_seconds: Option<f64>,
+    // страница, состояние которой только что изменилось
+    pub page: Option<PageState>,
+    // последняя ошибка записи журнала: задача идёт, но продолжить её после падения не выйдет
+    pub journal_error: Option<String>,
+    // запрос этой задачи стоит в очереди у лимита провайдера (самое долгое из текущих ожиданий)
+    pub limit_wait: Option<LimitWait>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    #[serde(flatten)]
+    pub progress: JobProgress,
+    pub pages: Vec<PageState>,
+}
+
+struct Timing {
+    started: Instant,
+    paused_at: Option<Instant>,
+    paused_total: Duration,
+}
+
+pub struct Job {
+    pub id: String,
+    options: BatchOptions,
+    control: watch::Sender<JobState>,
+    pages: Mutex<Vec<PageState>>,
+    timing: Mutex<Timing>,
+    // файл журнала в рабочей папке проекта; None — не пишем (нет app data dir)
+    journal: Option<PathBuf>,
+    journal_lock: Mutex<()>,
+    // запись журнала уже запланирована
+    journal_pending: AtomicBool,
+    // журнал удалён — отложенные записи его не воскрешают
+    journal_closed: AtomicBool,
+    journal_error: Mutex<Option<String>>,
+    // ожидания лимитов по страницам: индекс страницы -> ожидание
+    waits: Mutex<HashMap<usize, LimitWait>>,
+}
+
+// Что лежит на диске: достаточно, чтобы продолжить задачу после падения
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobJournal {
+    pub job_id: String,
+    pub options: BatchOptions,
+    pub pages: Vec<PageState>,
+    pub updated_at: u64,
+}
+
+// Краткая сводка незавершённой задачи для диалога "продолжить?"
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumableJob {
+    pub job_id: String,
+    pub project_id: String,
+    pub total: usize,
+    pub done: usize,
+    pub failed: usize,
+    pub updated_at: u64,
+    pub pages: Vec<BatchPageInfo>,
+}
+
+// Журнал, который не удалось прочитать: задачу из него не продолжить
+#[derive(Debug, Clone, Serialize)]
+pub struct BrokenJournal {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResumableJobs {
+    pub jobs: Vec<ResumableJob>,
+    pub broken: Vec<BrokenJournal>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchPageInfo {
+    pub path: String,
+    pub name: String,
+}
+
+impl Job {
+    fn new(id: String, pages: Vec<BatchPage>, options: BatchOptions, journal: Option<PathBuf>) -> Self {
+        let pages = pages
+            .into_iter()
+            .map(|p| PageState {
+                path: p.path,
+                name: p.name,
+                status: PageStatus::Pending,
+                completed: None,
+                running: None,
+                error: None,
+                items: Vec::new(),
+                detect_report: None,
+                panel_check: None,
+                panel_fallback: None,
+            })
+            .collect();
+        Self::with_pages(id, pages, options, journal)
+    }
+
+    fn with_pages(id: String, pages: Vec<PageState>, options: BatchOptions, journal: Option<PathBuf>) -> Self {
+        Job {
+            id,
+            options,
+            control: watch::channel(JobState::Running).0,
+            pages: Mutex::new(pages),
+            timing: Mutex::new(Timing {
+                started: Instant::now(),
+                paused_at: None,
+                paused_total: Duration::ZERO,
+            }),
+            journal,
+            journal_lock: Mutex::new(()),
+            journal_pending: AtomicBool::new(false),
+            journal_closed: AtomicBool::new(false),
+            journal_error: Mutex::new(None),
+            waits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Из журнала: упавшие и прерванные страницы снова в очередь, готовые этапы не повторяем
+    fn from_journal(journal: JobJournal, path: PathBuf) -> Self {
+        let pages = journal
+            .pages
+            .into_iter()
+            .map(|mut p| {
+                if p.status != PageStatus::Done {
+                    p.status = PageStatus::Pending;
+                    p.running = None;
+                    p.error = None;
+                }
+                p
+            })
+            .collect();
+        Self::with_pages(journal.job_id, pages, journal.options, Some(path))
+    }
+
+    // Атомарно переписывает журнал (tmp + rename), чтобы падение посреди записи его не портило.
+    // Снимок страниц берётся под journal_lock, поэтому последней на диск ложится самая свежая запись.
+    fn write_journal(&self) {
+        let Some(path) = &self.journal else {
+            return;
+        };
+        let _guard = self.journal_lock.lock().unwrap();
+        if self.journal_closed.load(Ordering::Acquire) {
+            return;
+        }
+        let journal = JobJournal {
+            job_id: self.id.clone(),
+            options: self.options.clone(),
+            pages: self.pages.lock().unwrap().clone(),
+            updated_at: now_ms(),
+        };
+        let result = serde_json::to_vec(&journal)
+            .map_err(|e| e.to_string())
+            .and_then(|json| write_atomic(path, &json))
+            .map_err(|e| format!("Failed to write job journal {}: {}", path.display(), e));
+        *self.journal_error.lock().unwrap() = result.err();
+    }
+
+    // Запись сразу (старт, продолжение, конец задачи) — вне async-рантайма
+    async fn flush_journal(self: &Arc<Self>) {
+        let job = self.clone();
+        let _ = tauri::async_runtime::spawn_blocking(move || job.write_journal()).await;
+    }
+
+    // Запись после этапа: не чаще раза в JOURNAL_EVERY — иначе большая задача переписывает
+    // журнал целиком после каждой страницы. Изменения за время ожидания попадут в ту же запись.
+    fn persist(self: &Arc<Self>) {
+        if self.journal.is_none() || self.journal_pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let job = self.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(JOURNAL_EVERY).await;
+            job.journal_pending.store(false, Ordering::Release);
+            job.flush_journal().await;
+        });
+    }
+
+    async fn remove_journal(self: &Arc<Self>) {
+        let job = self.clone();
+        let _ = tauri::async_runtime::spawn_blocking(move || {
+            if let Some(path) = &job.journal {
+                let _guard = job.journal_lock.lock().unwrap();
+                job.journal_closed.store(true, Ordering::Release);
+                let _ = std::fs::remove_file(path);
+            }
+        })
+        .await;
+    }
+
+    // Следующий этап страницы; None — страница уже готова
+    fn next_stage(&self, idx: usize) -> Option<Stage> {
+        match self.pages.lock().unwrap()[idx].completed {
+            None => Some(Stage::Detect),
+            Some(Stage::Detect) => Some(Stage::Ocr),
+            Some(Stage::Ocr) => Some(Stage::Translate),
+            Some(Stage::Translate) => None,
+        }
+    }
+
+    pub fn state(&self) -> JobState {
+        *self.control.borrow()
+    }
+
+    fn set_state(&self, state: JobState) {
+        let mut timing = self.timing.lock().unwrap();
+        match (self.state(), state) {
+            (JobState::Running, JobState::Paused) => timing.paused_at = Some(Instant::now()),
+            (JobState::Paused, _) => {
+                if let Some(at) = timing.paused_at.take() {
+                    timing.paused_total += at.elapsed();
+                }
+            }
+            _ => {}
+        }
+        drop(timing);
+        self.control.send_replace(state);
+    }
+
+    // Ждёт, пока задача не на паузе; false — задачу отменили
+    async fn wait_runnable(&self) -> bool {
+        let mut rx = self.control.subscribe();
+        loop {
+            match *rx.borrow_and_update() {
+                JobState::Running => return true,
+                JobState::Cancelled | JobState::Completed => return false,
+                JobState::Paused => {}
+            }
+            if rx.changed().await.is_err() {
+                return false;
+            }
+        }
+    }
+
+    async fn cancelled(&self) {
+        let mut rx = self.control.subscribe();
+        let _ = rx.wait_for(|s| *s == JobState::Cancelled).await;
+    }
+
+    fn elapsed(&self) -> Duration {
+        let timing = self.timing.lock().unwrap();
+        let paused = timing.paused_total + timing.paused_at.map(|t| t.elapsed()).unwrap_or_default();
+        timing.started.elapsed().saturating_sub(paused)
+    }
+
+    fn page(&self, idx: usize) -> PageState {
+        self.pages.lock().unwrap()[idx].clone()
+    }
+
+    fn update_page<F: FnOnce(&mut PageState)>(&self, idx: usize, f: F) -> PageState {
+        let mut pages = self.pages.lock().unwrap();
+        f(&mut pages[idx]);
+        pages[idx].clone()
+    }
+
+    fn progress(&self, page: Option<PageState>) -> JobProgress {
+        let pages = self.pages.lock().unwrap();
+        let reached = |s: Stage| {
+            pages
+                .iter()
+                .filter(|p| p.completed.is_some_and(|c| stage_rank(c) >= stage_rank(s)))
+                .count()
+        };
+        let (detected, recognized, translated) = (reached(Stage::Detect), reached(Stage::Ocr), reached(Stage::Translate));
+        let failed = pages.iter().filter(|p| p.status == PageStatus::Failed).count();
+        let total = pages.len();
+        drop(pages);
+
+        // ETA по пропускной способности конвейера: единица работы = страница на этапе
+        let elapsed = self.elapsed();
+        let units_done = detected + recognized + translated;
+        let units_left = (total - failed.min(total)) * 3;
+        let units_left = units_left.saturating_sub(units_done);
+        let eta_seconds = (units_done > 0 && units_left > 0)
+            .then(|| elapsed.as_secs_f64() / units_done as f64 * units_left as f64);
+
+        JobProgress {
+            job_id: self.id.clone(),
+            state: self.state(),
+            total,
+            detected,
+            recognized,
+            translated,
+            failed,
+            elapsed_ms: elapsed.as_millis() as u64,
+            eta_seconds,
+            page,
+            journal_error: self.journal_error.lock().unwrap().clone(),
+            limit_wait: self.waits.lock().unwrap().values().max_by_key(|w| w.wait_ms).cloned(),
+        }
+    }
+
+    fn set_wait(&self, idx: usize, wait: Option<LimitWait>) {
+        let mut waits = self.waits.lock().unwrap();
+        match wait {
+            Some(wait) => waits.insert(idx, wait),
+            None => waits.remove(&idx),
+        };
+    }
+
+    pub fn status(&self) -> JobStatus {
+        JobStatus {
+            progress: self.progress(None),
+            pages: self.pages.lock().unwrap().clone(),
+        }
+    }
+}
+
+fn stage_rank(s: Stage) -> u8 {
+    match s {
+        Stage::Detect => 1,
+        Stage::Ocr => 2,
+        Stage::Translate => 3,
+    }
+}
+
+fn emit_progress(app: &AppHandle, job: &Job, page: Option<PageState>) {
+    let _ = app.emit("job-progress", job.progress(page));
+}
+
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+}
+
+impl JobManager {
+    fn get(&self, job_id: &str) -> Result<Arc<Job>, String> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(job_id)
+            .cloned()
+            .ok_or_else(|| format!("Unknown job: {}", job_id))
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn new_job_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    format!("job-{}-{}", now_ms(), COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+// ---------- Журнал ----------
+
+const JOURNAL_EVERY: Duration = Duration::from_millis(500);
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    let mut f = std::fs::File::create(&tmp).map_err(|e| e.to_string())?;
+    f.write_all(bytes).map_err(|e| e.to_string())?;
+    f.sync_all().map_err(|e| e.to_string())?;
+    drop(f);
+    std::fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+// Рабочая папка проектов: <app data>/workspace/<projectId>/jobs/<jobId>.json
+fn workspace_root(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_data_dir().ok().map(|d| d.join("workspace"))
+}
+
+// Папка проекта в workspace (журналы задач, нарезки вебтунов)
+pub(crate) fn project_dir(app: &AppHandle, project_id: Option<&str>) -> Option<PathBuf> {
+    let project = project_id.filter(|p| !p.is_empty()).unwrap_or("default");
+    // projectId приходит с фронта — не даём ему выйти за пределы workspace
+    let project: String = project
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    workspace_root(app).map(|root| root.join(project))
+}
+
+fn journal_path(app: &AppHandle, project_id: Option<&str>, job_id: &str) -> Option<PathBuf> {
+    project_dir(app, project_id).map(|dir| dir.join("jobs").join(format!("{}.json", job_id)))
+}
+
+// Все журналы: битые тоже, с причиной — их показывает list_resumable_jobs
+fn scan_journals(app: &AppHandle) -> Vec<(PathBuf, Result<JobJournal, String>)> {
+    let Some(root) = workspace_root(app) else {
+        return Vec::new();
+    };
+    let Ok(projects) = std::fs::read_dir(&root) else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    for project in projects.flatten() {
+        let Ok(files) = std::fs::read_dir(project.path().join("jobs")) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let path = file.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let journal = std::fs::read(&path).map_err(|e| e.to_string()).and_then(|b| {
+                serde_json::from_slice::<JobJournal>(&b).map_err(|e| e.to_string())
+            });
+            out.push((path, journal));
+        }
+    }
+    out
+}
+
+fn read_journals(app: &AppHandle) -> Vec<(PathBuf, JobJournal)> {
+    scan_journals(app).into_iter().filter_map(|(path, j)| j.ok().map(|j| (path, j))).collect()
+}
+
+// ---------- Этапы ----------
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T, String> + Send + 'static) -> Result<T, String> {
+    tauri::async_runtime::spawn_blocking(f).await.map_err(|e| e.to_string())?
+}
+
+async fn run_detect(app: &AppHandle, job: &Job, idx: usize) -> Result<(), String> {
+    let path = job.page(idx).path;
+    let image_data = blocking(move || {
+        std::fs::read(&path).map(|b| STANDARD.encode(b)).map_err(|e| e.to_string())
+    })
+    .await?;
+
+    let pool = app.state::<EndpointPool>();
+    let http = app.state::<HttpClient>();
+    let cache = app.state::<ResultCache>();
+    let opts = &job.options;
+    // OCR и инпейнт потом работают с оригиналом — уменьшение и тайлы только для детекции
+    let detect = PageDetectOptions {
+        prep: DetectPrep {
+            max_side: opts.detect_max_side,
+            tiling: opts.tiling.clone(),
+        },
+        use_panels: opts.use_panel_detection,
+        panel_source: opts.panel_source,
+        reading: opts.reading.clone(),
+        postprocess: opts.postprocess.clone(),
+    };
+    let payload = serde_json::json!({
+        "image_data": image_data,
+        "detection_model": opts.detection_model,
+    });
+
+    let page = reading_order::detect_ordered(&pool, &http, &cache, &opts.api_url, payload, &detect).await?;
+    job.update_page(idx, |p| {
+        p.items = page.boxes.into_iter().map(JobItem::new).collect();
+        p.detect_report = page.report;
+        p.panel_check = page.panel_check;
+        p.panel_fallback = page.panel_fallback;
+    });
+    Ok(())
+}
+
+async fn run_ocr(app: &AppHandle, job: &Job, idx: usize) -> Result<(), String> {
+    let page = job.page(idx);
+    let boxes: Vec<BoundingBox> = page.items.iter().map(|i| i.bbox).collect();
+    let opts = &job.options;
+    let results = regions::recognize(
+        app,
+        &opts.api_url,
+        opts.ocr_engine.as_deref(),
+        page.path.clone(),
+        None,
+        boxes,
+        opts.crop.clone(),
+    )
+    .await?;
+
+    let ocr = stages::OcrInput::from_batch(opts);
+    job.update_page(idx, |p| {
+        for (item, text) in p.items.iter_mut().zip(results) {
+            item.stage_hashes.ocr = Some(stages::crop_hash(&p.path, &item.bbox, &ocr));
+            item.ocr_text = text;
+        }
+    });
+    Ok(())
+}
+
+fn strip_code_fences(s: &str) -> String {
+    s.lines()
+        .filter(|l| !l.trim_start().starts_with("```"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Порт parseNumberedLinesToPairs из src/utils/llm.ts: "12. текст" / "12) текст" / JSON-массив
+pub fn parse_numbered_lines(raw: &str) -> Vec<(usize, String)> {
+    let cleaned = strip_code_fences(raw);
+    let mut out = Vec::new();
+    for line in cleaned.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            continue;
+        }
+        let rest = &line[digits..];
+        let Some(sep) = rest.chars().next().filter(|c| matches!(c, '.' | '`' | ':' | '-' | ')')) else {
+            continue;
+        };
+        if let Ok(id) = line[..digits].parse::<usize>() {
+            out.push((id, rest[sep.len_utf8()..].trim().to_string()));
+        }
+    }
+    if out.is_empty() {
+        if let (Some(start), Some(end)) = (cleaned.find('['), cleaned.rfind(']')) {
+            if let Ok(Value::Array(arr)) = serde_json::from_str::<Value>(&cleaned[start..=end]) {
+                for v in arr {
+                    if let (Some(id), Some(t)) = (v.get("id").and_then(|i| i.as_u64()), v.get("translation").and_then(|t| t.as_str())) {
+                        out.push((id as usize, t.to_string()));
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+pub(crate) struct Translated {
+    pub id: usize,
+    pub text: String,
+    // английский промежуточный перевод, если был второй шаг через DeepLX
+    pub intermediate: Option<String>,
+}
+
+// Перевод пронумерованных строк: LLM, затем (если задан) DeepLX EN -> целевой язык
+pub(crate) async fn translate_lines(
+    app: &AppHandle,
+    opts: &BatchOptions,
+    lines: &[(usize, String)],
+    usage: UsageContext,
+) -> Result<Vec<Translated>, String> {
+    let numbered: Vec<String> = lines
+        .iter()
+        .filter(|(_, t)| !t.trim().is_empty())
+        .map(|(id, t)| format!("{}. {}", id, t))
+        .collect();
+    if numbered.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let http = app.state::<HttpClient>();
+    let ledger = app.state::<UsageLedger>();
+    let api_url = format!("{}/v1/chat/completions", opts.translation_url.trim_end_matches('/'));
+    let payload = serde_json::json!({
+        "model": if opts.model.is_empty() { "local-model" } else { opts.model.as_str() },
+        "messages": [
+            { "role": "system", "content": opts.system_prompt },
+            { "role": "user", "content": numbered.join("\n") },
+        ],
+        "stream": false,
+        "temperature": opts.temperature.unwrap_or(0.2),
+        "max_tokens": opts.max_tokens.unwrap_or(1500),
+    });
+    let resp = chat_completion(&http, &ledger, &api_url, &payload, Some(&usage)).await?;
+    let content = resp
+        .pointer("/choices/0/message/content")
+        .and_then(|c| c.as_str())
+        .unwrap_or_default();
+    let pairs = parse_numbered_lines(content);
+    if pairs.is_empty() {
+        return Err("Could not parse LLM response.".to_string());
+    }
+
+    // Второй шаг: EN -> целевой язык, английский остаётся в кэше
+    let Some(step) = &opts.deeplx else {
+        return Ok(pairs
+            .into_iter()
+            .map(|(id, text)| Translated { id, text, intermediate: None })
+            .collect());
+    };
+    let texts: Vec<String> = pairs.iter().map(|(_, t)| t.clone()).collect();
+    let resp = deeplx_request(&http, &step.url, step.api_key.as_deref(), &texts, &step.target_lang, Some("EN")).await?;
+    let data = resp.get("data").and_then(|d| d.as_str()).unwrap_or_default();
+    let second: Vec<&str> = data.split('\n').collect();
+    Ok(pairs
+        .into_iter()
+        .enumerate()
+        .filter_map(|(n, (id, en))| {
+            second.get(n).map(|t| Translated {
+                id,
+                text: t.to_string(),
+                intermediate: Some(en),
+            })
+        })
+        .collect())
+}
+
+async fn run_translate(app: &AppHandle, job: &Job, idx: usize) -> Result<(), String> {
+    let page = job.page(idx);
+    let lines: Vec<(usize, String)> = page
+        .items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, it)| it.ocr_text.clone().map(|t| (i + 1, t)))
+        .collect();
+    let usage = UsageContext {
+        project_id: job.options.project_id.clone(),
+        page: Some(page.name.clone()),
+        run_id: Some(job.id.clone()),
+    };
+    let translated = translate_lines(app, &job.options, &lines, usage).await?;
+
+    job.update_page(idx, |p| {
+        for t in translated {
+            let Some(item) = t.id.checked_sub(1).and_then(|i| p.items.get_mut(i)) else {
+                continue;
+            };
+            item.stage_hashes.translation = item.ocr_text.as_deref().map(stages::text_hash);
+            item.cached_intermediate_lang = t.intermediate.as_ref().map(|_| "EN".to_string());
+            item.cached_intermediate_text = t.intermediate;
+            item.translation = Some(t.text);
+        }
+    });
+    Ok(())
+}
+
+async fn run_stage(app: &AppHandle, job: &Job, stage: Stage, idx: usize) -> Result<(), String> {
+    let work: std::pin::Pin<Box<dyn Future<Output = Result<(), String>> + Send + '_>> = match stage {
+        Stage::Detect => Box::pin(run_detect(app, job, idx)),
+        Stage::Ocr => Box::pin(run_ocr(app, job, idx)),
+        Stage::Translate => Box::pin(run_translate(app, job, idx)),
+    };
+    tokio::select! {
+        r = work => r,
+        _ = job.cancelled() => Err("Cancelled".to_string()),
+    }
+}
+
+// Один этап конвейера: берёт страницы из очереди, держит не больше `limit` в работе,
+// готовые передаёт следующему этапу
+async fn stage_loop(
+    app: AppHandle,
+    job: Arc<Job>,
+    stage: Stage,
+    mut rx: mpsc::UnboundedReceiver<usize>,
+    next: Option<mpsc::UnboundedSender<usize>>,
+    limit: usize,
+) {
+    let sem = Arc::new(Semaphore::new(limit.max(1)));
+    let mut handles = Vec::new();
+    while let Some(idx) = rx.recv().await {
+        if !job.wait_runnable().await {
+            break;
+        }
+        let Ok(permit) = sem.clone().acquire_owned().await else {
+            break;
+        };
+        let (app, job, next) = (app.clone(), job.clone(), next.clone());
+        handles.push(tauri::async_runtime::spawn(async move {
+            let _permit = permit;
+            if !job.wait_runnable().await {
+                return;
+            }
+            let page = job.update_page(idx, |p| {
+                p.status = PageStatus::Running;
+                p.running = Some(stage);
+            });
+            emit_progress(&app, &job, Some(page));
+
+            // ожидания лимитов запросами этой страницы — в прогресс задачи
+            let on_wait: WaitHandler = {
+                let (app, job) = (app.clone(), job.clone());
+                Arc::new(move |wait| {
+                    job.set_wait(idx, wait);
+                    emit_progress(&app, &job, None);
+                })
+            };
+            let result = http::with_wait_handler(on_wait, run_stage(&app, &job, stage, idx)).await;
+            // отмена могла прервать запрос прямо в очереди
+            job.set_wait(idx, None);
+            let last = next.is_none();
+            let page = job.update_page(idx, |p| {
+                p.running = None;
+                match &result {
+                    Ok(()) => {
+                        p.completed = Some(stage);
+                        p.error = None;
+                        p.status = if last { PageStatus::Done } else { PageStatus::Pending };
+                    }
+                    Err(e) => {
+                        p.status = PageStatus::Failed;
+                        p.error = Some(format!("{:?}: {}", stage, e));
+                    }
+                }
+            });
+            // отменённый этап не записываем как ошибку — при продолжении он просто повторится
+            if job.state() != JobState::Cancelled {
+                job.persist();
+            }
+            emit_progress(&app, &job, Some(page));
+            if let (Ok(()), Some(next)) = (result, next) {
+                let _ = next.send(idx);
+            }
+        }));
+    }
+    for h in handles {
+        let _ = h.await;
+    }
+}
+
+async fn run_job(app: AppHandle, job: Arc<Job>) {
+    let limits = job.options.limits;
+    let (tx_detect, rx_detect) = mpsc::unbounded_channel();
+    let (tx_ocr, rx_ocr) = mpsc::unbounded_channel();
+    let (tx_translate, rx_translate) = mpsc::unbounded_channel();
+    // каждая страница входит в конвейер с того этапа, на котором остановилась
+    let total = job.pages.lock().unwrap().len();
+    for idx in 0..total {
+        let _ = match job.next_stage(idx) {
+            Some(Stage::Detect) => tx_detect.send(idx),
+            Some(Stage::Ocr) => tx_ocr.send(idx),
+            Some(Stage::Translate) => tx_translate.send(idx),
+            None => Ok(()),
+        };
+    }
+    drop(tx_detect);
+
+    tokio::join!(
+        stage_loop(app.clone(), job.clone(), Stage::Detect, rx_detect, Some(tx_ocr), limits.detect),
+        stage_loop(app.clone(), job.clone(), Stage::Ocr, rx_ocr, Some(tx_translate), limits.ocr),
+        stage_loop(app.clone(), job.clone(), Stage::Translate, rx_translate, None, limits.translate),
+    );
+
+    if job.state() == JobState::Cancelled {
+        job.remove_journal().await;
+    } else {
+        job.set_state(JobState::Completed);
+        // с ошибками журнал остаётся — упавшие страницы можно повторить
+        if job.progress(None).failed == 0 {
+            job.remove_journal().await;
+        } else {
+            job.flush_journal().await;
+        }
+    }
+    emit_progress(&app, &job, None);
+}
+
+// ---------- Команды ----------
+
+#[command]
+pub async fn start_batch_job(
+    app: AppHandle,
+    manager: State<'_, JobManager>,
+    pages: Vec<BatchPage>,
+    options: BatchOptions,
+) -> Result<String, String> {
+    if pages.is_empty() {
+        return Err("No pages to process".to_string());
+    }
+    let id = new_job_id();
+    let journal = journal_path(&app, options.project_id.as_deref(), &id);
+    let job = Arc::new(Job::new(id, pages, options, journal));
+    job.flush_journal().await;
+    manager.jobs.lock().unwrap().insert(job.id.clone(), job.clone());
+
+    emit_progress(&app, &job, None);
+    tauri::async_runtime::spawn(run_job(app, job.clone()));
+    Ok(job.id.clone())
+}
+
+// Незавершённые задачи из журналов (после падения или с упавшими страницами)
+#[command]
+pub async fn list_resumable_jobs(app: AppHandle, manager: State<'_, JobManager>) -> Result<ResumableJobs, String> {
+    let mut out = ResumableJobs::default();
+    let mut journals = Vec::new();
+    for (path, journal) in scan_journals(&app) {
+        match journal {
+            Ok(j) => journals.push((path, j)),
+            Err(error) => out.broken.push(BrokenJournal { path: path.display().to_string(), error }),
+        }
+    }
+    out.jobs = journals
+        .into_iter()
+        .filter(|(_, j)| {
+            // уже идущие в этом процессе не предлагаем
+            let active = manager
+                .jobs
+                .lock()
+                .unwrap()
+                .get(&j.job_id)
+                .is_some_and(|job| matches!(job.state(), JobState::Running | JobState::Paused));
+            !active && j.pages.iter().any(|p| p.status != PageStatus::Done)
+        })
+        .map(|(_, j)| ResumableJob {
+            project_id: j.options.project_id.clone().unwrap_or_else(|| "default".to_string()),
+            total: j.pages.len(),
+            done: j.pages.iter().filter(|p| p.status == PageStatus::Done).count(),
+            failed: j.pages.iter().filter(|p| p.status == PageStatus::Failed).count(),
+            updated_at: j.updated_at,
+            pages: j.pages.iter().map(|p| BatchPageInfo { path: p.path.clone(), name: p.name.clone() }).collect(),
+            job_id: j.job_id,
+        })
+        .collect();
+    out.jobs.sort_by_key(|j| std::cmp::Reverse(j.updated_at));
+    Ok(out)
+}
+
+#[command]
+pub async fn resume_batch_job(
+    app: AppHandle,
+    manager: State<'_, JobManager>,
+    job_id: String,
+    // ключ DeepLX в журнал не пишется
+    deeplx_api_key: Option<String>,
+) -> Result<String, String> {
+    if let Ok(job) = manager.get(&job_id) {
+        if matches!(job.state(), JobState::Running | JobState::Paused) {
+            return Err(format!("Job {} is already running", job_id));
+        }
+    }
+    let (path, mut journal) = read_journals(&app)
+        .into_iter()
+        .find(|(_, j)| j.job_id == job_id)
+        .ok_or_else(|| format!("No journal for job {}", job_id))?;
+    if let Some(step) = journal.options.deeplx.as_mut() {
+        step.api_key = deeplx_api_key.filter(|k| !k.is_empty());
+    }
+
+    let job = Arc::new(Job::from_journal(journal, path));
+    job.flush_journal().await;
+    manager.jobs.lock().unwrap().insert(job.id.clone(), job.clone());
+
+    emit_progress(&app, &job, None);
+    tauri::async_runtime::spawn(run_job(app, job.clone()));
+    Ok(job.id.clone())
+}
+
+// Пользователь отказался продолжать — журнал больше не нужен
+#[command]
+pub async fn discard_job(app: AppHandle, job_id: String) -> Result<(), String> {
+    for (path, journal) in read_journals(&app) {
+        if journal.job_id == job_id {
+            std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+pub async fn pause_job(app: AppHandle, manager: State<'_, JobManager>, job_id: String) -> Result<(), String> {
+    let job = manager.get(&job_id)?;
+    if job.state() == JobState::Running {
+        job.set_state(JobState::Paused);
+        emit_progress(&app, &job, None);
+    }
+    Ok(())
+}
+
+#[command]
+pub async fn resume_job(app: AppHandle, manager: State<'_, JobManager>, job_id: String) -> Result<(), String> {
+    let job = manager.get(&job_id)?;
+    if job.state() == JobState::Paused {
+        job.set_state(JobState::Running);
+        emit_progress(&app, &job, None);
+    }
+    Ok(())
+}
+
+#[command]
+pub async fn cancel_job(app: AppHandle, manager: State<'_, JobManager>, job_id: String) -> Result<(), String> {
+    let job = manager.get(&job_id)?;
+    if matches!(job.state(), JobState::Running | JobState::Paused) {
+        job.set_state(JobState::Cancelled);
+        emit_progress(&app, &job, None);
+    }
+    Ok(())
+}
+
+// После перезагрузки webview фронт забирает результаты отсюда
+#[command]
+pub async fn get_job_status(manager: State<'_, JobManager>, job_id: String) -> Result<JobStatus, String> {
+    Ok(manager.get(&job_id)?.status())
+}
+
+#[command]
+pub async fn list_jobs(manager: State<'_, JobManager>) -> Result<Vec<JobProgress>, String> {
+    let jobs = manager.jobs.lock().unwrap();
+    let mut list: Vec<JobProgress> = jobs.values().map(|j| j.progress(None)).collect();
+    list.sort_by(|a, b| a.job_id.cmp(&b.job_id));
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deeplx_key_is_not_journaled() {
+        // с фронта ключ приходит, в журнал не уходит
+        let step: DeeplxStep =
+            serde_json::from_value(serde_json::json!({ "url": "http://deeplx.lan", "apiKey": "secret", "targetLang": "RU" }))
+                .unwrap();
+        assert_eq!(step.api_key.as_deref(), Some("secret"));
+        let json = serde_json::to_string(&step).unwrap();
+        assert!(!json.contains("secret") && !json.contains("apiKey"), "{}", json);
+        let back: DeeplxStep = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.api_key, None);
+    }
+}
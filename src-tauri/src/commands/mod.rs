@@ -1,18 +1,77 @@
+pub mod backend;
+pub mod cache;
+pub mod downscale;
+pub mod endpoints;
 pub mod folder;
+pub mod formats;
 pub mod fonts;
+pub mod gutters;
+pub mod heuristic;
+pub mod http;
+pub mod jobs;
+pub mod normalize;
+pub mod pages;
 pub mod project;
+pub mod postprocess;
+pub mod ratelimit;
+pub mod reading_order;
+pub mod regions;
+pub mod stages;
+pub mod thumbs;
+pub mod tiling;
+pub mod transport;
+pub mod usage;
+pub mod vision;
+pub mod webtoon;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tauri::Emitter;
+use tauri::{Emitter, State};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ImageInfo {
     pub name: String,
     pub path: String,
+    // подпапка импорта ("Vol 01/Ch 003"), если страница из главы
+    pub chapter: Option<String>,
+    // исходный файл, если path — сконвертированная копия (commands/formats.rs)
+    pub original_path: Option<String>,
+    // поворот по EXIF / перевод в sRGB / конвертация при импорте (commands/normalize.rs)
+    pub normalization: Option<normalize::Normalization>,
     pub data_url: String,
     pub thumbnail: String,
+    // размеры оригинала (None — файл не прочитался)
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub x1: f64,
+    pub y1: f64,
+    pub x2: f64,
+    pub y2: f64,
+}
+
+impl BoundingBox {
+    pub fn scaled(&self, k: f64) -> BoundingBox {
+        BoundingBox { x1: self.x1 * k, y1: self.y1 * k, x2: self.x2 * k, y2: self.y2 * k }
+    }
+}
+
+// Кодирование страницы — тяжёлая CPU-работа, уводим с async-рантайма
+async fn with_vision(mut payload: Value, vision: Option<vision::VisionContext>) -> Result<Value, String> {
+    let Some(ctx) = vision else {
+        return Ok(payload);
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        vision::apply_vision_context(&mut payload, &ctx)?;
+        Ok(payload)
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
 pub async fn handle_response(response: reqwest::Response) -> Result<Value, String> {
@@ -26,59 +85,83 @@ pub async fn handle_response(response: reqwest::Response) -> Result<Value, Strin
 }
 
 #[tauri::command]
-pub async fn fetch_models(api_url: String) -> Result<Value, String> {
-    let client = reqwest::Client::new();
-    let response = client.get(&api_url).send().await.map_err(|e| e.to_string())?;
+pub async fn fetch_models(http: State<'_, http::HttpClient>, api_url: String) -> Result<Value, String> {
+    let response = http.get(&api_url).send().await.map_err(|e| e.to_string())?;
     handle_response(response).await
 }
 
 // ИЗМЕНЕНИЕ 4: Функция теперь принимает весь payload как `Value`
 #[tauri::command]
-pub async fn detect_text_areas(api_url: String, payload: Value) -> Result<Value, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/detect_text_areas", api_url.trim_end_matches('/'));
-    // Просто пересылаем полученный payload
-    let response = client.post(&url).json(&payload).send().await.map_err(|e| e.to_string())?;
-    handle_response(response).await
+pub async fn detect_text_areas(
+    pool: State<'_, endpoints::EndpointPool>,
+    http: State<'_, http::HttpClient>,
+    cache: State<'_, cache::ResultCache>,
+    api_url: String, payload: Value, max_side: Option<u32>, tiling: Option<tiling::TileOptions>) -> Result<Value, String> {
+    // Пересылаем payload (на один из узлов пула); большую страницу сначала уменьшаем
+    // или режем на тайлы, а боксы из ответа возвращаем в координаты оригинала
+    if heuristic::is_selected(&payload) {
+        return heuristic::detect_payload(&payload).await;
+    }
+    let prep = downscale::DetectPrep { max_side, tiling };
+    downscale::post_detect(&pool, &http, &cache, &api_url, "detect_text_areas", payload, &prep).await
 }
 
 #[tauri::command]
-pub async fn detect_panels(api_url: String, image_data: String) -> Result<Value, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/detect_panels", api_url.trim_end_matches('/'));
+pub async fn detect_panels(
+    pool: State<'_, endpoints::EndpointPool>,
+    http: State<'_, http::HttpClient>,
+    cache: State<'_, cache::ResultCache>,
+    api_url: String, image_data: String, max_side: Option<u32>, tiling: Option<tiling::TileOptions>) -> Result<Value, String> {
     let payload = serde_json::json!({ "image_data": image_data });
-    let response = client.post(&url).json(&payload).send().await.map_err(|e| e.to_string())?;
-    handle_response(response).await
+    let prep = downscale::DetectPrep { max_side, tiling };
+    downscale::post_detect(&pool, &http, &cache, &api_url, "detect_panels", payload, &prep).await
 }
 
 // ИЗМЕНЕНИЕ 5: Эта функция тоже теперь принимает весь payload
 #[tauri::command]
-pub async fn recognize_images_batch(api_url: String, payload: Value) -> Result<serde_json::Value, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/recognize_images_batch", api_url.trim_end_matches('/'));
+pub async fn recognize_images_batch(
+    pool: State<'_, endpoints::EndpointPool>,
+    http: State<'_, http::HttpClient>,
+    cache: State<'_, cache::ResultCache>,
+    api_url: String, payload: Value) -> Result<serde_json::Value, String> {
     // Просто пересылаем полученный payload
-    let response = client.post(&url).json(&payload).send().await.map_err(|e| e.to_string())?;
-    handle_response(response).await
+    cache::post_cached(&pool, &http, &cache, &api_url, "recognize_images_batch", &payload).await
 }
 
 #[tauri::command]
-pub async fn translate_text(api_url: String, payload: Value) -> Result<Value, String> {
-    if let Some(messages) = payload.get("messages").and_then(|m| m.as_array()) {
-        if let Some(last_message) = messages.last() {
-            if let Some(content) = last_message.get("content").and_then(|c| c.as_str()) {
-                println!("\n--- Sending to LLM ---\n{}\n----------------------", content);
-            }
-        }
-    }
+pub async fn translate_text(
+    ledger: State<'_, usage::UsageLedger>,
+    http: State<'_, http::HttpClient>,
+    api_url: String,
+    payload: Value,
+    vision: Option<vision::VisionContext>,
+    usage: Option<usage::UsageContext>,
+) -> Result<Value, String> {
+    let payload = with_vision(payload, vision).await?;
 
-    let client = reqwest::Client::new();
-    let response = client.post(&api_url).json(&payload).send().await.map_err(|e| e.to_string())?;
+    chat_completion(&http, &ledger, &api_url, &payload, usage.as_ref()).await
+}
+
+// Нестриминговый запрос к OpenAI-совместимому /v1/chat/completions с учётом токенов
+pub async fn chat_completion(
+    http: &http::HttpClient,
+    ledger: &usage::UsageLedger,
+    api_url: &str,
+    payload: &Value,
+    usage: Option<&usage::UsageContext>,
+) -> Result<Value, String> {
+    let mut permit = http.limit(api_url, ratelimit::estimate_tokens(payload)).await;
+    let response = http.post(api_url).json(payload).send().await.map_err(|e| e.to_string())?;
 
     let status = response.status();
     if status.is_success() {
         let body_text = response.text().await.map_err(|e| format!("Failed to read response body: {}", e))?;
-        println!("\n--- Received from LLM ---\n{}\n-------------------------", body_text);
         let json_value: Value = serde_json::from_str(&body_text).map_err(|e| format!("Failed to parse JSON from response: {}", e))?;
+        let model = payload.get("model").and_then(|m| m.as_str()).unwrap_or_default();
+        if let Some((prompt, completion)) = usage::parse_usage(&json_value) {
+            permit.settle(prompt + completion);
+        }
+        ledger.record_response(usage, model, &json_value);
         Ok(json_value)
     } else {
         let error_body = response.text().await.map_err(|e| format!("Failed to read error body: {}", e))?;
@@ -87,17 +170,21 @@ pub async fn translate_text(api_url: String, payload: Value) -> Result<Value, St
 }
 
 #[tauri::command]
-pub async fn translate_text_stream(window: tauri::Window, api_url: String, payload: Value, stream_id: String) -> Result<(), String> {
-    if let Some(messages) = payload.get("messages").and_then(|m| m.as_array()) {
-        if let Some(last_message) = messages.last() {
-            if let Some(content) = last_message.get("content").and_then(|c| c.as_str()) {
-                println!("\n--- Sending to LLM (Stream) ---\n{}\n-----------------------------", content);
-            }
-        }
-    }
+#[allow(clippy::too_many_arguments)]
+pub async fn translate_text_stream(
+    window: tauri::Window,
+    api_url: String,
+    payload: Value,
+    ledger: State<'_, usage::UsageLedger>,
+    http: State<'_, http::HttpClient>,
+    stream_id: String,
+    vision: Option<vision::VisionContext>,
+    usage: Option<usage::UsageContext>,
+) -> Result<(), String> {
+    let payload = with_stream_usage(with_vision(payload, vision).await?);
 
-    let client = reqwest::Client::new();
-    let mut response = client.post(&api_url).json(&payload).send().await.map_err(|e| e.to_string())?;
+    let mut permit = http.limit(&api_url, ratelimit::estimate_tokens(&payload)).await;
+    let mut response = http.post(&api_url).json(&payload).send().await.map_err(|e| e.to_string())?;
 
     if !response.status().is_success() {
         let status = response.status();
@@ -105,180 +192,188 @@ pub async fn translate_text_stream(window: tauri::Window, api_url: String, paylo
         return Err(format!("API Error: Status {}, Body: {}", status, text));
     }
 
-    while let Ok(Some(chunk)) = response.chunk().await {
-        let s = String::from_utf8_lossy(&chunk);
-        for line in s.split('\n') {
-            let line = line.trim();
-            if line.is_empty() { continue; }
-            if line.starts_with("data: ") {
-                let data = &line[6..];
-                if data == "[DONE]" {
-                    let _ = window.emit("llm-stream", serde_json::json!({ "id": stream_id, "done": true }));
-                    continue;
-                }
-                if let Ok(val) = serde_json::from_str::<Value>(data) {
-                    if let Some(choices) = val.get("choices").and_then(|v| v.as_array()) {
-                        for c in choices {
-                            if let Some(delta) = c.get("delta").and_then(|d| d.get("content")) {
-                                let _ = window.emit("llm-stream", serde_json::json!({ "id": stream_id, "delta": delta, "done": false }));
-                            }
-                        }
+    // usage приходит отдельным чанком (обычно последним)
+    let mut last_usage: Option<Value> = None;
+    let mut lines = SseLines::default();
+    loop {
+        let (batch, end) = match response.chunk().await {
+            Ok(Some(chunk)) => (lines.push(&chunk), false),
+            _ => (lines.finish(), true),
+        };
+        for data in batch.iter().filter_map(|l| sse_data(l)) {
+            if data == "[DONE]" {
+                let _ = window.emit("llm-stream", serde_json::json!({ "id": stream_id, "done": true }));
+                continue;
+            }
+            let Ok(val) = serde_json::from_str::<Value>(data) else {
+                continue;
+            };
+            if usage::parse_usage(&val).is_some() {
+                last_usage = Some(val.clone());
+            }
+            if let Some(choices) = val.get("choices").and_then(|v| v.as_array()) {
+                for c in choices {
+                    if let Some(delta) = c.get("delta").and_then(|d| d.get("content")) {
+                        let _ = window.emit("llm-stream", serde_json::json!({ "id": stream_id, "delta": delta, "done": false }));
                     }
                 }
             }
         }
+        if end {
+            break;
+        }
+    }
+    if let Some(val) = last_usage {
+        if let Some((prompt, completion)) = usage::parse_usage(&val) {
+            permit.settle(prompt + completion);
+        }
+        let model = payload.get("model").and_then(|m| m.as_str()).unwrap_or_default();
+        ledger.record_response(usage.as_ref(), model, &val);
     }
     Ok(())
 }
 
+// Без stream_options.include_usage OpenAI-совместимые серверы не присылают usage в потоке
+fn with_stream_usage(mut payload: Value) -> Value {
+    if payload.get("stream").and_then(Value::as_bool) != Some(true) {
+        return payload;
+    }
+    if let Some(obj) = payload.as_object_mut() {
+        let options = obj.entry("stream_options").or_insert_with(|| serde_json::json!({}));
+        if let Some(options) = options.as_object_mut() {
+            options.insert("include_usage".to_string(), Value::Bool(true));
+        }
+    }
+    payload
+}
+
+// Строки SSE из чанков ответа: строка (и символ UTF-8) может разойтись по двум чанкам
+#[derive(Default)]
+struct SseLines {
+    buf: Vec<u8>,
+}
+
+impl SseLines {
+    // Полные строки; хвост без перевода строки ждёт следующего чанка
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(chunk);
+        let Some(end) = self.buf.iter().rposition(|&b| b == b'\n') else {
+            return Vec::new();
+        };
+        let rest = self.buf.split_off(end + 1);
+        let done = std::mem::replace(&mut self.buf, rest);
+        String::from_utf8_lossy(&done).lines().map(str::to_string).collect()
+    }
+
+    // Конец потока: последняя строка могла прийти без перевода строки
+    fn finish(&mut self) -> Vec<String> {
+        let rest = std::mem::take(&mut self.buf);
+        String::from_utf8_lossy(&rest).lines().map(str::to_string).collect()
+    }
+}
+
+fn sse_data(line: &str) -> Option<&str> {
+    line.trim().strip_prefix("data:").map(str::trim_start)
+}
+
 #[tauri::command]
 pub async fn translate_deeplx(
+    http: State<'_, http::HttpClient>,
     api_url: String,
     api_key: Option<String>,
     texts: Vec<String>,
     target_lang: String,
     source_lang: Option<String>
 ) -> Result<Value, String> {
-    let client = reqwest::Client::new();
-    let text_payload = texts.join("\n");
-    
-    println!("\n--- DeepLX Translation Request ---");
-    println!("API URL: {}", api_url);
-    println!("Target Lang: {}", target_lang);
-    println!("Source Lang: {:?}", source_lang);
-    println!("Text payload (first 200 chars): {}", 
-        if text_payload.len() > 200 { 
-            format!("{}...", &text_payload[..200])
-        } else {
-            text_payload.clone()
-        }
-    );
+    deeplx_request(&http, &api_url, api_key.as_deref(), &texts, &target_lang, source_lang.as_deref()).await
+}
 
-    let mut payload = serde_json::json!({
+// Запрос к DeepLX; ответ приводится к виду { "code": 200, "data": "..." }
+pub async fn deeplx_request(
+    http: &http::HttpClient,
+    api_url: &str,
+    api_key: Option<&str>,
+    texts: &[String],
+    target_lang: &str,
+    source_lang: Option<&str>,
+) -> Result<Value, String> {
+    let text_payload = texts.join("\n");
+    let payload = serde_json::json!({
         "text": text_payload,
-        "target_lang": target_lang
+        "target_lang": target_lang,
+        "source_lang": source_lang.unwrap_or("auto"),
     });
-    if let Some(lang) = source_lang {
-        payload["source_lang"] = serde_json::json!(lang);
-    } else {
-        payload["source_lang"] = serde_json::json!("auto");
-    }
 
-    let mut builder = client.post(&api_url).json(&payload);
-    if let Some(key) = &api_key {
-        if !key.is_empty() {
-            builder = builder.bearer_auth(key);
-            println!("Using API key: {}***", &key[..std::cmp::min(4, key.len())]);
-        }
-    }
+    let _permit = http.limit(api_url, ratelimit::estimate_tokens(&payload)).await;
+    // Явный ключ DeepLX важнее общих настроек авторизации эндпоинта
+    let response = http.post_with_key(api_url, api_key).json(&payload).send().await.map_err(|e| e.to_string())?;
 
-    let response = builder.send().await.map_err(|e| {
-        println!("DeepLX Request failed: {}", e);
-        e.to_string()
-    })?;
-    
     let status = response.status();
-    println!("DeepLX Response status: {}", status);
-    
-    if status.is_success() {
-        let response_text = response.text().await.map_err(|e| {
-            println!("Failed to read DeepLX response text: {}", e);
-            e.to_string()
-        })?;
-        
-        println!("DeepLX Response body: {}", response_text);
-        
-        // Try to parse as JSON first
-        match serde_json::from_str::<Value>(&response_text) {
-            Ok(json) => {
-                // Check if it's already in the expected format
-                if json.get("code").is_some() && json.get("data").is_some() {
-                    println!("DeepLX returned expected format");
-                    return Ok(json);
-                }
-                
-                // If it's just the translation text or different format, wrap it
-                if let Some(text) = json.as_str() {
-                    println!("DeepLX returned plain text, wrapping");
-                    return Ok(serde_json::json!({
-                        "code": 200,
-                        "data": text
-                    }));
-                }
-                
-                // Try to extract text from various possible formats
-                if let Some(text) = json.get("data").and_then(|v| v.as_str()) {
-                    return Ok(serde_json::json!({
-                        "code": 200,
-                        "data": text
-                    }));
-                }
-                
-                if let Some(text) = json.get("text").and_then(|v| v.as_str()) {
-                    return Ok(serde_json::json!({
-                        "code": 200,
-                        "data": text
-                    }));
-                }
-                
-                // If none of the above, treat whole response as translation
-                println!("DeepLX returned unknown JSON format, treating as translation");
-                return Ok(serde_json::json!({
-                    "code": 200,
-                    "data": response_text
-                }));
-            },
-            Err(_) => {
-                // If it's not JSON, treat as plain text translation
-                println!("DeepLX returned non-JSON response, treating as plain text");
-                return Ok(serde_json::json!({
-                    "code": 200,
-                    "data": response_text
-                }));
-            }
-        }
-    } else {
-        let error_body = response.text().await.unwrap_or_default();
-        let error_msg = format!("DeepLX API Error: Status {}, Body: {}", status, error_body);
-        println!("{}", error_msg);
-        Err(error_msg)
+    let response_text = response.text().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!("DeepLX API Error: Status {}, Body: {}", status, response_text));
+    }
+    Ok(deeplx_result(&response_text))
+}
+
+// Разные сборки DeepLX отвечают по-разному: { code, data }, { data }, { text }, строка JSON или просто текст
+fn deeplx_result(response_text: &str) -> Value {
+    let json = serde_json::from_str::<Value>(response_text).ok();
+    if let Some(json) = json.as_ref().filter(|j| j.get("code").is_some() && j.get("data").is_some()) {
+        return json.clone();
     }
+    let text = json
+        .as_ref()
+        .and_then(|j| j.as_str().or_else(|| j.get("data")?.as_str()).or_else(|| j.get("text")?.as_str()))
+        .unwrap_or(response_text);
+    serde_json::json!({ "code": 200, "data": text })
 }
 
 #[tauri::command]
-pub async fn inpaint_image(api_url: String, image_data: String, mask_data: String) -> Result<Value, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/inpaint", api_url.trim_end_matches('/'));
+pub async fn inpaint_image(
+    pool: State<'_, endpoints::EndpointPool>,
+    http: State<'_, http::HttpClient>,
+    cache: State<'_, cache::ResultCache>,
+    api_url: String, image_data: String, mask_data: String) -> Result<Value, String> {
     let payload = serde_json::json!({ "image_data": image_data, "mask_data": mask_data });
-    let response = client.post(&url).json(&payload).send().await.map_err(|e| e.to_string())?;
-    handle_response(response).await
+    cache::post_cached(&pool, &http, &cache, &api_url, "inpaint", &payload).await
 }
 
 #[tauri::command]
-pub async fn inpaint_text_auto(api_url: String, image_data: String, boxes: Option<Vec<Vec<i32>>>, dilate: Option<i32>) -> Result<Value, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/inpaint_auto_text", api_url.trim_end_matches('/'));
+pub async fn inpaint_text_auto(
+    pool: State<'_, endpoints::EndpointPool>,
+    http: State<'_, http::HttpClient>,
+    cache: State<'_, cache::ResultCache>,
+    api_url: String,
+    image_data: String,
+    boxes: Option<Vec<Vec<i32>>>,
+    dilate: Option<i32>,
+) -> Result<Value, String> {
     let payload = serde_json::json!({ "image_data": image_data, "boxes": boxes, "dilate": dilate.unwrap_or(2) });
-    let response = client.post(&url).json(&payload).send().await.map_err(|e| e.to_string())?;
-    handle_response(response).await
+    cache::post_cached(&pool, &http, &cache, &api_url, "inpaint_auto_text", &payload).await
 }
 
 #[tauri::command]
-pub async fn inpaint_lama(api_url: String, image_data: String, mask_data: String, model: Option<String>) -> Result<Value, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/inpaint_lama", api_url.trim_end_matches('/'));
+pub async fn inpaint_lama(
+    pool: State<'_, endpoints::EndpointPool>,
+    http: State<'_, http::HttpClient>,
+    cache: State<'_, cache::ResultCache>,
+    api_url: String,
+    image_data: String,
+    mask_data: String,
+    model: Option<String>,
+) -> Result<Value, String> {
     let payload = serde_json::json!({
         "image_data": image_data,
         "mask_data": mask_data,
         "model": model.unwrap_or_else(|| "lama_large_512px".to_string())
     });
-    let response = client.post(&url).json(&payload).send().await.map_err(|e| e.to_string())?;
-    handle_response(response).await
+    cache::post_cached(&pool, &http, &cache, &api_url, "inpaint_lama", &payload).await
 }
 
 #[tauri::command]
-pub async fn fetch_image(url: String) -> Result<String, String> {
-    let resp = reqwest::get(&url).await.map_err(|e| e.to_string())?;
+pub async fn fetch_image(http: State<'_, http::HttpClient>, url: String) -> Result<String, String> {
+    let resp = http.get(&url).send().await.map_err(|e| e.to_string())?;
     if !resp.status().is_success() {
         return Err(format!("HTTP {}", resp.status()));
     }
@@ -294,14 +389,68 @@ pub async fn read_file_b64(path: String) -> Result<String, String> {
 }
 
 #[tauri::command]
-pub async fn inpaint_manual_mask(api_url: String, image_data: String, mask_data: String, model: Option<String>) -> Result<Value, String> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/inpaint_manual", api_url.trim_end_matches('/'));
+pub async fn inpaint_manual_mask(
+    pool: State<'_, endpoints::EndpointPool>,
+    http: State<'_, http::HttpClient>,
+    cache: State<'_, cache::ResultCache>,
+    api_url: String,
+    image_data: String,
+    mask_data: String,
+    model: Option<String>,
+) -> Result<Value, String> {
     let payload = serde_json::json!({
         "image_data": image_data,
         "mask_data": mask_data,
         "model": model.unwrap_or_else(|| "lama_large_512px".to_string())
     });
-    let response = client.post(&url).json(&payload).send().await.map_err(|e| e.to_string())?;
-    handle_response(response).await
-}
\ No newline at end of file
+    cache::post_cached(&pool, &http, &cache, &api_url, "inpaint_manual", &payload).await
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn stream_requests_ask_for_usage() {
+        let payload = with_stream_usage(json!({ "model": "m", "stream": true }));
+        assert_eq!(payload["stream_options"], json!({ "include_usage": true }));
+        // свои stream_options не затираем
+        let payload = with_stream_usage(json!({ "stream": true, "stream_options": { "foo": 1 } }));
+        assert_eq!(payload["stream_options"], json!({ "foo": 1, "include_usage": true }));
+        let payload = with_stream_usage(json!({ "stream": false }));
+        assert!(payload.get("stream_options").is_none());
+    }
+
+    #[test]
+    fn sse_line_split_across_chunks() {
+        let mut lines = SseLines::default();
+        assert!(lines.push(b"data: {\"choices\":[{\"del").is_empty());
+        assert_eq!(lines.push(b"ta\":{}}]}\r\ndata: [DO"), vec!["data: {\"choices\":[{\"delta\":{}}]}"]);
+        assert_eq!(lines.push(b"NE]\n\n"), vec!["data: [DONE]", ""]);
+        assert!(lines.finish().is_empty());
+    }
+
+    #[test]
+    fn sse_utf8_split_across_chunks() {
+        let text = "data: привет\n".as_bytes();
+        let mut lines = SseLines::default();
+        // разрез посреди двухбайтового символа
+        assert!(lines.push(&text[..8]).is_empty());
+        assert_eq!(lines.push(&text[8..]), vec!["data: привет"]);
+    }
+
+    #[test]
+    fn sse_last_line_without_newline() {
+        let mut lines = SseLines::default();
+        assert!(lines.push(b"data: {\"usage\":{}}").is_empty());
+        assert_eq!(lines.finish(), vec!["data: {\"usage\":{}}"]);
+    }
+
+    #[test]
+    fn sse_data_prefix() {
+        assert_eq!(sse_data("data: [DONE]"), Some("[DONE]"));
+        assert_eq!(sse_data("data:{}"), Some("{}"));
+        assert_eq!(sse_data(": keep-alive"), None);
+        assert_eq!(sse_data("event: message"), None);
+    }
+}
@@ -0,0 +1,305 @@
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+use crate::commands::formats::Format;
+
+// Что сделано со страницей при импорте; хранится в проекте, чтобы боксы, маски и экспорт
+// жили в одних пикселях — повернутых по EXIF и в sRGB
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Normalization {
+    // формат исходника, если он сконвертирован (TIFF, AVIF, HEIC)
+    pub converted_from: Option<Format>,
+    // применённая EXIF-ориентация (1 — без поворота)
+    pub orientation: u16,
+    // имя ICC-профиля исходника, если цвета переведены в sRGB
+    pub icc_profile: Option<String>,
+}
+
+impl Normalization {
+    pub fn is_noop(&self) -> bool {
+        self.converted_from.is_none() && self.orientation <= 1 && self.icc_profile.is_none()
+    }
+}
+
+// EXIF Orientation (1..8); нет EXIF — 1
+pub fn exif_orientation(bytes: &[u8]) -> u16 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|e| e.get_field(exif::Tag::Orientation, exif::In::PRIMARY).and_then(|f| f.value.get_uint(0)))
+        .filter(|o| (1..=8).contains(o))
+        .map(|o| o as u16)
+        .unwrap_or(1)
+}
+
+pub fn orient(img: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+// JPEG: профиль порезан на куски по APP2-сегментам "ICC_PROFILE\0"
+fn jpeg_icc(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut chunks: Vec<(u8, &[u8])> = Vec::new();
+    let mut i = 2;
+    loop {
+        // перед маркером может быть сколько угодно байтов-заполнителей 0xFF
+        while bytes.get(i) == Some(&0xFF) && bytes.get(i + 1) == Some(&0xFF) {
+            i += 1;
+        }
+        if i + 4 > bytes.len() || bytes[i] != 0xFF {
+            break;
+        }
+        let marker = bytes[i + 1];
+        // SOS — дальше данные изображения, метаданных не будет
+        if marker == 0xDA {
+            break;
+        }
+        let len = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+        let seg = bytes.get(i + 4..i + 2 + len)?;
+        if marker == 0xE2 && seg.len() > 14 && seg.starts_with(b"ICC_PROFILE\0") {
+            chunks.push((seg[12], &seg[14..]));
+        }
+        i += 2 + len;
+    }
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by_key(|(n, _)| *n);
+    Some(chunks.into_iter().flat_map(|(_, c)| c.iter().copied()).collect())
+}
+
+// WebP: чанк ICCP в RIFF-контейнере
+fn webp_icc(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut i = 12;
+    while i + 8 <= bytes.len() {
+        let len = u32::from_le_bytes([bytes[i + 4], bytes[i + 5], bytes[i + 6], bytes[i + 7]]) as usize;
+        if &bytes[i..i + 4] == b"ICCP" {
+            return bytes.get(i + 8..i + 8 + len).map(|c| c.to_vec());
+        }
+        i += 8 + len + (len & 1);
+    }
+    None
+}
+
+pub fn icc_profile(bytes: &[u8], format: Format) -> Option<Vec<u8>> {
+    use image::ImageDecoder;
+    match format {
+        Format::Jpeg => jpeg_icc(bytes),
+        Format::Webp => webp_icc(bytes),
+        Format::Png => image::codecs::png::PngDecoder::new(Cursor::new(bytes)).ok()?.icc_profile(),
+        Format::Tiff => image::codecs::tiff::TiffDecoder::new(Cursor::new(bytes)).ok()?.icc_profile(),
+        _ => None,
+    }
+}
+
+// Имя профиля из тега desc (ICC v2 — ASCII, v4 — mluc в UTF-16BE)
+pub fn profile_name(icc: &[u8]) -> Option<String> {
+    let be32 = |at: usize| icc.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let count = be32(128)?;
+    let (offset, size) = (0..count.min(256)).find_map(|k| {
+        let entry = 132 + k * 12;
+        (icc.get(entry..entry + 4)? == b"desc").then(|| Some((be32(entry + 4)?, be32(entry + 8)?)))?
+    })?;
+    let tag = icc.get(offset..offset + size)?;
+    match tag.get(..4)? {
+        b"desc" => {
+            let len = u32::from_be_bytes(tag.get(8..12)?.try_into().ok()?) as usize;
+            let text = tag.get(12..12 + len)?;
+            Some(String::from_utf8_lossy(text).trim_end_matches('\0').to_string())
+        }
+        b"mluc" => {
+            let len = u32::from_be_bytes(tag.get(20..24)?.try_into().ok()?) as usize;
+            let at = u32::from_be_bytes(tag.get(24..28)?.try_into().ok()?) as usize;
+            let units: Vec<u16> = tag.get(at..at + len)?.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            Some(String::from_utf16_lossy(&units).trim_end_matches('\0').to_string())
+        }
+        _ => None,
+    }
+}
+
+// Переводить ли в sRGB: sRGB-профили ("sRGB IEC61966-2.1" и т.п.) не трогаем — перекодировать
+// страницу незачем; CMYK и профили, которые qcms не читает, оставляем как есть
+pub fn needs_srgb(icc: &[u8]) -> bool {
+    let rgb = icc.get(16..20) == Some(b"RGB ".as_slice());
+    let srgb = profile_name(icc).map(|n| n.contains("sRGB")).unwrap_or(false);
+    rgb && !srgb && qcms::Profile::new_from_slice(icc, false).is_some()
+}
+
+pub fn to_srgb(img: &DynamicImage, icc: &[u8]) -> Result<DynamicImage, String> {
+    let src = qcms::Profile::new_from_slice(icc, false).ok_or("unsupported ICC profile")?;
+    let mut dst = qcms::Profile::new_sRGB();
+    dst.precache_output_transform();
+    let ty = if img.color().has_alpha() { qcms::DataType::RGBA8 } else { qcms::DataType::RGB8 };
+    let transform = qcms::Transform::new(&src, &dst, ty, qcms::Intent::Perceptual)
+        .ok_or("ICC profile cannot be converted to sRGB")?;
+    Ok(if img.color().has_alpha() {
+        let mut rgba = img.to_rgba8();
+        transform.apply(&mut rgba);
+        DynamicImage::ImageRgba8(rgba)
+    } else {
+        let mut rgb = img.to_rgb8();
+        transform.apply(&mut rgb);
+        DynamicImage::ImageRgb8(rgb)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn app2(seq: u8, total: u8, data: &[u8]) -> Vec<u8> {
+        let mut seg = vec![0xFF, 0xE2];
+        seg.extend_from_slice(&((2 + 14 + data.len()) as u16).to_be_bytes());
+        seg.extend_from_slice(b"ICC_PROFILE\0");
+        seg.extend_from_slice(&[seq, total]);
+        seg.extend_from_slice(data);
+        seg
+    }
+
+    fn jpeg(segments: &[Vec<u8>]) -> Vec<u8> {
+        let mut b = vec![0xFF, 0xD8];
+        // APP0 JFIF
+        b.extend_from_slice(&[0xFF, 0xE0, 0, 7, b'J', b'F', b'I', b'F', 0]);
+        for s in segments {
+            b.extend_from_slice(s);
+        }
+        b.extend_from_slice(&[0xFF, 0xDA, 0, 2]);
+        b
+    }
+
+    #[test]
+    fn jpeg_icc_reassembles_chunks_in_order() {
+        let bytes = jpeg(&[app2(2, 3, b"def"), app2(1, 3, b"abc"), app2(3, 3, b"gh")]);
+        assert_eq!(jpeg_icc(&bytes).as_deref(), Some(b"abcdefgh".as_slice()));
+    }
+
+    #[test]
+    fn jpeg_icc_skips_fill_bytes() {
+        let mut second = vec![0xFF, 0xFF, 0xFF];
+        second.extend(app2(2, 2, b"def"));
+        let bytes = jpeg(&[app2(1, 2, b"abc"), second]);
+        assert_eq!(jpeg_icc(&bytes).as_deref(), Some(b"abcdef".as_slice()));
+    }
+
+    #[test]
+    fn jpeg_icc_ignores_other_segments_and_scan_data() {
+        assert_eq!(jpeg_icc(&jpeg(&[])), None);
+        // ICC после SOS — уже не метаданные
+        let mut bytes = jpeg(&[]);
+        bytes.extend(app2(1, 1, b"abc"));
+        assert_eq!(jpeg_icc(&bytes), None);
+        // обрезанный сегмент
+        let mut cut = jpeg(&[]);
+        cut.truncate(6);
+        assert_eq!(jpeg_icc(&cut), None);
+    }
+
+    fn riff_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut c = id.to_vec();
+        c.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        c.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            c.push(0);
+        }
+        c
+    }
+
+    fn webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut b = b"RIFF".to_vec();
+        b.extend_from_slice(&((4 + body.len()) as u32).to_le_bytes());
+        b.extend_from_slice(b"WEBP");
+        b.extend(body);
+        b
+    }
+
+    #[test]
+    fn webp_icc_finds_iccp_after_padded_chunk() {
+        let bytes = webp(&[riff_chunk(b"VP8X", &[0x20; 10]), riff_chunk(b"EXIF", b"odd"), riff_chunk(b"ICCP", b"profile")]);
+        assert_eq!(webp_icc(&bytes).as_deref(), Some(b"profile".as_slice()));
+        assert_eq!(webp_icc(&webp(&[riff_chunk(b"VP8 ", &[0; 6])])), None);
+    }
+
+    // профиль с одним тегом desc
+    fn icc_with_desc(tag: &[u8]) -> Vec<u8> {
+        let mut icc = vec![0u8; 128];
+        icc[16..20].copy_from_slice(b"RGB ");
+        icc.extend_from_slice(&1u32.to_be_bytes());
+        icc.extend_from_slice(b"desc");
+        icc.extend_from_slice(&144u32.to_be_bytes());
+        icc.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        icc.extend_from_slice(tag);
+        icc
+    }
+
+    #[test]
+    fn profile_name_v2_desc() {
+        let name = b"Adobe RGB (1998)\0";
+        let mut tag = b"desc\0\0\0\0".to_vec();
+        tag.extend_from_slice(&(name.len() as u32).to_be_bytes());
+        tag.extend_from_slice(name);
+        assert_eq!(profile_name(&icc_with_desc(&tag)).as_deref(), Some("Adobe RGB (1998)"));
+    }
+
+    #[test]
+    fn profile_name_v4_mluc() {
+        let text: Vec<u8> = "Display P3".encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        let mut tag = b"mluc\0\0\0\0".to_vec();
+        tag.extend_from_slice(&1u32.to_be_bytes());
+        tag.extend_from_slice(&12u32.to_be_bytes());
+        tag.extend_from_slice(b"enUS");
+        tag.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        tag.extend_from_slice(&28u32.to_be_bytes());
+        tag.extend_from_slice(&text);
+        assert_eq!(profile_name(&icc_with_desc(&tag)).as_deref(), Some("Display P3"));
+    }
+
+    #[test]
+    fn profile_name_missing_or_broken() {
+        assert_eq!(profile_name(&[0u8; 64]), None);
+        assert_eq!(profile_name(&icc_with_desc(b"text\0\0\0\0")), None);
+        // смещение тега за концом профиля
+        let mut icc = icc_with_desc(b"desc");
+        icc[136..140].copy_from_slice(&4096u32.to_be_bytes());
+        assert_eq!(profile_name(&icc), None);
+    }
+
+    #[test]
+    fn orient_all_exif_orientations() {
+        let (w, h) = (3u32, 2u32);
+        // пиксель помнит свои исходные координаты
+        let src = DynamicImage::ImageRgb8(RgbImage::from_fn(w, h, |x, y| Rgb([x as u8, y as u8, 0])));
+        // для каждой ориентации: откуда в исходнике берётся пиксель (x, y) результата
+        type From = fn(u32, u32) -> (u32, u32);
+        let cases: [(u16, From); 8] = [
+            (1, |x, y| (x, y)),
+            (2, |x, y| (2 - x, y)),
+            (3, |x, y| (2 - x, 1 - y)),
+            (4, |x, y| (x, 1 - y)),
+            (5, |x, y| (y, x)),
+            (6, |x, y| (y, 1 - x)),
+            (7, |x, y| (2 - y, 1 - x)),
+            (8, |x, y| (2 - y, x)),
+        ];
+        for (orientation, from) in cases {
+            let out = orient(src.clone(), orientation).to_rgb8();
+            let dims = if orientation >= 5 { (h, w) } else { (w, h) };
+            assert_eq!(out.dimensions(), dims, "orientation {}", orientation);
+            for (x, y, p) in out.enumerate_pixels() {
+                let (sx, sy) = from(x, y);
+                assert_eq!((p[0] as u32, p[1] as u32), (sx, sy), "orientation {} at ({}, {})", orientation, x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::{ImageFormat, Rgba, RgbaImage};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::commands::{thumbs, BoundingBox};
+
+// Страницы открытых проектов для протокола mtimg://<project>/<page path>/<layer>.
+// Протокол отдаёт только то, что зарегистрировано здесь, — произвольные файлы с диска не читаются.
+
+pub const SCHEME: &str = "mtimg";
+
+#[derive(Default)]
+struct Page {
+    boxes: Vec<BoundingBox>,
+    // слои, которых нет на диске (маска, результат), — PNG/JPEG как прислал фронт
+    mask: Option<Vec<u8>>,
+    final_image: Option<Vec<u8>>,
+}
+
+#[derive(Default)]
+pub struct PageRegistry {
+    // project id -> (путь страницы -> страница)
+    projects: Mutex<HashMap<String, HashMap<String, Page>>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageRef {
+    pub path: String,
+    #[serde(default)]
+    pub boxes: Vec<BoundingBox>,
+}
+
+// Список страниц проекта целиком; слои страниц, оставшихся в списке, сохраняются
+#[command]
+pub fn register_pages(registry: State<'_, PageRegistry>, project_id: String, pages: Vec<PageRef>) {
+    let mut projects = registry.projects.lock().unwrap();
+    let mut old = projects.remove(&project_id).unwrap_or_default();
+    let next = pages
+        .into_iter()
+        .map(|p| {
+            let mut page = old.remove(&p.path).unwrap_or_default();
+            page.boxes = p.boxes;
+            (p.path, page)
+        })
+        .collect();
+    projects.insert(project_id, next);
+}
+
+#[command]
+pub fn close_project_pages(registry: State<'_, PageRegistry>, project_id: String) {
+    registry.projects.lock().unwrap().remove(&project_id);
+}
+
+// Маска или готовая страница из webview (data URL); None — убрать слой
+#[command]
+pub fn set_page_layer(
+    registry: State<'_, PageRegistry>,
+    project_id: String,
+    path: String,
+    layer: String,
+    data_url: Option<String>,
+) -> Result<(), String> {
+    let bytes = data_url
+        .filter(|d| !d.is_empty())
+        .map(|d| {
+            let b64 = d.split_once(',').map(|(_, b)| b.to_string()).unwrap_or(d);
+            STANDARD.decode(b64).map_err(|e| e.to_string())
+        })
+        .transpose()?;
+    let mut projects = registry.projects.lock().unwrap();
+    let page = projects
+        .get_mut(&project_id)
+        .and_then(|p| p.get_mut(&path))
+        .ok_or("Page is not registered")?;
+    match layer.as_str() {
+        "mask" => page.mask = bytes,
+        "final" => page.final_image = bytes,
+        other => return Err(format!("Unknown layer: {}", other)),
+    }
+    Ok(())
+}
+
+fn mime(bytes: &[u8]) -> &'static str {
+    match image::guess_format(bytes) {
+        Ok(ImageFormat::Png) => "image/png",
+        Ok(ImageFormat::Jpeg) => "image/jpeg",
+        Ok(ImageFormat::WebP) => "image/webp",
+        Ok(ImageFormat::Gif) => "image/gif",
+        Ok(ImageFormat::Bmp) => "image/bmp",
+        Ok(ImageFormat::Tiff) => "image/tiff",
+        Ok(ImageFormat::Avif) => "image/avif",
+        _ => "application/octet-stream",
+    }
+}
+
+fn etag_of(bytes: &[u8]) -> String {
+    let digest = format!("{:x}", Sha256::digest(bytes));
+    format!("\"{}\"", &digest[..16])
+}
+
+fn file_etag(path: &Path) -> Option<String> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    Some(format!("\"{:x}-{:x}\"", mtime, meta.len()))
+}
+
+// Маска "на лету" из боксов: непрозрачный белый по баблам на прозрачном фоне
+fn render_mask(path: &Path, boxes: &[BoundingBox]) -> Result<Vec<u8>, String> {
+    let (w, h) = image::image_dimensions(path).map_err(|e| e.to_string())?;
+    let mut mask = RgbaImage::new(w, h);
+    for b in boxes {
+        let (x1, y1) = (b.x1.max(0.0) as u32, b.y1.max(0.0) as u32);
+        let (x2, y2) = ((b.x2.max(0.0) as u32).min(w), (b.y2.max(0.0) as u32).min(h));
+        for y in y1..y2 {
+            for x in x1..x2 {
+                mask.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+            }
+        }
+    }
+    let mut buf = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgba8(mask)
+        .write_to(&mut buf, image::ImageOutputFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(buf.into_inner())
+}
+
+fn status(code: StatusCode, msg: &str) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(code)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(msg.as_bytes().to_vec())
+        .unwrap()
+}
+
+// bytes=a-b | bytes=a- | bytes=-n  →  [start, end)
+fn parse_range(value: &str, len: usize) -> Option<(usize, usize)> {
+    let spec = value.strip_prefix("bytes=")?.split(',').next()?.trim();
+    let (a, b) = spec.split_once('-')?;
+    let (start, end) = match (a.parse::<usize>().ok(), b.parse::<usize>().ok()) {
+        (Some(a), Some(b)) => (a, b + 1),
+        (Some(a), None) => (a, len),
+        (None, Some(n)) => (len.saturating_sub(n), len),
+        (None, None) => return None,
+    };
+    let end = end.min(len);
+    (start < end).then_some((start, end))
+}
+
+fn respond(request: &Request<Vec<u8>>, body: Vec<u8>, content_type: &str, etag: String) -> Response<Vec<u8>> {
+    let base = || {
+        Response::builder()
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(header::ETAG, &etag)
+            // адрес страницы не меняется при перезаписи файла — всегда перепроверяем по ETag
+            .header(header::CACHE_CONTROL, "no-cache")
+            .header(header::ACCEPT_RANGES, "bytes")
+    };
+    let header_str = |name| request.headers().get(name).and_then(|v| v.to_str().ok());
+    if header_str(header::IF_NONE_MATCH) == Some(etag.as_str()) {
+        return base().status(StatusCode::NOT_MODIFIED).body(Vec::new()).unwrap();
+    }
+    if let Some(range) = header_str(header::RANGE) {
+        let len = body.len();
+        return match parse_range(range, len) {
+            Some((start, end)) => base()
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end - 1, len))
+                .body(body[start..end].to_vec())
+                .unwrap(),
+            None => base()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Vec::new())
+                .unwrap(),
+        };
+    }
+    base().status(StatusCode::OK).header(header::CONTENT_TYPE, content_type).body(body).unwrap()
+}
+
+// Разбор адреса: весь путь — один закодированный сегмент "<project>/<page path>/<layer>"
+// (так его собирает convertFileSrc на фронте); на Windows адрес http://mtimg.localhost/...
+fn parse_uri(request: &Request<Vec<u8>>) -> Option<(String, String, String)> {
+    let raw = request.uri().path().trim_start_matches('/');
+    let decoded = percent_decode_str(raw).decode_utf8().ok()?.to_string();
+    let (project, rest) = decoded.split_once('/')?;
+    let (page, layer) = rest.rsplit_once('/')?;
+    Some((project.to_string(), page.to_string(), layer.to_string()))
+}
+
+pub fn serve(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let Some((project, page_path, layer)) = parse_uri(request) else {
+        return status(StatusCode::BAD_REQUEST, "Bad page address");
+    };
+    let registry = app.state::<PageRegistry>();
+    // под замком только копируем нужное; файлы читаем уже без него
+    let (boxes, mask, final_image) = {
+        let projects = registry.projects.lock().unwrap();
+        let Some(page) = projects.get(&project).and_then(|p| p.get(&page_path)) else {
+            return status(StatusCode::FORBIDDEN, "Page is not part of an open project");
+        };
+        match layer.as_str() {
+            "mask" => (page.boxes.clone(), page.mask.clone(), None),
+            "final" => (Vec::new(), None, page.final_image.clone()),
+            _ => (Vec::new(), None, None),
+        }
+    };
+    let path = Path::new(&page_path);
+
+    let result: Result<(Vec<u8>, &str, String), String> = match layer.as_str() {
+        "original" => std::fs::read(path).map_err(|e| e.to_string()).map(|bytes| {
+            let etag = file_etag(path).unwrap_or_else(|| etag_of(&bytes));
+            let mime = mime(&bytes);
+            (bytes, mime, etag)
+        }),
+        "thumb" => thumbs::thumb_cached(thumbs::cache_dir(app).as_deref(), path).and_then(|t| {
+            let b64 = t.thumbnail.split_once(',').map(|(_, b)| b).unwrap_or(&t.thumbnail);
+            let bytes = STANDARD.decode(b64).map_err(|e| e.to_string())?;
+            let etag = file_etag(path).unwrap_or_else(|| etag_of(&bytes));
+            Ok((bytes, "image/jpeg", etag))
+        }),
+        "mask" => match mask {
+            Some(bytes) => Ok((bytes.clone(), mime(&bytes), etag_of(&bytes))),
+            None => render_mask(path, &boxes).map(|bytes| {
+                let etag = etag_of(&bytes);
+                (bytes, "image/png", etag)
+            }),
+        },
+        "final" => match final_image {
+            Some(bytes) => Ok((bytes.clone(), mime(&bytes), etag_of(&bytes))),
+            None => return status(StatusCode::NOT_FOUND, "No final image for this page"),
+        },
+        _ => return status(StatusCode::NOT_FOUND, "Unknown layer"),
+    };
+    match result {
+        Ok((body, content_type, etag)) => respond(request, body, content_type, etag),
+        Err(e) => status(StatusCode::NOT_FOUND, &e),
+    }
+}
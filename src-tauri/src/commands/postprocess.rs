@@ -0,0 +1,360 @@
+use serde::{Deserialize, Serialize};
+
+use crate::commands::BoundingBox;
+
+// Чистка боксов детектора перед OCR: обрезка по краям страницы, мусор, дубли, разорванные баблы
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PostprocessOptions {
+    // обрезать боксы по границам страницы
+    pub clamp: bool,
+    // меньше такой площади (px²) — шум на скринтоне
+    pub min_area: f64,
+    // меньше такой стороны (px)
+    pub min_side: f64,
+    // вытянутые сильнее (длинная сторона / короткая) — полосы, линии рамок
+    pub max_aspect: f64,
+    // NMS: из двух боксов с IoU не меньше оставляем более уверенный
+    pub iou_threshold: f64,
+    // ...и бокс, почти целиком лежащий в другом (доля его площади)
+    pub containment: f64,
+    // склеивать половинки одного бабла
+    pub merge: bool,
+    // допустимый зазор между половинками, доля меньшей стороны; половинки разрезанного бабла
+    // перекрываются или касаются, а между соседними баблами обычно виден зазор
+    pub merge_gap: f64,
+    // половинки должны совпадать по поперечной оси на такую долю большей из них
+    pub merge_alignment: f64,
+    // объединение должно быть заполнено боксами хотя бы на такую долю
+    pub merge_fill: f64,
+}
+
+impl Default for PostprocessOptions {
+    fn default() -> Self {
+        PostprocessOptions {
+            clamp: true,
+            min_area: 150.0,
+            min_side: 8.0,
+            max_aspect: 15.0,
+            iou_threshold: 0.5,
+            containment: 0.85,
+            merge: true,
+            merge_gap: 0.02,
+            merge_alignment: 0.8,
+            merge_fill: 0.8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Clamped,
+    Filtered,
+    Suppressed,
+    Merged,
+}
+
+// Одна правка: что было и что стало (None — бокс выкинут)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub before: Vec<BoundingBox>,
+    pub after: Option<BoundingBox>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostprocessReport {
+    pub input: usize,
+    pub output: usize,
+    pub clamped: usize,
+    pub filtered: usize,
+    pub suppressed: usize,
+    pub merged: usize,
+    pub changes: Vec<Change>,
+}
+
+impl PostprocessReport {
+    fn push(&mut self, kind: ChangeKind, before: Vec<BoundingBox>, after: Option<BoundingBox>, reason: String) {
+        match kind {
+            ChangeKind::Clamped => self.clamped += 1,
+            ChangeKind::Filtered => self.filtered += 1,
+            ChangeKind::Suppressed => self.suppressed += 1,
+            ChangeKind::Merged => self.merged += 1,
+        }
+        self.changes.push(Change { kind, before, after, reason });
+    }
+}
+
+// Бокс детектора с уверенностью, если бэкенд её прислал
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Detection {
+    #[serde(flatten)]
+    pub bbox: BoundingBox,
+    #[serde(default, alias = "confidence")]
+    pub score: Option<f64>,
+}
+
+fn width(b: &BoundingBox) -> f64 {
+    (b.x2 - b.x1).max(0.0)
+}
+fn height(b: &BoundingBox) -> f64 {
+    (b.y2 - b.y1).max(0.0)
+}
+fn area(b: &BoundingBox) -> f64 {
+    width(b) * height(b)
+}
+fn intersection(a: &BoundingBox, b: &BoundingBox) -> f64 {
+    let w = a.x2.min(b.x2) - a.x1.max(b.x1);
+    let h = a.y2.min(b.y2) - a.y1.max(b.y1);
+    if w > 0.0 && h > 0.0 {
+        w * h
+    } else {
+        0.0
+    }
+}
+fn union_box(a: &BoundingBox, b: &BoundingBox) -> BoundingBox {
+    BoundingBox { x1: a.x1.min(b.x1), y1: a.y1.min(b.y1), x2: a.x2.max(b.x2), y2: a.y2.max(b.y2) }
+}
+// Перекрытие отрезков [a1, a2] и [b1, b2] как доля большего (маленький бокс у края большого
+// не считается совпадающим); зазор — отрицательный
+fn span_overlap(a1: f64, a2: f64, b1: f64, b2: f64) -> (f64, f64) {
+    let shared = a2.min(b2) - a1.max(b1);
+    let longer = (a2 - a1).max(b2 - b1).max(1.0);
+    (shared / longer, shared)
+}
+
+// Похожи ли два бокса на половинки одного бабла
+fn halves(a: &BoundingBox, b: &BoundingBox, opts: &PostprocessOptions) -> bool {
+    let u = union_box(a, b);
+    let covered = area(a) + area(b) - intersection(a, b);
+    if area(&u) <= 0.0 || covered / area(&u) < opts.merge_fill {
+        return false;
+    }
+    let (long, short) = (width(&u).max(height(&u)), width(&u).min(height(&u)).max(1.0));
+    if long / short > opts.max_aspect {
+        return false;
+    }
+    // один над другим: совпадают по x, по y стык или небольшой зазор
+    let (x_align, _) = span_overlap(a.x1, a.x2, b.x1, b.x2);
+    let (_, y_shared) = span_overlap(a.y1, a.y2, b.y1, b.y2);
+    let y_gap = height(a).min(height(b)) * opts.merge_gap;
+    if x_align >= opts.merge_alignment && y_shared >= -y_gap {
+        return true;
+    }
+    // рядом по горизонтали
+    let (y_align, _) = span_overlap(a.y1, a.y2, b.y1, b.y2);
+    let (_, x_shared) = span_overlap(a.x1, a.x2, b.x1, b.x2);
+    let x_gap = width(a).min(width(b)) * opts.merge_gap;
+    y_align >= opts.merge_alignment && x_shared >= -x_gap
+}
+
+fn describe(b: &BoundingBox) -> String {
+    format!("{:.0}×{:.0}", width(b), height(b))
+}
+
+// Порядок шагов: обрезка → фильтры → NMS → склейка (склейка после NMS, чтобы не слить дубли)
+pub fn postprocess(
+    detections: Vec<Detection>,
+    bounds: Option<(u32, u32)>,
+    opts: &PostprocessOptions,
+) -> (Vec<BoundingBox>, PostprocessReport) {
+    let mut report = PostprocessReport { input: detections.len(), ..Default::default() };
+    let mut items: Vec<Detection> = Vec::with_capacity(detections.len());
+
+    for mut d in detections {
+        let b = d.bbox;
+        // перевёрнутые координаты — нормализуем молча, это не правка детекции
+        d.bbox = BoundingBox { x1: b.x1.min(b.x2), y1: b.y1.min(b.y2), x2: b.x1.max(b.x2), y2: b.y1.max(b.y2) };
+
+        if let (true, Some((w, h))) = (opts.clamp, bounds) {
+            let (w, h) = (w as f64, h as f64);
+            let c = BoundingBox { x1: d.bbox.x1.clamp(0.0, w), y1: d.bbox.y1.clamp(0.0, h), x2: d.bbox.x2.clamp(0.0, w), y2: d.bbox.y2.clamp(0.0, h) };
+            if c.x1 != d.bbox.x1 || c.y1 != d.bbox.y1 || c.x2 != d.bbox.x2 || c.y2 != d.bbox.y2 {
+                report.push(ChangeKind::Clamped, vec![d.bbox], Some(c), format!("outside {}×{} page", w, h));
+                d.bbox = c;
+            }
+        }
+
+        let (w, h) = (width(&d.bbox), height(&d.bbox));
+        let reason = if area(&d.bbox) < opts.min_area {
+            Some(format!("area {:.0} < {:.0}", area(&d.bbox), opts.min_area))
+        } else if w.min(h) < opts.min_side {
+            Some(format!("side {:.0} < {:.0}", w.min(h), opts.min_side))
+        } else if w.max(h) / w.min(h).max(1.0) > opts.max_aspect {
+            Some(format!("aspect {:.1} > {:.1}", w.max(h) / w.min(h).max(1.0), opts.max_aspect))
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => report.push(ChangeKind::Filtered, vec![d.bbox], None, reason),
+            None => items.push(d),
+        }
+    }
+
+    // NMS: более уверенные (или, без score, более крупные) идут первыми
+    items.sort_by(|a, b| {
+        b.score
+            .unwrap_or(0.0)
+            .total_cmp(&a.score.unwrap_or(0.0))
+            .then(area(&b.bbox).total_cmp(&area(&a.bbox)))
+    });
+    let mut kept: Vec<Detection> = Vec::with_capacity(items.len());
+    for d in items {
+        let dup = kept.iter().find_map(|k| {
+            let inter = intersection(&k.bbox, &d.bbox);
+            let iou = inter / (area(&k.bbox) + area(&d.bbox) - inter).max(1.0);
+            let inside = inter / area(&d.bbox).max(1.0);
+            if iou >= opts.iou_threshold {
+                Some((k.bbox, format!("IoU {:.2}", iou)))
+            } else if inside >= opts.containment {
+                Some((k.bbox, format!("{:.0}% inside another box", inside * 100.0)))
+            } else {
+                None
+            }
+        });
+        match dup {
+            Some((winner, reason)) => report.push(ChangeKind::Suppressed, vec![d.bbox, winner], Some(winner), reason),
+            None => kept.push(d),
+        }
+    }
+
+    // склеиваем попарно, пока есть что склеить (бабл может быть разорван на три куска)
+    let mut boxes: Vec<BoundingBox> = kept.into_iter().map(|d| d.bbox).collect();
+    if opts.merge {
+        'again: loop {
+            for i in 0..boxes.len() {
+                for j in i + 1..boxes.len() {
+                    if halves(&boxes[i], &boxes[j], opts) {
+                        let (a, b) = (boxes[i], boxes.swap_remove(j));
+                        let u = union_box(&a, &b);
+                        report.push(
+                            ChangeKind::Merged,
+                            vec![a, b],
+                            Some(u),
+                            format!("{} + {} look like one bubble", describe(&a), describe(&b)),
+                        );
+                        boxes[i] = u;
+                        continue 'again;
+                    }
+                }
+            }
+            break;
+        }
+    }
+
+    report.output = boxes.len();
+    (boxes, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn det(x1: f64, y1: f64, x2: f64, y2: f64, score: Option<f64>) -> Detection {
+        Detection { bbox: BoundingBox { x1, y1, x2, y2 }, score }
+    }
+
+    fn run(detections: Vec<Detection>) -> (Vec<BoundingBox>, PostprocessReport) {
+        postprocess(detections, Some((800, 1200)), &PostprocessOptions::default())
+    }
+
+    fn coords(b: &BoundingBox) -> [f64; 4] {
+        [b.x1, b.y1, b.x2, b.y2]
+    }
+
+    #[test]
+    fn overlapping_duplicates_are_suppressed() {
+        let (boxes, report) = run(vec![
+            det(100.0, 100.0, 250.0, 220.0, Some(0.6)),
+            det(104.0, 98.0, 252.0, 224.0, Some(0.9)),
+            // почти целиком внутри первого
+            det(120.0, 120.0, 230.0, 200.0, Some(0.5)),
+        ]);
+        assert_eq!(boxes.len(), 1, "{:?}", boxes);
+        assert_eq!(coords(&boxes[0]), [104.0, 98.0, 252.0, 224.0]);
+        assert_eq!(report.suppressed, 2);
+        assert_eq!(report.merged, 0);
+    }
+
+    #[test]
+    fn without_scores_larger_box_wins() {
+        let (boxes, report) = run(vec![det(100.0, 100.0, 240.0, 210.0, None), det(98.0, 96.0, 250.0, 220.0, None)]);
+        assert_eq!(boxes.len(), 1);
+        assert_eq!(coords(&boxes[0]), [98.0, 96.0, 250.0, 220.0]);
+        assert_eq!(report.suppressed, 1);
+    }
+
+    #[test]
+    fn adjacent_stacked_bubbles_are_kept() {
+        // два бабла одной ширины друг под другом с узким зазором
+        let (boxes, report) = run(vec![
+            det(100.0, 100.0, 260.0, 200.0, Some(0.9)),
+            det(100.0, 204.0, 260.0, 300.0, Some(0.9)),
+        ]);
+        assert_eq!(boxes.len(), 2, "{:?}", report.changes);
+        assert_eq!(report.merged, 0);
+    }
+
+    #[test]
+    fn adjacent_side_by_side_bubbles_are_kept() {
+        // вертикальные баблы рядом, как в японской манге
+        let (boxes, report) = run(vec![
+            det(300.0, 100.0, 380.0, 300.0, Some(0.8)),
+            det(384.0, 100.0, 460.0, 296.0, Some(0.8)),
+        ]);
+        assert_eq!(boxes.len(), 2, "{:?}", report.changes);
+    }
+
+    #[test]
+    fn small_bubble_touching_a_large_one_is_kept() {
+        let (boxes, _) = run(vec![
+            det(100.0, 100.0, 300.0, 260.0, Some(0.9)),
+            det(150.0, 260.0, 230.0, 320.0, Some(0.9)),
+        ]);
+        assert_eq!(boxes.len(), 2);
+    }
+
+    #[test]
+    fn split_bubble_halves_are_merged() {
+        // детектор разрезал бабл по строкам: половинки перекрываются на пару пикселей
+        let (boxes, report) = run(vec![
+            det(200.0, 400.0, 340.0, 462.0, Some(0.7)),
+            det(204.0, 460.0, 338.0, 520.0, Some(0.7)),
+        ]);
+        assert_eq!(boxes.len(), 1, "{:?}", boxes);
+        assert_eq!(coords(&boxes[0]), [200.0, 400.0, 340.0, 520.0]);
+        assert_eq!(report.merged, 1);
+    }
+
+    #[test]
+    fn boxes_are_clamped_at_the_page_edge() {
+        let (boxes, report) = run(vec![det(-12.0, 1100.0, 150.0, 1250.0, Some(0.9))]);
+        assert_eq!(coords(&boxes[0]), [0.0, 1100.0, 150.0, 1200.0]);
+        assert_eq!(report.clamped, 1);
+        assert_eq!(report.changes[0].kind, ChangeKind::Clamped);
+    }
+
+    #[test]
+    fn box_outside_the_page_is_filtered_after_clamp() {
+        // после обрезки остаётся полоска в 5px
+        let (boxes, report) = run(vec![det(795.0, 100.0, 900.0, 200.0, Some(0.9))]);
+        assert!(boxes.is_empty());
+        assert_eq!((report.clamped, report.filtered), (1, 1));
+    }
+
+    #[test]
+    fn noise_and_lines_are_filtered() {
+        let (boxes, report) = run(vec![
+            det(10.0, 10.0, 20.0, 20.0, None),
+            det(100.0, 100.0, 700.0, 110.0, None),
+            det(100.0, 300.0, 220.0, 400.0, None),
+        ]);
+        assert_eq!(boxes.len(), 1);
+        assert_eq!(report.filtered, 2);
+        assert_eq!(report.output, 1);
+    }
+}
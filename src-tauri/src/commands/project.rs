@@ -3,7 +3,10 @@ use serde::Deserialize;
 use serde_json;
 use std::fs;
 use std::io::Read;
-use tauri::command;
+use tauri::{command, State};
+
+use crate::commands::usage::{self, ProjectUsage, UsageLedger};
+use crate::commands::webtoon::{self, SliceOptions};
 
 use std::io::Write;
 use zip::write::ZipWriter;
@@ -35,12 +38,34 @@ pub async fn create_directory_structure(path: String) -> Result<(), String> {
 
     Ok(())
 }
+// reslice — склеить страницы в ленту и нарезать заново (высота кусков под требования площадки)
 #[command]
-pub async fn export_flattened_images(images: Vec<FlatImage>) -> Result<(), String> {
+pub async fn export_flattened_images(images: Vec<FlatImage>, reslice: Option<SliceOptions>) -> Result<(), String> {
 use rfd::FileDialog;let folder = FileDialog::new()
     .set_title("Export Images")
     .pick_folder();
 
+if let (Some(dir), Some(opts)) = (&folder, reslice) {
+    let pages = images
+        .iter()
+        .map(|img| {
+            // страницу не пропускаем молча: без неё лента нарежется со сдвигом
+            img.data_url
+                .split_once(',')
+                .ok_or_else(|| "not a data URL".to_string())
+                .and_then(|(_, b64)| STANDARD.decode(b64).map_err(|e| e.to_string()))
+                .map_err(|e| format!("Page {}: {}", img.name, e))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let slices = tauri::async_runtime::spawn_blocking(move || webtoon::reslice_pngs(&pages, &opts))
+        .await
+        .map_err(|e| e.to_string())??;
+    for (n, bytes) in slices.iter().enumerate() {
+        fs::write(dir.join(format!("{:03}.png", n + 1)), bytes).map_err(|e| e.to_string())?;
+    }
+    return Ok(());
+}
+
 if let Some(dir) = folder {
     for img in images {
         // data:image/png;base64,...
@@ -56,14 +81,29 @@ if let Some(dir) = folder {
 }
 Ok(())}
 #[command]
-pub async fn save_project(project_data: String, output_path: String) -> Result<(), String> {
+pub async fn save_project(
+    ledger: State<'_, UsageLedger>,
+    project_data: String,
+    output_path: String,
+) -> Result<(), String> {
+    // Токены/стоимость сохраняем рядом с project.json
+    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&project_data) {
+        if let Some(usage) = ledger.project(&usage::project_id_of(&parsed)) {
+            let json = serde_json::to_string_pretty(&usage).map_err(|e| e.to_string())?;
+            fs::write(format!("{}/usage.json", output_path), json).map_err(|e| e.to_string())?;
+        }
+    }
     fs::write(format!("{}/project.json", output_path), project_data).map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[command]
-pub async fn export_images(project_data: String, output_path: String) -> Result<(), String> {
-    save_project(project_data, output_path).await
+pub async fn export_images(
+    ledger: State<'_, UsageLedger>,
+    project_data: String,
+    output_path: String,
+) -> Result<(), String> {
+    save_project(ledger, project_data, output_path).await
 }
 
 // Имя с .png (для маски/финала)
@@ -116,6 +156,7 @@ fn write_png_1bit<W: std::io::Write>(mut w: W, gray: &image::GrayImage) -> Resul
 
 #[command]
 pub async fn export_project(
+    ledger: State<'_, UsageLedger>,
     project_data: serde_json::Value,
     image_data: Vec<ImageData>,
 ) -> Result<(), String> {
@@ -142,6 +183,14 @@ pub async fn export_project(
             zip.write_all(formatted.as_bytes())
                 .map_err(|e| e.to_string())?;
 
+            // usage.json — учёт токенов и стоимости по проекту
+            if let Some(usage) = ledger.project(&usage::project_id_of(&project_data)) {
+                zip.start_file("usage.json", options)
+                    .map_err(|e| e.to_string())?;
+                let json = serde_json::to_string_pretty(&usage).map_err(|e| e.to_string())?;
+                zip.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+            }
+
             // Папки
             zip.add_directory("originals/", options)
                 .map_err(|e| e.to_string())?;
@@ -221,7 +270,9 @@ pub async fn export_project(
 }
 
 #[command]
-pub async fn import_project() -> Result<Option<serde_json::Value>, String> {
+pub async fn import_project(
+    ledger: State<'_, UsageLedger>,
+) -> Result<Option<serde_json::Value>, String> {
     use rfd::FileDialog;
     use zip::read::ZipArchive;
     let file_path = FileDialog::new()
@@ -244,6 +295,15 @@ pub async fn import_project() -> Result<Option<serde_json::Value>, String> {
         let mut project_data: serde_json::Value =
             serde_json::from_str(&project_content).map_err(|e| e.to_string())?;
 
+        // usage.json (если есть) — восстанавливаем учёт токенов проекта
+        if let Ok(mut uf) = archive.by_name("usage.json") {
+            let mut ubuf = String::new();
+            uf.read_to_string(&mut ubuf).map_err(|e| e.to_string())?;
+            if let Ok(usage) = serde_json::from_str::<ProjectUsage>(&ubuf) {
+                ledger.restore(&usage::project_id_of(&project_data), usage);
+            }
+        }
+
         // Пройтись по images и подставить dataUrl/mask/final
         if let Some(images) = project_data["images"].as_array_mut() {
             for image in images {
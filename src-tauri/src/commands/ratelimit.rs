@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::commands::http::url_has_prefix;
+
+// Лимит для всех URL с префиксом url_prefix (как EndpointAuth — берётся самый длинный)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    pub url_prefix: String,
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+    pub max_concurrent: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WaitReason {
+    Concurrency,
+    Requests,
+    Tokens,
+}
+
+// Запрос стоит в очереди у лимита: в прогресс своей задачи (JobProgress.limit_wait),
+// вне задач — событием "rate-limit-wait"
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LimitWait {
+    pub url: String,
+    pub url_prefix: String,
+    pub reason: WaitReason,
+    pub wait_ms: u64,
+}
+
+// Token bucket: ёмкость = минутная норма, пополняется равномерно.
+// Баланс может уйти в минус, если фактический расход оказался больше оценки.
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    per_sec: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn per_minute(n: u32) -> Self {
+        let capacity = n.max(1) as f64;
+        Bucket {
+            capacity,
+            tokens: capacity,
+            per_sec: capacity / 60.0,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * self.per_sec).min(self.capacity);
+        self.last = now;
+    }
+
+    // Сколько ждать, пока в ведре наберётся n (запрос больше ёмкости ждёт полное ведро)
+    fn wait_for(&self, n: f64) -> Duration {
+        let need = n.min(self.capacity) - self.tokens;
+        if need <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(need / self.per_sec)
+        }
+    }
+}
+
+struct Limiter {
+    rule: RateLimit,
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+    concurrency: Option<Arc<Semaphore>>,
+}
+
+type Shared = Arc<Mutex<Limiter>>;
+
+// Держит слот конкурентности до конца запроса; settle() поправляет расход токенов по факту
+pub struct LimitPermit {
+    limiter: Option<Shared>,
+    reserved: f64,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl LimitPermit {
+    pub fn settle(&mut self, actual_tokens: u64) {
+        if let Some(l) = &self.limiter {
+            if let Some(b) = l.lock().unwrap().tokens.as_mut() {
+                b.tokens -= actual_tokens as f64 - self.reserved;
+            }
+            self.reserved = actual_tokens as f64;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    limiters: Mutex<HashMap<String, Shared>>,
+}
+
+impl RateLimiter {
+    // Пересобирает лимиты; счётчики уже известных префиксов сохраняются, если правило не менялось
+    pub fn configure(&self, rules: &[RateLimit]) {
+        let mut limiters = self.limiters.lock().unwrap();
+        let mut next = HashMap::new();
+        for rule in rules.iter().filter(|r| !r.url_prefix.trim().is_empty()) {
+            let key = rule.url_prefix.trim().trim_end_matches('/').to_string();
+            let same = limiters.get(&key).is_some_and(|l| {
+                let l = l.lock().unwrap();
+                l.rule.requests_per_minute == rule.requests_per_minute
+                    && l.rule.tokens_per_minute == rule.tokens_per_minute
+                    && l.rule.max_concurrent == rule.max_concurrent
+            });
+            let limiter = match limiters.remove(&key) {
+                Some(existing) if same => existing,
+                _ => Arc::new(Mutex::new(Limiter {
+                    rule: rule.clone(),
+                    requests: rule.requests_per_minute.filter(|n| *n > 0).map(Bucket::per_minute),
+                    tokens: rule.tokens_per_minute.filter(|n| *n > 0).map(Bucket::per_minute),
+                    concurrency: rule
+                        .max_concurrent
+                        .filter(|n| *n > 0)
+                        .map(|n| Arc::new(Semaphore::new(n as usize))),
+                })),
+            };
+            next.insert(key, limiter);
+        }
+        *limiters = next;
+    }
+
+    fn find(&self, url: &str) -> Option<(String, Shared)> {
+        self.limiters
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(prefix, _)| url_has_prefix(url, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(p, l)| (p.clone(), l.clone()))
+    }
+
+    // Ждёт слот и квоту для запроса на url; on_wait вызывается перед каждым ожиданием
+    pub async fn acquire(&self, url: &str, tokens: u64, on_wait: impl Fn(LimitWait)) -> LimitPermit {
+        let Some((prefix, limiter)) = self.find(url) else {
+            return LimitPermit { limiter: None, reserved: 0.0, _slot: None };
+        };
+        let wait = |reason, d: Duration| LimitWait {
+            url: url.to_string(),
+            url_prefix: prefix.clone(),
+            reason,
+            wait_ms: d.as_millis() as u64,
+        };
+
+        let sem = limiter.lock().unwrap().concurrency.clone();
+        let slot = match sem {
+            Some(sem) => Some(match sem.clone().try_acquire_owned() {
+                Ok(p) => p,
+                Err(_) => {
+                    on_wait(wait(WaitReason::Concurrency, Duration::ZERO));
+                    // семафор не закрывается, ошибки тут не бывает
+                    sem.acquire_owned().await.expect("rate limit semaphore closed")
+                }
+            }),
+            None => None,
+        };
+
+        let reserved = tokens as f64;
+        loop {
+            let pause = {
+                let mut l = limiter.lock().unwrap();
+                let req_wait = l.requests.as_mut().map(|b| {
+                    b.refill();
+                    b.wait_for(1.0)
+                });
+                let tok_wait = l.tokens.as_mut().map(|b| {
+                    b.refill();
+                    b.wait_for(reserved)
+                });
+                match (req_wait.unwrap_or_default(), tok_wait.unwrap_or_default()) {
+                    (r, t) if r.is_zero() && t.is_zero() => {
+                        if let Some(b) = l.requests.as_mut() {
+                            b.tokens -= 1.0;
+                        }
+                        if let Some(b) = l.tokens.as_mut() {
+                            b.tokens -= reserved;
+                        }
+                        None
+                    }
+                    (r, t) if r >= t => Some((WaitReason::Requests, r)),
+                    (_, t) => Some((WaitReason::Tokens, t)),
+                }
+            };
+            match pause {
+                None => break,
+                Some((reason, d)) => {
+                    on_wait(wait(reason, d));
+                    tokio::time::sleep(d).await;
+                }
+            }
+        }
+
+        LimitPermit {
+            limiter: Some(limiter),
+            reserved,
+            _slot: slot,
+        }
+    }
+}
+
+// Грубая оценка токенов по тексту запроса (~4 символа на токен) плюс лимит ответа
+pub fn estimate_tokens(payload: &serde_json::Value) -> u64 {
+    let text_len = match payload.get("messages").and_then(|m| m.as_array()) {
+        Some(messages) => messages
+            .iter()
+            .map(|m| match m.get("content") {
+                Some(serde_json::Value::String(s)) => s.chars().count(),
+                Some(serde_json::Value::Array(parts)) => parts
+                    .iter()
+                    .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                    .map(|t| t.chars().count())
+                    .sum(),
+                _ => 0,
+            })
+            .sum(),
+        None => payload.get("text").and_then(|t| t.as_str()).map(|t| t.chars().count()).unwrap_or(0),
+    };
+    let completion = payload.get("max_tokens").and_then(|m| m.as_u64()).unwrap_or(0);
+    (text_len as u64).div_ceil(4) + completion
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(prefixes: &[&str]) -> RateLimiter {
+        let limiter = RateLimiter::default();
+        let rules: Vec<RateLimit> = prefixes
+            .iter()
+            .map(|p| RateLimit { url_prefix: p.to_string(), max_concurrent: Some(1), ..Default::default() })
+            .collect();
+        limiter.configure(&rules);
+        limiter
+    }
+
+    #[test]
+    fn longest_matching_prefix_wins() {
+        let l = limiter(&["https://api.example.com", "https://api.example.com/v1/"]);
+        assert_eq!(l.find("https://api.example.com/v1/chat").map(|f| f.0).as_deref(), Some("https://api.example.com/v1"));
+        assert_eq!(l.find("https://api.example.com/v2/chat").map(|f| f.0).as_deref(), Some("https://api.example.com"));
+    }
+
+    #[test]
+    fn lookalike_hosts_and_ports_are_not_limited() {
+        let l = limiter(&["https://api.example.com"]);
+        assert!(l.find("https://api.example.com.attacker.net/v1").is_none());
+        assert!(l.find("https://api.example.com:8443/v1").is_none());
+        assert!(l.find("https://other.example.com/v1").is_none());
+    }
+}
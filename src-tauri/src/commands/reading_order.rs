@@ -0,0 +1,537 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::State;
+
+use crate::commands::cache::ResultCache;
+use crate::commands::downscale::{self, DetectPrep};
+use crate::commands::endpoints::EndpointPool;
+use crate::commands::gutters::{self, GutterOptions, PanelCheck};
+use crate::commands::heuristic;
+use crate::commands::http::HttpClient;
+use crate::commands::postprocess::{self, Detection, PostprocessOptions, PostprocessReport};
+use crate::commands::BoundingBox;
+
+// Порядок чтения баблов: внутри панелей и панелей на странице.
+// Нумерация баблов уходит в LLM строками "1. ...", так что от порядка зависит перевод.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadingMode {
+    // манга: справа налево, сверху вниз
+    #[default]
+    Rtl,
+    // комиксы, манхва-страницы: слева направо, сверху вниз
+    Ltr,
+    // вертикальная лента: сверху вниз, колонок нет
+    Webtoon,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReadingOptions {
+    pub mode: ReadingMode,
+    // два бокса в одной "строке", если перекрываются по вертикали на такую долю меньшей высоты
+    pub row_overlap: f64,
+    // панель вложена в другую, если та покрывает такую долю её площади
+    pub nested_coverage: f64,
+}
+
+impl Default for ReadingOptions {
+    fn default() -> Self {
+        ReadingOptions {
+            mode: ReadingMode::Rtl,
+            row_overlap: 0.35,
+            nested_coverage: 0.8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x1: f64,
+    y1: f64,
+    x2: f64,
+    y2: f64,
+}
+
+impl Rect {
+    fn of_box(b: &BoundingBox) -> Rect {
+        Rect { x1: b.x1.min(b.x2), y1: b.y1.min(b.y2), x2: b.x1.max(b.x2), y2: b.y1.max(b.y2) }
+    }
+    fn of_panel(p: &[f64; 4]) -> Rect {
+        Rect { x1: p[0].min(p[2]), y1: p[1].min(p[3]), x2: p[0].max(p[2]), y2: p[1].max(p[3]) }
+    }
+    fn w(&self) -> f64 {
+        (self.x2 - self.x1).max(1.0)
+    }
+    fn h(&self) -> f64 {
+        (self.y2 - self.y1).max(1.0)
+    }
+    fn area(&self) -> f64 {
+        self.w() * self.h()
+    }
+    fn cx(&self) -> f64 {
+        (self.x1 + self.x2) / 2.0
+    }
+    fn intersection(&self, o: &Rect) -> f64 {
+        let iw = self.x2.min(o.x2) - self.x1.max(o.x1);
+        let ih = self.y2.min(o.y2) - self.y1.max(o.y1);
+        if iw > 0.0 && ih > 0.0 {
+            iw * ih
+        } else {
+            0.0
+        }
+    }
+}
+
+// Единица упорядочивания: бабл или панель (со своим содержимым)
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Bubble(usize),
+    Panel(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Axis {
+    X,
+    Y,
+}
+
+struct Layout<'a> {
+    bubbles: Vec<Rect>,
+    panels: Vec<Rect>,
+    // дети панели (вложенные панели и баблы); для страницы — корни
+    children: Vec<Vec<Unit>>,
+    roots: Vec<Unit>,
+    opts: &'a ReadingOptions,
+}
+
+impl Layout<'_> {
+    fn rect(&self, u: Unit) -> Rect {
+        match u {
+            Unit::Bubble(i) => self.bubbles[i],
+            Unit::Panel(i) => self.panels[i],
+        }
+    }
+
+    // Разбиение по просвету вдоль оси. Боксы ужимаются на row_overlap/2 с каждой стороны:
+    // небольшое перекрытие (кривые рамки, соседние баблы) разрезу не мешает.
+    fn split(&self, units: &[Unit], axis: Axis) -> Option<Vec<Vec<Unit>>> {
+        let s = self.opts.row_overlap / 2.0;
+        let mut spans: Vec<(f64, f64, Unit)> = units
+            .iter()
+            .map(|&u| {
+                let r = self.rect(u);
+                let (a, b) = match axis {
+                    Axis::Y => (r.y1, r.y2),
+                    Axis::X => (r.x1, r.x2),
+                };
+                let m = (b - a).max(1.0) * s;
+                (a + m, b - m, u)
+            })
+            .collect();
+        spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut groups: Vec<Vec<Unit>> = Vec::new();
+        let mut reach = f64::NEG_INFINITY;
+        for (a, b, u) in spans {
+            match groups.last_mut() {
+                Some(g) if a < reach => g.push(u),
+                _ => groups.push(vec![u]),
+            }
+            reach = reach.max(b);
+        }
+        (groups.len() > 1).then_some(groups)
+    }
+
+    // XY-cut: сначала строки (сверху вниз), затем колонки (по направлению чтения).
+    // Где разрезать нельзя — жадно: самый верхний "ряд", в нём по направлению чтения.
+    fn order(&self, units: Vec<Unit>, out: &mut Vec<usize>) {
+        if units.len() <= 1 {
+            for u in units {
+                self.emit(u, out);
+            }
+            return;
+        }
+        if let Some(rows) = self.split(&units, Axis::Y) {
+            for row in rows {
+                self.order(row, out);
+            }
+            return;
+        }
+        if self.opts.mode != ReadingMode::Webtoon {
+            if let Some(mut cols) = self.split(&units, Axis::X) {
+                if self.opts.mode == ReadingMode::Rtl {
+                    cols.reverse();
+                }
+                for col in cols {
+                    self.order(col, out);
+                }
+                return;
+            }
+        }
+        for u in self.greedy(units) {
+            self.emit(u, out);
+        }
+    }
+
+    fn greedy(&self, mut rest: Vec<Unit>) -> Vec<Unit> {
+        let rtl = self.opts.mode == ReadingMode::Rtl;
+        let mut out = Vec::with_capacity(rest.len());
+        while !rest.is_empty() {
+            let top = rest.iter().map(|&u| self.rect(u).y1).fold(f64::INFINITY, f64::min);
+            // кандидаты — те, что начинаются в верхней "строке"
+            let pick = rest
+                .iter()
+                .enumerate()
+                .filter(|(_, &u)| {
+                    let r = self.rect(u);
+                    r.y1 <= top + r.h() * self.opts.row_overlap
+                })
+                .max_by(|(_, &a), (_, &b)| {
+                    let (a, b) = (self.rect(a).cx(), self.rect(b).cx());
+                    if rtl {
+                        a.total_cmp(&b)
+                    } else {
+                        b.total_cmp(&a)
+                    }
+                })
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            out.push(rest.remove(pick));
+        }
+        out
+    }
+
+    fn emit(&self, u: Unit, out: &mut Vec<usize>) {
+        match u {
+            Unit::Bubble(i) => out.push(i),
+            Unit::Panel(p) => self.order(self.children[p].clone(), out),
+        }
+    }
+}
+
+// Индексы bubbles в порядке чтения
+pub fn reading_order(bubbles: &[BoundingBox], panels: &[[f64; 4]], opts: &ReadingOptions) -> Vec<usize> {
+    let bubbles: Vec<Rect> = bubbles.iter().map(Rect::of_box).collect();
+    let panels: Vec<Rect> = panels.iter().map(Rect::of_panel).collect();
+
+    // Родитель панели — самая маленькая панель, которая её почти целиком покрывает
+    let parent: Vec<Option<usize>> = (0..panels.len())
+        .map(|i| {
+            (0..panels.len())
+                .filter(|&j| j != i)
+                .filter(|&j| {
+                    let (p, c) = (&panels[j], &panels[i]);
+                    // равные панели (дубли детектора) — родитель тот, у кого меньше индекс
+                    (p.area() > c.area() || (p.area() == c.area() && j < i))
+                        && p.intersection(c) / c.area() >= opts.nested_coverage
+                })
+                .min_by(|&a, &b| panels[a].area().total_cmp(&panels[b].area()))
+        })
+        .collect();
+
+    let mut children: Vec<Vec<Unit>> = vec![Vec::new(); panels.len()];
+    let mut roots = Vec::new();
+    for (i, p) in parent.iter().enumerate() {
+        match p {
+            Some(p) => children[*p].push(Unit::Panel(i)),
+            None => roots.push(Unit::Panel(i)),
+        }
+    }
+
+    // Бабл — в панель с наибольшим пересечением (при равенстве — в более вложенную);
+    // вне панелей — на уровень страницы, в общий порядок с панелями
+    for (i, b) in bubbles.iter().enumerate() {
+        let best = (0..panels.len())
+            .map(|p| (p, panels[p].intersection(b)))
+            .filter(|(_, a)| *a > 0.0)
+            .max_by(|(pa, a), (pb, b)| a.total_cmp(b).then(panels[*pb].area().total_cmp(&panels[*pa].area())));
+        match best {
+            Some((p, _)) => children[p].push(Unit::Bubble(i)),
+            None => roots.push(Unit::Bubble(i)),
+        }
+    }
+
+    let layout = Layout {
+        bubbles,
+        panels,
+        children,
+        roots,
+        opts,
+    };
+    let mut out = Vec::with_capacity(layout.bubbles.len());
+    layout.order(layout.roots.clone(), &mut out);
+    out
+}
+
+pub fn order_bubbles(bubbles: &[BoundingBox], panels: &[[f64; 4]], opts: &ReadingOptions) -> Vec<BoundingBox> {
+    reading_order(bubbles, panels, opts).into_iter().map(|i| bubbles[i]).collect()
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageDetectOptions {
+    #[serde(flatten)]
+    pub prep: DetectPrep,
+    #[serde(default)]
+    pub use_panels: bool,
+    #[serde(default)]
+    pub panel_source: PanelSource,
+    #[serde(default)]
+    pub reading: ReadingOptions,
+    // чистка боксов до OCR (None — боксы как есть)
+    #[serde(default)]
+    pub postprocess: Option<PostprocessOptions>,
+}
+
+// Откуда брать панели
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PanelSource {
+    // модель бэкенда (при ошибке — гуттеры)
+    #[default]
+    Backend,
+    // локально по гуттерам (commands/gutters.rs)
+    Gutters,
+    // модель, плюс сверка с гуттерами в panel_check
+    Check,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectedPage {
+    // уже в порядке чтения
+    pub boxes: Vec<BoundingBox>,
+    pub panels: Vec<[f64; 4]>,
+    // что поменяла чистка боксов
+    pub report: Option<PostprocessReport>,
+    // сверка панелей модели с гуттерами (PanelSource::Check)
+    pub panel_check: Option<PanelCheck>,
+    // почему панели взяли по гуттерам, а не от модели
+    pub panel_fallback: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BoxesResponse {
+    #[serde(default)]
+    boxes: Vec<Detection>,
+}
+
+#[derive(Deserialize)]
+struct PanelsResponse {
+    #[serde(default)]
+    panels: Vec<[f64; 4]>,
+}
+
+// Баблы (+ панели, если включены) и сразу порядок чтения — общий путь для UI и пакетной обработки
+pub async fn detect_ordered(
+    pool: &EndpointPool,
+    http: &HttpClient,
+    cache: &ResultCache,
+    api_url: &str,
+    payload: Value,
+    opts: &PageDetectOptions,
+) -> Result<DetectedPage, String> {
+    let image_data = payload.get("image_data").cloned().unwrap_or(Value::Null);
+    let offline = heuristic::is_selected(&payload);
+    let resp = if offline {
+        heuristic::detect_payload(&payload).await?
+    } else {
+        downscale::post_detect(pool, http, cache, api_url, "detect_text_areas", payload, &opts.prep).await?
+    };
+    let detections = serde_json::from_value::<BoxesResponse>(resp)
+        .map_err(|e| format!("Invalid detection response: {}", e))?
+        .boxes;
+    let (boxes, report) = match opts.postprocess.clone() {
+        Some(pp) => {
+            let image = image_data.as_str().map(str::to_string);
+            let (boxes, report) = tauri::async_runtime::spawn_blocking(move || {
+                // границы страницы — из заголовка картинки; без них просто не обрезаем
+                let bounds = image.and_then(|b64| {
+                    let b64 = b64.split_once(',').map(|(_, b)| b.to_string()).unwrap_or(b64);
+                    STANDARD.decode(b64).ok().and_then(|bytes| downscale::dimensions(&bytes).ok())
+                });
+                postprocess::postprocess(detections, bounds, &pp)
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+            (boxes, Some(report))
+        }
+        None => (detections.into_iter().map(|d| d.bbox).collect(), None),
+    };
+    let local = || gutters::detect_async(image_data.as_str().unwrap_or_default().to_string(), GutterOptions::default());
+    let (mut panel_check, mut panel_fallback) = (None, None);
+    let panels = match (opts.use_panels, offline || opts.panel_source == PanelSource::Gutters) {
+        (false, _) => Vec::new(),
+        // без бэкенда — панели по гуттерам
+        (true, true) => local().await?,
+        (true, false) => {
+            let payload = serde_json::json!({ "image_data": image_data });
+            match downscale::post_detect(pool, http, cache, api_url, "detect_panels", payload, &opts.prep).await {
+                Ok(resp) => {
+                    let panels = serde_json::from_value::<PanelsResponse>(resp).map(|p| p.panels).unwrap_or_default();
+                    if opts.panel_source == PanelSource::Check {
+                        panel_check = Some(gutters::check(&panels, &local().await?, 0.5));
+                    }
+                    panels
+                }
+                Err(e) => {
+                    panel_fallback = Some(format!("Panel detection failed: {}", e));
+                    local().await?
+                }
+            }
+        }
+    };
+    Ok(DetectedPage {
+        boxes: order_bubbles(&boxes, &panels, &opts.reading),
+        panels,
+        report,
+        panel_check,
+        panel_fallback,
+    })
+}
+
+#[tauri::command]
+pub async fn detect_page(
+    pool: State<'_, EndpointPool>,
+    http: State<'_, HttpClient>,
+    cache: State<'_, ResultCache>,
+    api_url: String,
+    payload: Value,
+    options: Option<PageDetectOptions>,
+) -> Result<DetectedPage, String> {
+    detect_ordered(&pool, &http, &cache, &api_url, payload, &options.unwrap_or_default()).await
+}
+
+// Переупорядочить уже найденные баблы (ручные правки, смена режима)
+#[tauri::command]
+pub fn order_reading(boxes: Vec<BoundingBox>, panels: Vec<[f64; 4]>, reading: Option<ReadingOptions>) -> Vec<BoundingBox> {
+    order_bubbles(&boxes, &panels, &reading.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bx(x1: f64, y1: f64, x2: f64, y2: f64) -> BoundingBox {
+        BoundingBox { x1, y1, x2, y2 }
+    }
+
+    fn order(bubbles: &[BoundingBox], panels: &[[f64; 4]], mode: ReadingMode) -> Vec<usize> {
+        reading_order(bubbles, panels, &ReadingOptions { mode, ..Default::default() })
+    }
+
+    // 2×2 кадра: в каждом по баблу
+    fn grid() -> (Vec<BoundingBox>, Vec<[f64; 4]>) {
+        let panels = vec![
+            [0.0, 0.0, 490.0, 490.0],
+            [510.0, 0.0, 1000.0, 490.0],
+            [0.0, 510.0, 490.0, 1000.0],
+            [510.0, 510.0, 1000.0, 1000.0],
+        ];
+        let bubbles = vec![
+            bx(100.0, 100.0, 200.0, 200.0), // левый верх
+            bx(600.0, 100.0, 700.0, 200.0), // правый верх
+            bx(100.0, 600.0, 200.0, 700.0), // левый низ
+            bx(600.0, 600.0, 700.0, 700.0), // правый низ
+        ];
+        (bubbles, panels)
+    }
+
+    #[test]
+    fn rtl_grid_reads_right_to_left_row_by_row() {
+        let (bubbles, panels) = grid();
+        assert_eq!(order(&bubbles, &panels, ReadingMode::Rtl), vec![1, 0, 3, 2]);
+    }
+
+    #[test]
+    fn ltr_grid_reads_left_to_right_row_by_row() {
+        let (bubbles, panels) = grid();
+        assert_eq!(order(&bubbles, &panels, ReadingMode::Ltr), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn rtl_tall_panel_on_the_right_comes_first() {
+        // справа высокий кадр на всю страницу, слева два друг под другом
+        let panels = vec![[0.0, 0.0, 490.0, 490.0], [0.0, 510.0, 490.0, 1000.0], [510.0, 0.0, 1000.0, 1000.0]];
+        let bubbles = vec![bx(100.0, 100.0, 200.0, 200.0), bx(100.0, 600.0, 200.0, 700.0), bx(700.0, 800.0, 800.0, 900.0)];
+        assert_eq!(order(&bubbles, &panels, ReadingMode::Rtl), vec![2, 0, 1]);
+        assert_eq!(order(&bubbles, &panels, ReadingMode::Ltr), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn bubbles_inside_panel_follow_mode() {
+        // два бабла в одной строке (перекрываются по вертикали), третий ниже
+        let bubbles = vec![bx(100.0, 100.0, 200.0, 300.0), bx(400.0, 150.0, 500.0, 320.0), bx(250.0, 500.0, 350.0, 600.0)];
+        assert_eq!(order(&bubbles, &[], ReadingMode::Rtl), vec![1, 0, 2]);
+        assert_eq!(order(&bubbles, &[], ReadingMode::Ltr), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn nested_panel_is_read_in_place() {
+        // большой кадр с врезкой в правом нижнем углу; под ним ещё кадр
+        let panels = vec![[0.0, 0.0, 1000.0, 600.0], [600.0, 350.0, 980.0, 580.0], [0.0, 620.0, 1000.0, 1000.0]];
+        let bubbles = vec![
+            bx(50.0, 50.0, 200.0, 200.0),   // верх большого, слева
+            bx(800.0, 50.0, 950.0, 200.0),  // верх большого, справа
+            bx(700.0, 400.0, 800.0, 500.0), // во врезке
+            bx(400.0, 700.0, 500.0, 800.0), // нижний кадр
+        ];
+        assert_eq!(order(&bubbles, &panels, ReadingMode::Rtl), vec![1, 0, 2, 3]);
+        assert_eq!(order(&bubbles, &panels, ReadingMode::Ltr), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn bubble_overlapping_two_panels_goes_to_the_larger_overlap() {
+        let panels = vec![[510.0, 0.0, 1000.0, 490.0], [0.0, 0.0, 490.0, 490.0]];
+        // бабл на стыке, больше в левом кадре
+        let bubbles = vec![bx(400.0, 100.0, 540.0, 200.0), bx(700.0, 300.0, 800.0, 400.0)];
+        assert_eq!(order(&bubbles, &panels, ReadingMode::Rtl), vec![1, 0]);
+    }
+
+    #[test]
+    fn overlapping_panels_do_not_break_ordering() {
+        // кадры заходят друг на друга на 40px
+        let panels = vec![[0.0, 0.0, 540.0, 500.0], [500.0, 0.0, 1000.0, 500.0], [0.0, 480.0, 1000.0, 1000.0]];
+        let bubbles = vec![bx(100.0, 100.0, 200.0, 200.0), bx(800.0, 100.0, 900.0, 200.0), bx(400.0, 700.0, 500.0, 800.0)];
+        assert_eq!(order(&bubbles, &panels, ReadingMode::Rtl), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn orphans_are_ordered_with_panels() {
+        // бабл в гуттере между рядами читается между ними, а не в конце
+        let panels = vec![[0.0, 0.0, 1000.0, 400.0], [0.0, 600.0, 1000.0, 1000.0]];
+        let bubbles = vec![bx(400.0, 700.0, 500.0, 800.0), bx(400.0, 450.0, 500.0, 550.0), bx(400.0, 100.0, 500.0, 200.0)];
+        assert_eq!(order(&bubbles, &panels, ReadingMode::Rtl), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn webtoon_reads_top_down_without_columns() {
+        // в ленте баблы зигзагом: колонки не выделяем, идём строго сверху вниз
+        let bubbles = vec![
+            bx(500.0, 1000.0, 700.0, 1200.0),
+            bx(100.0, 100.0, 300.0, 300.0),
+            bx(500.0, 150.0, 700.0, 350.0),
+            bx(100.0, 2000.0, 300.0, 2200.0),
+        ];
+        assert_eq!(order(&bubbles, &[], ReadingMode::Webtoon), vec![1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn every_bubble_is_returned_once() {
+        let bubbles: Vec<BoundingBox> = (0..40)
+            .map(|i| {
+                let (x, y) = ((i * 37 % 900) as f64, (i * 53 % 1300) as f64);
+                bx(x, y, x + 80.0, y + 120.0)
+            })
+            .collect();
+        let panels = vec![[0.0, 0.0, 600.0, 700.0], [300.0, 300.0, 1000.0, 1400.0], [50.0, 50.0, 250.0, 250.0]];
+        for mode in [ReadingMode::Rtl, ReadingMode::Ltr, ReadingMode::Webtoon] {
+            let mut got = order(&bubbles, &panels, mode);
+            got.sort();
+            assert_eq!(got, (0..40).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::{imageops::FilterType, DynamicImage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{command, AppHandle, Manager};
+
+use crate::commands::cache::{self, ResultCache};
+use crate::commands::endpoints::EndpointPool;
+use crate::commands::http::HttpClient;
+use crate::commands::BoundingBox;
+
+// движок OCR, если не задан
+pub const DEFAULT_OCR_ENGINE: &str = "manga";
+
+// Как готовить кропы для OCR
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CropOptions {
+    // отступ вокруг бокса, px (обрезается по краю страницы)
+    pub padding: u32,
+    // повернуть вертикальные кропы на 90° против часовой (для движков без вертикального текста)
+    pub rotate_vertical: bool,
+    // вертикальным считаем кроп с высотой > ширина * vertical_ratio
+    pub vertical_ratio: f64,
+    // мелкие кропы увеличиваем, пока меньшая сторона не станет min_side (0 — не увеличивать)
+    pub min_side: u32,
+    pub max_upscale: f64,
+}
+
+impl Default for CropOptions {
+    fn default() -> Self {
+        CropOptions {
+            padding: 0,
+            rotate_vertical: false,
+            vertical_ratio: 1.5,
+            min_side: 0,
+            max_upscale: 3.0,
+        }
+    }
+}
+
+// Картинка страницы: файл на диске или data URL (для temp:// страниц)
+pub fn load_page_image(path: &str, data_url: Option<&str>) -> Result<DynamicImage, String> {
+    match data_url.filter(|d| !d.is_empty()) {
+        Some(d) => {
+            let b64 = d.split_once(',').map(|(_, b)| b).unwrap_or(d);
+            let bytes = STANDARD.decode(b64).map_err(|e| e.to_string())?;
+            image::load_from_memory(&bytes).map_err(|e| format!("Failed to decode {}: {}", path, e))
+        }
+        None => image::open(path).map_err(|e| format!("Failed to open {}: {}", path, e)),
+    }
+}
+
+// Бокс с отступом в пикселях: начало вниз, конец вверх (до обрезки по краю страницы)
+pub fn crop_rect(b: &BoundingBox, padding: u32) -> (u32, u32, u32, u32) {
+    let pad = padding as f64;
+    (
+        (b.x1 - pad).max(0.0).floor() as u32,
+        (b.y1 - pad).max(0.0).floor() as u32,
+        (b.x2 + pad).max(0.0).ceil() as u32,
+        (b.y2 + pad).max(0.0).ceil() as u32,
+    )
+}
+
+fn crop_one(img: &DynamicImage, b: &BoundingBox, opts: &CropOptions) -> DynamicImage {
+    let (iw, ih) = (img.width(), img.height());
+    let (x1, y1, x2, y2) = crop_rect(b, opts.padding);
+    let x1 = x1.min(iw.saturating_sub(1));
+    let y1 = y1.min(ih.saturating_sub(1));
+    let x2 = x2.clamp(x1 + 1, iw.max(x1 + 1));
+    let y2 = y2.clamp(y1 + 1, ih.max(y1 + 1));
+    let mut crop = img.crop_imm(x1, y1, x2 - x1, y2 - y1);
+
+    let short = crop.width().min(crop.height());
+    if opts.min_side > 0 && short < opts.min_side {
+        let k = (opts.min_side as f64 / short as f64).min(opts.max_upscale.max(1.0));
+        let (w, h) = ((crop.width() as f64 * k).round() as u32, (crop.height() as f64 * k).round() as u32);
+        crop = crop.resize_exact(w.max(1), h.max(1), FilterType::Lanczos3);
+    }
+
+    if opts.rotate_vertical && crop.height() as f64 > crop.width() as f64 * opts.vertical_ratio {
+        // столбцы сверху вниз становятся строками слева направо
+        crop = crop.rotate270();
+    }
+    crop
+}
+
+// Вырезает боксы из оригинала и кодирует в PNG (без потерь, в отличие от canvas JPEG)
+pub fn crop_regions(img: &DynamicImage, boxes: &[BoundingBox], opts: &CropOptions) -> Result<Vec<String>, String> {
+    boxes
+        .iter()
+        .map(|b| {
+            let mut buf = std::io::Cursor::new(Vec::new());
+            crop_one(img, b, opts)
+                .write_to(&mut buf, image::ImageOutputFormat::Png)
+                .map_err(|e| e.to_string())?;
+            Ok(STANDARD.encode(buf.into_inner()))
+        })
+        .collect()
+}
+
+// OCR боксов страницы одним батчем; результат в порядке boxes
+pub async fn recognize(
+    app: &AppHandle,
+    api_url: &str,
+    engine: Option<&str>,
+    path: String,
+    data_url: Option<String>,
+    boxes: Vec<BoundingBox>,
+    opts: CropOptions,
+) -> Result<Vec<Option<String>>, String> {
+    if boxes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let count = boxes.len();
+    let images_data = tauri::async_runtime::spawn_blocking(move || {
+        let img = load_page_image(&path, data_url.as_deref())?;
+        crop_regions(&img, &boxes, &opts)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    let pool = app.state::<EndpointPool>();
+    let http = app.state::<HttpClient>();
+    let cache = app.state::<ResultCache>();
+    let payload = serde_json::json!({
+        "images_data": images_data,
+        "engine": engine.unwrap_or(DEFAULT_OCR_ENGINE),
+        "langs": Value::Null,
+        "auto_rotate": false,
+    });
+    let check = |r: &Value| ocr_results(r, count).map(|_| ());
+    let resp =
+        cache::post_cached_checked(&pool, &http, &cache, api_url, "recognize_images_batch", &payload, check).await?;
+    ocr_results(&resp, count)
+}
+
+// Результаты сопоставляются с боксами по индексу — ответ другой длины отбрасываем целиком
+fn ocr_results(resp: &Value, count: usize) -> Result<Vec<Option<String>>, String> {
+    let results = resp
+        .get("results")
+        .and_then(|r| r.as_array())
+        .ok_or_else(|| "Invalid OCR response".to_string())?;
+    if results.len() != count {
+        return Err(format!("OCR returned {} results for {} boxes", results.len(), count));
+    }
+    Ok(results.iter().map(|v| v.as_str().filter(|s| !s.is_empty()).map(str::to_string)).collect())
+}
+
+// Кропы режутся здесь из оригинала — в IPC идут только боксы, а не base64 картинок.
+// Ответ в том же виде, что у recognize_images_batch: { "results": [...] }
+#[command]
+pub async fn recognize_regions(
+    app: AppHandle,
+    api_url: String,
+    path: String,
+    data_url: Option<String>,
+    boxes: Vec<BoundingBox>,
+    engine: Option<String>,
+    options: Option<CropOptions>,
+) -> Result<Value, String> {
+    let results = recognize(&app, &api_url, engine.as_deref(), path, data_url, boxes, options.unwrap_or_default()).await?;
+    Ok(serde_json::json!({ "results": results }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(x1: f64, y1: f64, x2: f64, y2: f64) -> BoundingBox {
+        BoundingBox { x1, y1, x2, y2 }
+    }
+
+    #[test]
+    fn ocr_results_must_match_box_count() {
+        let resp = serde_json::json!({ "results": ["a", "", null] });
+        assert_eq!(ocr_results(&resp, 3).unwrap(), vec![Some("a".to_string()), None, None]);
+        assert!(ocr_results(&resp, 4).is_err());
+        assert!(ocr_results(&resp, 2).is_err());
+        assert!(ocr_results(&serde_json::json!({}), 0).is_err());
+    }
+
+    #[test]
+    fn crop_rect_floors_start_and_ceils_end() {
+        assert_eq!(crop_rect(&bbox(10.6, 20.2, 30.1, 40.9), 0), (10, 20, 31, 41));
+        // отступ не уводит начало за край страницы
+        assert_eq!(crop_rect(&bbox(2.5, 3.0, 10.0, 12.0), 4), (0, 0, 14, 16));
+    }
+}
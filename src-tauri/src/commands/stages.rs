@@ -0,0 +1,434 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{command, AppHandle, Manager};
+
+use crate::commands::cache::{self, ResultCache};
+use crate::commands::endpoints::EndpointPool;
+use crate::commands::http::HttpClient;
+use crate::commands::jobs::{self, BatchOptions};
+use crate::commands::regions::{self, CropOptions};
+use crate::commands::usage::UsageContext;
+use crate::commands::BoundingBox;
+
+// Граф этапов: box -> crop -> OCR -> перевод, mask -> inpaint.
+// У каждого результата хранится хэш входа, из которого он получен;
+// результат устарел, если текущий хэш входа другой.
+
+// Хэши входов, на которых этапы запускались в последний раз
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StageHashes {
+    // хэш кропа (страница + бокс + движок и настройки кропа), на котором делали OCR
+    pub ocr: Option<String>,
+    // хэш OCR-текста, который переводили
+    pub translation: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageKind {
+    Ocr,
+    Translation,
+}
+
+fn sha256_hex(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // разделитель, чтобы ("ab","c") и ("a","bc") не совпадали
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Чем делают OCR: результат зависит от движка и подготовки кропа, а не только от бокса
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OcrInput {
+    pub engine: Option<String>,
+    pub crop: CropOptions,
+}
+
+impl OcrInput {
+    pub fn from_batch(options: &BatchOptions) -> Self {
+        OcrInput { engine: options.ocr_engine.clone(), crop: options.crop.clone() }
+    }
+}
+
+// Оригинал страницы не меняется (инпейнт идёт в копию), поэтому кроп определяется путём,
+// пиксельным прямоугольником (округление как в regions::crop_rect) и настройками OCR.
+pub fn crop_hash(path: &str, b: &BoundingBox, ocr: &OcrInput) -> String {
+    let (x1, y1, x2, y2) = regions::crop_rect(b, ocr.crop.padding);
+    let coords = format!("{}:{}:{}:{}", x1, y1, x2, y2);
+    let engine = ocr.engine.as_deref().unwrap_or(regions::DEFAULT_OCR_ENGINE);
+    let crop = serde_json::to_string(&ocr.crop).unwrap_or_default();
+    sha256_hex(&[b"crop", path.as_bytes(), coords.as_bytes(), engine.as_bytes(), crop.as_bytes()])
+}
+
+pub fn text_hash(text: &str) -> String {
+    sha256_hex(&[b"ocr", text.trim().as_bytes()])
+}
+
+pub fn mask_hash(path: &str, mask_data_url: &str) -> String {
+    let b64 = mask_data_url.split_once(',').map(|(_, b)| b).unwrap_or(mask_data_url);
+    sha256_hex(&[b"mask", path.as_bytes(), b64.as_bytes()])
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageItem {
+    pub id: usize,
+    #[serde(rename = "box")]
+    pub bbox: BoundingBox,
+    pub ocr_text: Option<String>,
+    pub translation: Option<String>,
+    pub cached_intermediate_text: Option<String>,
+    pub cached_intermediate_lang: Option<String>,
+    #[serde(default)]
+    pub stage_hashes: StageHashes,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StagePage {
+    pub path: String,
+    pub name: Option<String>,
+    // для temp:// страниц — оригинал в data URL
+    pub data_url: Option<String>,
+    pub items: Vec<StageItem>,
+    pub mask_data_url: Option<String>,
+    // хэш маски, с которой делали инпейнт
+    pub inpaint_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurrentHashes {
+    pub id: usize,
+    pub ocr: String,
+    pub translation: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StaleItem {
+    pub id: usize,
+    pub stages: Vec<StageKind>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StaleReport {
+    pub items: Vec<StaleItem>,
+    pub inpaint: bool,
+}
+
+// Есть хэш — сравниваем с текущим входом (пустой OCR с тем же кропом не повторяем).
+// Нет хэша: есть результат — сделано до появления хэшей, считаем актуальным; нет — этап не запускался.
+fn stale_stages(path: &str, ocr: &OcrInput, item: &StageItem) -> Vec<StageKind> {
+    let mut stages = Vec::new();
+    let ocr_stale = match &item.stage_hashes.ocr {
+        Some(h) => *h != crop_hash(path, &item.bbox, ocr),
+        None => item.ocr_text.is_none(),
+    };
+    if ocr_stale {
+        stages.push(StageKind::Ocr);
+    }
+
+    // устаревший OCR тянет за собой перевод
+    let translation_stale = match item.ocr_text.as_deref().filter(|t| !t.trim().is_empty()) {
+        None => ocr_stale && item.translation.is_some(),
+        Some(text) => {
+            ocr_stale
+                || item.translation.is_none()
+                || item.stage_hashes.translation.as_ref().is_some_and(|h| *h != text_hash(text))
+        }
+    };
+    if translation_stale {
+        stages.push(StageKind::Translation);
+    }
+    stages
+}
+
+pub fn find_stale(page: &StagePage, ocr: &OcrInput) -> StaleReport {
+    let items = page
+        .items
+        .iter()
+        .filter_map(|item| {
+            let stages = stale_stages(&page.path, ocr, item);
+            (!stages.is_empty()).then_some(StaleItem { id: item.id, stages })
+        })
+        .collect();
+    let inpaint = match page.mask_data_url.as_deref().filter(|m| !m.is_empty()) {
+        Some(mask) => page.inpaint_hash.as_deref() != Some(mask_hash(&page.path, mask).as_str()),
+        None => false,
+    };
+    StaleReport { items, inpaint }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputHashes {
+    pub items: Vec<CurrentHashes>,
+    pub inpaint: Option<String>,
+}
+
+// Хэши входов сейчас — UI записывает их в stageHashes / inpaintHash при ручном запуске этапа
+#[command]
+pub async fn stage_input_hashes(
+    path: String,
+    items: Vec<StageItem>,
+    mask_data_url: Option<String>,
+    ocr: Option<OcrInput>,
+) -> Result<InputHashes, String> {
+    let ocr = ocr.unwrap_or_default();
+    Ok(InputHashes {
+        items: items
+            .iter()
+            .map(|i| CurrentHashes {
+                id: i.id,
+                ocr: crop_hash(&path, &i.bbox, &ocr),
+                translation: i.ocr_text.as_deref().map(text_hash),
+            })
+            .collect(),
+        inpaint: mask_data_url.as_deref().map(|m| mask_hash(&path, m)),
+    })
+}
+
+#[command]
+pub async fn find_stale_items(page: StagePage, ocr: Option<OcrInput>) -> Result<StaleReport, String> {
+    Ok(find_stale(&page, &ocr.unwrap_or_default()))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshResult {
+    pub items: Vec<StageItem>,
+    // base64 PNG нового инпейнта, если маска устарела
+    pub image_data: Option<String>,
+    pub inpaint_hash: Option<String>,
+    // что осталось устаревшим (например, OCR вернул пустой текст)
+    pub report: StaleReport,
+}
+
+// Переводить ли бокс после OCR: устаревший по отчёту до OCR или устаревший теперь —
+// у нового бокса до OCR нечего переводить, и в отчёте у него только OCR
+fn needs_translation(path: &str, ocr: &OcrInput, before: &StaleReport, item: &StageItem) -> bool {
+    before.items.iter().any(|s| s.id == item.id && s.stages.contains(&StageKind::Translation))
+        || stale_stages(path, ocr, item).contains(&StageKind::Translation)
+}
+
+// Перезапускает только устаревшие этапы страницы
+#[command]
+pub async fn refresh_stale(
+    app: AppHandle,
+    page: StagePage,
+    options: BatchOptions,
+    inpaint_model: Option<String>,
+) -> Result<RefreshResult, String> {
+    let ocr = OcrInput::from_batch(&options);
+    let report = find_stale(&page, &ocr);
+    let mut items = page.items.clone();
+    let needs = |id: usize, stage: StageKind| {
+        report.items.iter().any(|s| s.id == id && s.stages.contains(&stage))
+    };
+
+    // OCR
+    let ocr_idx: Vec<usize> = (0..items.len()).filter(|&i| needs(items[i].id, StageKind::Ocr)).collect();
+    if !ocr_idx.is_empty() {
+        let boxes = ocr_idx.iter().map(|&i| items[i].bbox).collect();
+        let texts = regions::recognize(
+            &app,
+            &options.api_url,
+            options.ocr_engine.as_deref(),
+            page.path.clone(),
+            page.data_url.clone(),
+            boxes,
+            options.crop.clone(),
+        )
+        .await?;
+        for (&i, text) in ocr_idx.iter().zip(texts) {
+            items[i].stage_hashes.ocr = Some(crop_hash(&page.path, &items[i].bbox, &ocr));
+            items[i].ocr_text = text;
+        }
+    }
+
+    // Перевод: устаревшие до OCR + те, кому OCR только что дал текст
+    let lines: Vec<(usize, String)> = items
+        .iter()
+        .filter(|it| needs_translation(&page.path, &ocr, &report, it))
+        .filter_map(|it| it.ocr_text.clone().filter(|t| !t.trim().is_empty()).map(|t| (it.id, t)))
+        .collect();
+    for it in items.iter_mut().filter(|it| needs(it.id, StageKind::Translation)) {
+        if it.ocr_text.as_deref().is_none_or(|t| t.trim().is_empty()) {
+            it.translation = None;
+            it.stage_hashes.translation = None;
+        }
+    }
+    if !lines.is_empty() {
+        let usage = UsageContext {
+            project_id: options.project_id.clone(),
+            page: page.name.clone(),
+            run_id: None,
+        };
+        for t in jobs::translate_lines(&app, &options, &lines, usage).await? {
+            let Some(item) = items.iter_mut().find(|it| it.id == t.id) else {
+                continue;
+            };
+            item.stage_hashes.translation = item.ocr_text.as_deref().map(text_hash);
+            item.cached_intermediate_lang = t.intermediate.as_ref().map(|_| "EN".to_string());
+            item.cached_intermediate_text = t.intermediate;
+            item.translation = Some(t.text);
+        }
+    }
+
+    // Инпейнт по сохранённой маске — всегда от оригинала
+    let (mut image_data, mut inpaint_hash) = (None, page.inpaint_hash.clone());
+    if let (true, Some(mask)) = (report.inpaint, page.mask_data_url.as_deref()) {
+        let original = match page.data_url.as_deref().filter(|d| !d.is_empty()) {
+            Some(d) => d.split_once(',').map(|(_, b)| b).unwrap_or(d).to_string(),
+            None => super::read_file_b64(page.path.clone()).await?,
+        };
+        let payload = serde_json::json!({
+            "image_data": original,
+            "mask_data": mask.split_once(',').map(|(_, b)| b).unwrap_or(mask),
+            "model": inpaint_model.unwrap_or_else(|| "lama_large_512px".to_string()),
+        });
+        let pool = app.state::<EndpointPool>();
+        let http = app.state::<HttpClient>();
+        let cache = app.state::<ResultCache>();
+        let resp = cache::post_cached(&pool, &http, &cache, &options.api_url, "inpaint_manual", &payload).await?;
+        image_data = resp.get("image_data").and_then(|d| d.as_str()).map(str::to_string);
+        inpaint_hash = Some(mask_hash(&page.path, mask));
+    }
+
+    let after = StagePage {
+        items: items.clone(),
+        inpaint_hash: inpaint_hash.clone(),
+        ..page
+    };
+    Ok(RefreshResult {
+        report: find_stale(&after, &ocr),
+        items,
+        image_data,
+        inpaint_hash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "/pages/001.png";
+
+    fn item(id: usize) -> StageItem {
+        StageItem {
+            id,
+            bbox: BoundingBox { x1: 10.0, y1: 20.0, x2: 110.0, y2: 80.0 },
+            ocr_text: None,
+            translation: None,
+            cached_intermediate_text: None,
+            cached_intermediate_lang: None,
+            stage_hashes: StageHashes::default(),
+        }
+    }
+
+    fn page(items: Vec<StageItem>) -> StagePage {
+        StagePage { path: PATH.to_string(), name: None, data_url: None, items, mask_data_url: None, inpaint_hash: None }
+    }
+
+    // то, что refresh_stale записывает после OCR
+    fn ocr(it: &mut StageItem, text: &str) {
+        it.stage_hashes.ocr = Some(crop_hash(PATH, &it.bbox, &OcrInput::default()));
+        it.ocr_text = Some(text.to_string());
+    }
+
+    fn translate(it: &mut StageItem, text: &str) {
+        it.stage_hashes.translation = it.ocr_text.as_deref().map(text_hash);
+        it.translation = Some(text.to_string());
+    }
+
+    #[test]
+    fn new_box_is_translated_after_ocr() {
+        let mut it = item(1);
+        let before = find_stale(&page(vec![it.clone()]), &OcrInput::default());
+        // до OCR переводить нечего — в отчёте только OCR
+        assert_eq!(before.items[0].stages, vec![StageKind::Ocr]);
+
+        ocr(&mut it, "こんにちは");
+        assert!(needs_translation(PATH, &OcrInput::default(), &before, &it));
+        translate(&mut it, "Hello");
+        assert!(find_stale(&page(vec![it]), &OcrInput::default()).items.is_empty());
+    }
+
+    #[test]
+    fn empty_ocr_is_not_translated() {
+        let mut it = item(1);
+        let before = find_stale(&page(vec![it.clone()]), &OcrInput::default());
+        ocr(&mut it, "  ");
+        assert!(!needs_translation(PATH, &OcrInput::default(), &before, &it));
+        // пустой OCR с тем же кропом не повторяем
+        assert!(find_stale(&page(vec![it]), &OcrInput::default()).items.is_empty());
+    }
+
+    #[test]
+    fn moved_box_invalidates_ocr_and_translation() {
+        let mut it = item(1);
+        ocr(&mut it, "text");
+        translate(&mut it, "текст");
+        it.bbox.x2 += 15.0;
+        let report = find_stale(&page(vec![it]), &OcrInput::default());
+        assert_eq!(report.items[0].stages, vec![StageKind::Ocr, StageKind::Translation]);
+    }
+
+    #[test]
+    fn sub_pixel_move_inside_same_pixels_keeps_ocr() {
+        let mut it = item(1);
+        ocr(&mut it, "text");
+        translate(&mut it, "текст");
+        // 10.0 -> 10.4 режется с того же пикселя; 80.0 -> 80.4 уже добавляет строку
+        it.bbox.x1 += 0.4;
+        assert!(find_stale(&page(vec![it.clone()]), &OcrInput::default()).items.is_empty());
+        it.bbox.y2 += 0.4;
+        let report = find_stale(&page(vec![it]), &OcrInput::default());
+        assert_eq!(report.items[0].stages, vec![StageKind::Ocr, StageKind::Translation]);
+    }
+
+    #[test]
+    fn changed_crop_options_or_engine_invalidate_ocr() {
+        let mut it = item(1);
+        ocr(&mut it, "text");
+        translate(&mut it, "текст");
+        let padded = OcrInput { crop: CropOptions { padding: 4, ..CropOptions::default() }, ..OcrInput::default() };
+        let report = find_stale(&page(vec![it.clone()]), &padded);
+        assert_eq!(report.items[0].stages, vec![StageKind::Ocr, StageKind::Translation]);
+        let rotated = OcrInput {
+            crop: CropOptions { rotate_vertical: true, ..CropOptions::default() },
+            ..OcrInput::default()
+        };
+        assert!(!find_stale(&page(vec![it.clone()]), &rotated).items.is_empty());
+        let engine = OcrInput { engine: Some("other".to_string()), ..OcrInput::default() };
+        assert!(!find_stale(&page(vec![it.clone()]), &engine).items.is_empty());
+        // движок по умолчанию и явный "manga" — один и тот же вход
+        let manga = OcrInput { engine: Some("manga".to_string()), ..OcrInput::default() };
+        assert!(find_stale(&page(vec![it]), &manga).items.is_empty());
+    }
+
+    #[test]
+    fn edited_ocr_invalidates_translation_only() {
+        let mut it = item(1);
+        ocr(&mut it, "text");
+        translate(&mut it, "текст");
+        it.ocr_text = Some("edited".to_string());
+        let report = find_stale(&page(vec![it]), &OcrInput::default());
+        assert_eq!(report.items[0].stages, vec![StageKind::Translation]);
+    }
+
+    #[test]
+    fn changed_mask_invalidates_inpaint() {
+        let mut p = page(Vec::new());
+        p.mask_data_url = Some("data:image/png;base64,AAAA".to_string());
+        p.inpaint_hash = Some(mask_hash(PATH, "data:image/png;base64,AAAA"));
+        assert!(!find_stale(&p, &OcrInput::default()).inpaint);
+        p.mask_data_url = Some("data:image/png;base64,BBBB".to_string());
+        assert!(find_stale(&p, &OcrInput::default()).inpaint);
+    }
+}
@@ -0,0 +1,178 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::UNIX_EPOCH;
+use tauri::{command, AppHandle, Manager};
+
+// Превью для ленты страниц: большая сторона, px
+pub const THUMB_SIDE: u32 = 256;
+const THUMB_QUALITY: u8 = 80;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thumb {
+    // размеры оригинала
+    pub width: u32,
+    pub height: u32,
+    // data:image/jpeg;base64,...
+    pub thumbnail: String,
+}
+
+// Кэш превью: <app data>/thumbs/<2 символа>/<ключ>.json
+pub fn cache_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_data_dir().ok().map(|d| d.join("thumbs"))
+}
+
+// Ключ — путь + mtime + размер: перезаписанный файл получит новое превью
+pub fn file_key(path: &Path) -> Option<String> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    let mut hasher = Sha256::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    hasher.update([0u8]);
+    hasher.update(mtime.to_le_bytes());
+    hasher.update(meta.len().to_le_bytes());
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+pub fn make_thumb(path: &Path) -> Result<Thumb, String> {
+    let img = image::io::Reader::open(path)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .decode()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let small = img.thumbnail(THUMB_SIDE, THUMB_SIDE);
+    let mut buf = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgb8(small.to_rgb8())
+        .write_to(&mut buf, image::ImageOutputFormat::Jpeg(THUMB_QUALITY))
+        .map_err(|e| e.to_string())?;
+    Ok(Thumb {
+        width: img.width(),
+        height: img.height(),
+        thumbnail: format!("data:image/jpeg;base64,{}", STANDARD.encode(buf.into_inner())),
+    })
+}
+
+// Превью из кэша или заново (и в кэш); ошибки записи кэша не мешают
+pub fn thumb_cached(dir: Option<&Path>, path: &Path) -> Result<Thumb, String> {
+    let entry = dir.zip(file_key(path)).map(|(d, key)| d.join(&key[..2]).join(format!("{}.json", key)));
+    if let Some(thumb) = entry
+        .as_ref()
+        .and_then(|e| std::fs::read(e).ok())
+        .and_then(|b| serde_json::from_slice::<Thumb>(&b).ok())
+    {
+        return Ok(thumb);
+    }
+    let thumb = make_thumb(path)?;
+    if let Some(entry) = entry {
+        // не записали — в следующий раз превью просто посчитается заново
+        let _ = entry
+            .parent()
+            .map(std::fs::create_dir_all)
+            .transpose()
+            .and_then(|_| std::fs::write(&entry, serde_json::to_vec(&thumb).unwrap_or_default()));
+    }
+    Ok(thumb)
+}
+
+// f по всем элементам на нескольких потоках (до 8); результат i — для items[i].
+// Паника в f (битый файл в декодере) — Err только для этого элемента, остальные не теряются
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<Result<R, String>> {
+    let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).clamp(1, 8).min(items.len().max(1));
+    let next = AtomicUsize::new(0);
+    let done: Vec<Vec<(usize, Result<R, String>)>> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut out = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            break;
+                        };
+                        let r = std::panic::catch_unwind(AssertUnwindSafe(|| f(item)))
+                            .map_err(|_| "decoder panicked".to_string());
+                        out.push((i, r));
+                    }
+                    out
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap_or_default()).collect()
+    });
+    let mut slots: Vec<Option<Result<R, String>>> = (0..items.len()).map(|_| None).collect();
+    for (i, r) in done.into_iter().flatten() {
+        slots[i] = Some(r);
+    }
+    slots.into_iter().map(|r| r.unwrap_or_else(|| Err("worker thread failed".to_string()))).collect()
+}
+
+// Превью страницы или почему его нет
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageThumb {
+    pub thumb: Option<Thumb>,
+    pub error: Option<String>,
+}
+
+// Превью пачкой на всех ядрах
+pub fn thumbs_parallel(dir: Option<&Path>, paths: &[PathBuf]) -> Vec<PageThumb> {
+    parallel_map(paths, |path| thumb_cached(dir, path))
+        .into_iter()
+        .map(|r| match r.and_then(|t| t) {
+            Ok(thumb) => PageThumb { thumb: Some(thumb), error: None },
+            Err(error) => PageThumb { thumb: None, error: Some(error) },
+        })
+        .collect()
+}
+
+// Превью для уже известных путей (открытие проекта); нечитаемый файл — с error
+#[command]
+pub async fn get_thumbnails(app: AppHandle, paths: Vec<String>) -> Result<Vec<PageThumb>, String> {
+    let dir = cache_dir(&app);
+    let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+    tauri::async_runtime::spawn_blocking(move || thumbs_parallel(dir.as_deref(), &paths))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_map_keeps_order() {
+        let items: Vec<u32> = (0..100).collect();
+        let out = parallel_map(&items, |x| x * 2);
+        assert_eq!(out.len(), 100);
+        assert!(out.iter().enumerate().all(|(i, r)| r.as_ref().ok() == Some(&(i as u32 * 2))));
+    }
+
+    #[test]
+    fn panic_fails_only_its_item() {
+        let items: Vec<u32> = (0..40).collect();
+        let out = parallel_map(&items, |x| {
+            if *x == 7 {
+                panic!("corrupt file");
+            }
+            x + 1
+        });
+        assert_eq!(out.len(), items.len());
+        for (i, r) in out.iter().enumerate() {
+            if i == 7 {
+                assert_eq!(r.as_ref().err().map(String::as_str), Some("decoder panicked"));
+            } else {
+                assert_eq!(r.as_ref().ok(), Some(&(i as u32 + 1)));
+            }
+        }
+    }
+
+    #[test]
+    fn empty_input() {
+        assert!(parallel_map(&[] as &[u32], |x| *x).is_empty());
+    }
+}
@@ -0,0 +1,361 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Детекция длинных вертикальных страниц (вебтуны ~800×30000) по перекрывающимся тайлам
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TileOptions {
+    // высота тайла, px (0 — полторы ширины страницы)
+    pub tile_height: u32,
+    // перекрытие соседних тайлов, px
+    pub overlap: u32,
+    // режем только страницы с высотой > ширина * min_aspect
+    pub min_aspect: f64,
+    // боксы из разных тайлов склеиваются, если IoU не меньше (один и тот же бокс в зоне перекрытия)
+    pub iou_threshold: f64,
+    // ...или если один кусок обрезан швом, другой продолжается за шов и по ширине они совпадают
+    // на такую долю большего (вложенная панель внутри большой так не совпадает)
+    pub seam_alignment: f64,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        TileOptions {
+            tile_height: 0,
+            overlap: 256,
+            min_aspect: 2.5,
+            iou_threshold: 0.5,
+            seam_alignment: 0.8,
+        }
+    }
+}
+
+pub struct Tile {
+    pub y: u32,
+    pub height: u32,
+    pub image_data: String,
+}
+
+// Тайлы по вертикали (y, высота): первый от верха, последний прижат к низу, остальные
+// расставлены равномерно — перекрытие не меньше заданного и без почти одинаковых тайлов у низа
+pub fn plan_tiles(width: u32, height: u32, opts: &TileOptions) -> Vec<(u32, u32)> {
+    if (height as f64) <= width as f64 * opts.min_aspect.max(1.0) {
+        return vec![(0, height)];
+    }
+    let tile_h = match opts.tile_height {
+        0 => (width as f64 * 1.5) as u32,
+        h => h,
+    }
+    .max(256)
+    .min(height);
+    let overlap = opts.overlap.min(tile_h / 2);
+    let step = tile_h - overlap;
+    if tile_h >= height {
+        return vec![(0, height)];
+    }
+    // сколько тайлов с шагом step нужно, чтобы дойти до низа
+    let count = (height - overlap).div_ceil(step).max(2) as u64;
+    let span = (height - tile_h) as u64;
+    (0..count).map(|i| ((span * i / (count - 1)) as u32, tile_h)).collect()
+}
+
+// Нарезает страницу на тайлы (PNG base64). None — страница не длинная, резать не нужно.
+pub fn split_tiles(image_b64: &str, opts: &TileOptions) -> Result<Option<Vec<Tile>>, String> {
+    let b64 = image_b64.split_once(',').map(|(_, b)| b).unwrap_or(image_b64);
+    let bytes = STANDARD.decode(b64).map_err(|e| e.to_string())?;
+    let img = image::load_from_memory(&bytes).map_err(|e| e.to_string())?;
+    let plan = plan_tiles(img.width(), img.height(), opts);
+    if plan.len() < 2 {
+        return Ok(None);
+    }
+    plan.into_iter()
+        .map(|(y, h)| {
+            let mut buf = std::io::Cursor::new(Vec::new());
+            img.crop_imm(0, y, img.width(), h)
+                .write_to(&mut buf, image::ImageOutputFormat::Png)
+                .map_err(|e| e.to_string())?;
+            Ok(Tile {
+                y,
+                height: h,
+                image_data: STANDARD.encode(buf.into_inner()),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map(Some)
+}
+
+// Координаты бокса из ответа: {x1, y1, x2, y2, ...} или [x1, y1, x2, y2]
+fn coords(v: &Value) -> Option<[f64; 4]> {
+    match v {
+        Value::Object(m) => Some([
+            m.get("x1")?.as_f64()?,
+            m.get("y1")?.as_f64()?,
+            m.get("x2")?.as_f64()?,
+            m.get("y2")?.as_f64()?,
+        ]),
+        Value::Array(a) if a.len() >= 4 => Some([a[0].as_f64()?, a[1].as_f64()?, a[2].as_f64()?, a[3].as_f64()?]),
+        _ => None,
+    }
+}
+
+fn set_coords(v: &mut Value, c: [f64; 4]) {
+    match v {
+        Value::Object(m) => {
+            for (key, n) in ["x1", "y1", "x2", "y2"].into_iter().zip(c) {
+                m.insert(key.to_string(), Value::from(n));
+            }
+        }
+        Value::Array(a) => {
+            for (slot, n) in a.iter_mut().zip(c) {
+                *slot = Value::from(n);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn area(c: &[f64; 4]) -> f64 {
+    (c[2] - c[0]).max(0.0) * (c[3] - c[1]).max(0.0)
+}
+
+fn intersection(a: &[f64; 4], b: &[f64; 4]) -> f64 {
+    area(&[a[0].max(b[0]), a[1].max(b[1]), a[2].min(b[2]), a[3].min(b[3])])
+}
+
+// край бокса ближе — бокс обрезан краем тайла (после уменьшения координаты неточны на пару px)
+const SEAM_EDGE: f64 = 4.0;
+
+struct Merged {
+    coords: [f64; 4],
+    value: Value,
+    tiles: Vec<usize>,
+    // швы (y внутренних краёв тайла), которыми бокс обрезан
+    cuts: Vec<f64>,
+}
+
+fn class_of(v: &Value) -> Option<&Value> {
+    v.get("class").or_else(|| v.get("label"))
+}
+
+// Склеивает дубли на швах. tiles — (y, высота) тайлов в порядке per_tile.
+// Боксы одного тайла не сливаются между собой — соседние пузыри внутри тайла модель уже развела сама;
+// вложенные боксы из разных тайлов тоже не сливаются, если ни один не обрезан швом.
+pub fn merge_tiles(per_tile: Vec<Vec<Value>>, tiles: &[(u32, u32)], opts: &TileOptions) -> Vec<Value> {
+    let page_bottom = tiles.iter().map(|(y, h)| y + h).max().unwrap_or(0) as f64;
+    let mut merged: Vec<Merged> = Vec::new();
+    let mut passthrough = Vec::new();
+    for (tile, items) in per_tile.into_iter().enumerate() {
+        let (top, bottom) = tiles.get(tile).map(|&(y, h)| (y as f64, (y + h) as f64)).unwrap_or((0.0, page_bottom));
+        for value in items {
+            let Some(c) = coords(&value) else {
+                passthrough.push(value);
+                continue;
+            };
+            let mut cuts = Vec::new();
+            if top > 0.0 && c[1] - top <= SEAM_EDGE {
+                cuts.push(top);
+            }
+            if bottom < page_bottom && bottom - c[3] <= SEAM_EDGE {
+                cuts.push(bottom);
+            }
+            merged.push(Merged { coords: c, value, tiles: vec![tile], cuts });
+        }
+    }
+
+    // a обрезан швом, b через этот шов продолжается и совпадает с a по ширине
+    let continues = |a: &Merged, b: &Merged| {
+        let crosses = a.cuts.iter().any(|&s| b.coords[1] < s - SEAM_EDGE && b.coords[3] > s + SEAM_EDGE);
+        let shared = a.coords[2].min(b.coords[2]) - a.coords[0].max(b.coords[0]);
+        let wider = (a.coords[2] - a.coords[0]).max(b.coords[2] - b.coords[0]).max(1.0);
+        crosses && shared / wider >= opts.seam_alignment
+    };
+    let same = |a: &Merged, b: &Merged| {
+        if a.tiles.iter().any(|t| b.tiles.contains(t)) || class_of(&a.value) != class_of(&b.value) {
+            return false;
+        }
+        let inter = intersection(&a.coords, &b.coords);
+        if inter <= 0.0 {
+            return false;
+        }
+        let union = area(&a.coords) + area(&b.coords) - inter;
+        inter / union >= opts.iou_threshold || continues(a, b) || continues(b, a)
+    };
+
+    // повторяем, пока есть что склеить (бокс может тянуться через несколько тайлов)
+    let mut changed = true;
+    while changed {
+        changed = false;
+        'outer: for i in 0..merged.len() {
+            for j in i + 1..merged.len() {
+                if same(&merged[i], &merged[j]) {
+                    let b = merged.swap_remove(j);
+                    let a = &mut merged[i];
+                    let c = [
+                        a.coords[0].min(b.coords[0]),
+                        a.coords[1].min(b.coords[1]),
+                        a.coords[2].max(b.coords[2]),
+                        a.coords[3].max(b.coords[3]),
+                    ];
+                    // прочие поля (score, class) — от большего куска
+                    if area(&b.coords) > area(&a.coords) {
+                        a.value = b.value;
+                    }
+                    a.coords = c;
+                    a.tiles.extend(b.tiles);
+                    // обрезанным остаётся только тот край, который так и не продолжился
+                    a.cuts.extend(b.cuts);
+                    a.cuts.retain(|&s| (c[1] - s).abs() <= SEAM_EDGE || (c[3] - s).abs() <= SEAM_EDGE);
+                    changed = true;
+                    break 'outer;
+                }
+            }
+        }
+    }
+
+    merged.sort_by(|a, b| a.coords[1].total_cmp(&b.coords[1]).then(a.coords[0].total_cmp(&b.coords[0])));
+    merged
+        .into_iter()
+        .map(|mut m| {
+            set_coords(&mut m.value, m.coords);
+            m.value
+        })
+        .chain(passthrough)
+        .collect()
+}
+
+// Сдвигает boxes/panels ответа тайла в координаты страницы
+pub fn offset(resp: &mut Value, key: &str, dy: f64) -> Vec<Value> {
+    let Some(list) = resp.get_mut(key).and_then(|l| l.as_array_mut()) else {
+        return Vec::new();
+    };
+    list.iter_mut()
+        .map(|v| {
+            if let Some(c) = coords(v) {
+                set_coords(v, [c[0], c[1] + dy, c[2], c[3] + dy]);
+            }
+            v.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn b(x1: f64, y1: f64, x2: f64, y2: f64) -> Value {
+        json!({ "x1": x1, "y1": y1, "x2": x2, "y2": y2 })
+    }
+
+    fn all_coords(list: &[Value]) -> Vec<[f64; 4]> {
+        list.iter().filter_map(coords).collect()
+    }
+
+    #[test]
+    fn short_page_is_one_tile() {
+        assert_eq!(plan_tiles(800, 1900, &TileOptions::default()), vec![(0, 1900)]);
+    }
+
+    #[test]
+    fn tiles_overlap_and_last_one_is_flush_with_bottom() {
+        // тайл 1200 (1.5 ширины), шаг не больше 1200 - 256
+        let plan = plan_tiles(800, 5000, &TileOptions::default());
+        assert_eq!(plan, vec![(0, 1200), (760, 1200), (1520, 1200), (2280, 1200), (3040, 1200), (3800, 1200)]);
+        for pair in plan.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert!(a.0 + a.1 >= b.0 + 256, "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn tile_height_and_overlap_are_bounded() {
+        let opts = TileOptions { tile_height: 100, overlap: 1000, ..Default::default() };
+        // тайл не ниже 256, перекрытие не больше половины тайла
+        let plan = plan_tiles(100, 700, &opts);
+        assert_eq!(plan, vec![(0, 256), (111, 256), (222, 256), (333, 256), (444, 256)]);
+    }
+
+    // два тайла: [0, 1200) и [944, 2144), шов первого — 1200, второго — 944
+    const TILES: [(u32, u32); 2] = [(0, 1200), (944, 1200)];
+
+    #[test]
+    fn duplicate_in_overlap_is_merged() {
+        let out = merge_tiles(
+            vec![vec![b(100.0, 1000.0, 300.0, 1100.0)], vec![b(102.0, 998.0, 301.0, 1102.0)]],
+            &TILES,
+            &TileOptions::default(),
+        );
+        assert_eq!(all_coords(&out), vec![[100.0, 998.0, 301.0, 1102.0]]);
+    }
+
+    #[test]
+    fn box_cut_by_seam_is_joined_with_its_continuation() {
+        // бабл 1100..1300: в первом тайле обрезан швом 1200, во втором виден целиком
+        let out = merge_tiles(
+            vec![vec![b(200.0, 1100.0, 400.0, 1199.0)], vec![b(198.0, 1100.0, 402.0, 1300.0)]],
+            &TILES,
+            &TileOptions::default(),
+        );
+        assert_eq!(all_coords(&out), vec![[198.0, 1100.0, 402.0, 1300.0]]);
+    }
+
+    #[test]
+    fn panel_taller_than_overlap_is_joined() {
+        // панель 600..1800 обрезана в обоих тайлах: снизу швом 1200, сверху швом 944
+        let out = merge_tiles(
+            vec![vec![b(0.0, 600.0, 800.0, 1200.0)], vec![b(0.0, 944.0, 800.0, 1800.0)]],
+            &TILES,
+            &TileOptions::default(),
+        );
+        assert_eq!(all_coords(&out), vec![[0.0, 600.0, 800.0, 1800.0]]);
+    }
+
+    #[test]
+    fn nested_inset_panel_is_kept() {
+        // большая панель через шов и маленькая вставка внутри неё во втором тайле
+        let out = merge_tiles(
+            vec![
+                vec![b(0.0, 600.0, 800.0, 1200.0)],
+                vec![b(0.0, 944.0, 800.0, 1800.0), b(450.0, 1000.0, 750.0, 1300.0)],
+            ],
+            &TILES,
+            &TileOptions::default(),
+        );
+        assert_eq!(all_coords(&out), vec![[0.0, 600.0, 800.0, 1800.0], [450.0, 1000.0, 750.0, 1300.0]]);
+    }
+
+    #[test]
+    fn nested_boxes_away_from_seams_are_kept() {
+        // оба бокса целиком в зоне перекрытия, один внутри другого — это разные объекты
+        let out = merge_tiles(
+            vec![vec![b(100.0, 960.0, 500.0, 1180.0)], vec![b(150.0, 1000.0, 300.0, 1100.0)]],
+            &TILES,
+            &TileOptions::default(),
+        );
+        assert_eq!(out.len(), 2);
+    }
+
+    #[test]
+    fn boxes_of_one_tile_or_different_class_are_kept() {
+        let out = merge_tiles(
+            vec![vec![b(100.0, 100.0, 300.0, 200.0), b(102.0, 102.0, 302.0, 202.0)]],
+            &TILES,
+            &TileOptions::default(),
+        );
+        assert_eq!(out.len(), 2);
+
+        let mut text = b(100.0, 1000.0, 300.0, 1100.0);
+        text["class"] = json!("text");
+        let mut bubble = b(100.0, 1000.0, 300.0, 1100.0);
+        bubble["class"] = json!("bubble");
+        assert_eq!(merge_tiles(vec![vec![text], vec![bubble]], &TILES, &TileOptions::default()).len(), 2);
+    }
+
+    #[test]
+    fn offset_shifts_boxes_and_array_panels() {
+        let mut resp = json!({ "boxes": [b(1.0, 2.0, 3.0, 4.0)], "panels": [[0.0, 10.0, 50.0, 60.0]] });
+        assert_eq!(all_coords(&offset(&mut resp, "boxes", 944.0)), vec![[1.0, 946.0, 3.0, 948.0]]);
+        assert_eq!(all_coords(&offset(&mut resp, "panels", 944.0)), vec![[0.0, 954.0, 50.0, 1004.0]]);
+    }
+}
@@ -0,0 +1,228 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use reqwest::multipart::{Form, Part};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+// Как отправлять картинки на эндпоинт. JSON с base64 понимают все серверы,
+// multipart и сырые байты — только если сервер их объявил (capabilities / openapi).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Json,
+    Multipart,
+    Raw,
+}
+
+impl Transport {
+    fn from_content_type(ct: &str) -> Option<Transport> {
+        let ct = ct.split(';').next().unwrap_or(ct).trim().to_ascii_lowercase();
+        match ct.as_str() {
+            "application/json" => Some(Transport::Json),
+            "multipart/form-data" => Some(Transport::Multipart),
+            "application/octet-stream" => Some(Transport::Raw),
+            _ if ct.starts_with("image/") => Some(Transport::Raw),
+            _ => None,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Transport> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(Transport::Json),
+            "multipart" | "form" => Some(Transport::Multipart),
+            "raw" | "binary" | "bytes" => Some(Transport::Raw),
+            other => Transport::from_content_type(other),
+        }
+    }
+}
+
+// Из предложенного сервером выбираем самый компактный: multipart подходит для любых запросов,
+// raw — только для одной картинки (остальное уходит в query), поэтому он второй
+pub fn negotiate(offered: &[Transport]) -> Transport {
+    [Transport::Multipart, Transport::Raw]
+        .into_iter()
+        .find(|t| offered.contains(t))
+        .unwrap_or(Transport::Json)
+}
+
+// /capabilities: { "transports": { "detect_text_areas": ["multipart", "json"], "inpaint_lama": "raw" } }
+pub fn from_capabilities(doc: &Value) -> BTreeMap<String, Vec<Transport>> {
+    let mut out = BTreeMap::new();
+    if let Some(map) = doc.get("transports").and_then(|t| t.as_object()) {
+        for (name, v) in map {
+            let offered: Vec<Transport> = match v {
+                Value::String(s) => Transport::from_name(s).into_iter().collect(),
+                Value::Array(a) => a.iter().filter_map(|s| s.as_str()).filter_map(Transport::from_name).collect(),
+                _ => Vec::new(),
+            };
+            out.insert(name.trim_start_matches('/').to_string(), offered);
+        }
+    }
+    out
+}
+
+// FastAPI: типы тела запроса из paths./x.post.requestBody.content
+pub fn from_openapi(spec: &Value) -> BTreeMap<String, Vec<Transport>> {
+    let mut out = BTreeMap::new();
+    if let Some(paths) = spec.get("paths").and_then(|p| p.as_object()) {
+        for (path, ops) in paths {
+            let Some(content) = ops.pointer("/post/requestBody/content").and_then(|c| c.as_object()) else {
+                continue;
+            };
+            let offered = content.keys().filter_map(|ct| Transport::from_content_type(ct)).collect();
+            out.insert(path.trim_start_matches('/').to_string(), offered);
+        }
+    }
+    out
+}
+
+// Выбранный транспорт для (узел, эндпоинт). Чего нет в таблице — JSON.
+#[derive(Default)]
+pub struct TransportTable {
+    by_node: Mutex<HashMap<String, BTreeMap<String, Transport>>>,
+}
+
+impl TransportTable {
+    pub fn set(&self, base: &str, table: BTreeMap<String, Transport>) {
+        self.by_node.lock().unwrap().insert(base.trim_end_matches('/').to_string(), table);
+    }
+
+    pub fn get(&self, base: &str, path: &str) -> Transport {
+        self.by_node
+            .lock()
+            .unwrap()
+            .get(base.trim_end_matches('/'))
+            .and_then(|t| t.get(path).copied())
+            .unwrap_or_default()
+    }
+
+    // Сервер не принял бинарный запрос — до следующего опроса шлём JSON
+    pub fn downgrade(&self, base: &str, path: &str) {
+        if let Some(t) = self.by_node.lock().unwrap().get_mut(base.trim_end_matches('/')) {
+            t.insert(path.to_string(), Transport::Json);
+        }
+    }
+}
+
+// Поля с картинками в запросах manga-translator-api: image_data, mask_data, images_data
+fn is_binary_field(key: &str) -> bool {
+    key.ends_with("_data")
+}
+
+fn decode(b64: &str) -> Option<Vec<u8>> {
+    let b64 = b64.split_once(',').map(|(_, b)| b).unwrap_or(b64);
+    STANDARD.decode(b64).ok()
+}
+
+fn file_part(key: &str, bytes: Vec<u8>) -> Part {
+    let format = image::guess_format(&bytes).ok();
+    let ext = format.and_then(|f| f.extensions_str().first().copied()).unwrap_or("bin");
+    let mime = format.map(|f| f.to_mime_type()).unwrap_or("application/octet-stream");
+    let name = key.trim_end_matches("_data");
+    Part::bytes(bytes)
+        .file_name(format!("{}.{}", name, ext))
+        .mime_str(mime)
+        .expect("static mime type")
+}
+
+// Скаляры — как есть, вложенные объекты/массивы — JSON-строкой
+fn text_value(v: &Value) -> Option<String> {
+    match v {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        _ => Some(v.to_string()),
+    }
+}
+
+fn multipart(payload: &Map<String, Value>) -> Form {
+    let mut form = Form::new();
+    for (key, value) in payload {
+        match value {
+            Value::String(s) if is_binary_field(key) => match decode(s) {
+                Some(bytes) => form = form.part(key.clone(), file_part(key, bytes)),
+                None => form = form.text(key.clone(), s.clone()),
+            },
+            // images_data: несколько файлов под одним именем
+            Value::Array(items) if is_binary_field(key) && items.iter().all(|i| i.is_string()) => {
+                for item in items.iter().filter_map(|i| i.as_str()) {
+                    form = match decode(item) {
+                        Some(bytes) => form.part(key.clone(), file_part(key, bytes)),
+                        None => form.text(key.clone(), item.to_string()),
+                    };
+                }
+            }
+            _ => {
+                if let Some(text) = text_value(value) {
+                    form = form.text(key.clone(), text);
+                }
+            }
+        }
+    }
+    form
+}
+
+type Query = Vec<(String, String)>;
+
+// Одна картинка в теле, остальные параметры — в query. None, если картинок не одна.
+fn raw(payload: &Map<String, Value>) -> Option<(Vec<u8>, Query)> {
+    let mut binary = payload.iter().filter(|(k, v)| is_binary_field(k) && !v.is_null());
+    let (_, image) = binary.next()?;
+    if binary.next().is_some() {
+        return None;
+    }
+    let bytes = decode(image.as_str()?)?;
+    let query = payload
+        .iter()
+        .filter(|(k, _)| !is_binary_field(k))
+        .filter_map(|(k, v)| text_value(v).map(|t| (k.clone(), t)))
+        .collect();
+    Some((bytes, query))
+}
+
+// Кладёт payload в запрос выбранным транспортом; если не получается — обычный JSON.
+// Возвращает фактически использованный транспорт.
+pub fn apply(req: RequestBuilder, transport: Transport, payload: &Value) -> (RequestBuilder, Transport) {
+    let Some(map) = payload.as_object() else {
+        return (req.json(payload), Transport::Json);
+    };
+    // бинарные ответы тоже принимаем: read_response превратит их в base64
+    let accept = "application/json, image/png;q=0.9, image/*;q=0.8";
+    match transport {
+        Transport::Json => (req.json(payload), Transport::Json),
+        Transport::Multipart => (req.header("Accept", accept).multipart(multipart(map)), Transport::Multipart),
+        Transport::Raw => match raw(map) {
+            Some((bytes, query)) => {
+                let mime = image::guess_format(&bytes)
+                    .map(|f| f.to_mime_type())
+                    .unwrap_or("application/octet-stream");
+                (
+                    req.header("Accept", accept)
+                        .header("Content-Type", mime)
+                        .query(&query)
+                        .body(bytes),
+                    Transport::Raw,
+                )
+            }
+            None => (req.json(payload), Transport::Json),
+        },
+    }
+}
+
+// JSON как раньше; картинку в ответе отдаём в том же виде, что JSON-эндпоинты: { "image_data": base64 }
+pub async fn read_response(response: reqwest::Response) -> Result<Value, String> {
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let binary = content_type.starts_with("image/") || content_type.starts_with("application/octet-stream");
+    if !binary || !response.status().is_success() {
+        return super::handle_response(response).await;
+    }
+    let bytes = response.bytes().await.map_err(|e| format!("Failed to read image response: {}", e))?;
+    Ok(serde_json::json!({ "image_data": STANDARD.encode(bytes) }))
+}
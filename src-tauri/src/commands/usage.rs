@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use tauri::{command, State};
+
+// Ключ проекта, если фронт его не передал
+pub const DEFAULT_PROJECT: &str = "default";
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub requests: u64,
+    pub cost: f64,
+}
+
+impl TokenUsage {
+    fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.requests += other.requests;
+        self.cost += other.cost;
+    }
+}
+
+// Цена за 1M токенов, в валюте, которую выбрал пользователь
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+}
+
+// К чему относится запрос: проект / страница / запуск (process all, ручной перевод)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageContext {
+    pub project_id: Option<String>,
+    pub page: Option<String>,
+    pub run_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectUsage {
+    pub total: TokenUsage,
+    pub by_page: BTreeMap<String, TokenUsage>,
+    pub by_run: BTreeMap<String, TokenUsage>,
+    pub by_model: BTreeMap<String, TokenUsage>,
+}
+
+#[derive(Default)]
+struct LedgerInner {
+    prices: HashMap<String, ModelPrice>,
+    projects: HashMap<String, ProjectUsage>,
+}
+
+#[derive(Default)]
+pub struct UsageLedger(Mutex<LedgerInner>);
+
+impl UsageLedger {
+    pub fn record(&self, ctx: Option<&UsageContext>, model: &str, prompt_tokens: u64, completion_tokens: u64) {
+        let mut inner = self.0.lock().unwrap();
+        let price = inner.prices.get(model).copied().unwrap_or_default();
+        let entry = TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            requests: 1,
+            cost: prompt_tokens as f64 / 1_000_000.0 * price.prompt_per_million
+                + completion_tokens as f64 / 1_000_000.0 * price.completion_per_million,
+        };
+
+        let project_id = ctx
+            .and_then(|c| c.project_id.clone())
+            .unwrap_or_else(|| DEFAULT_PROJECT.to_string());
+        let project = inner.projects.entry(project_id).or_default();
+        project.total.add(&entry);
+        project.by_model.entry(model.to_string()).or_default().add(&entry);
+        if let Some(page) = ctx.and_then(|c| c.page.as_ref()) {
+            project.by_page.entry(page.clone()).or_default().add(&entry);
+        }
+        if let Some(run) = ctx.and_then(|c| c.run_id.as_ref()) {
+            project.by_run.entry(run.clone()).or_default().add(&entry);
+        }
+    }
+
+    // Учёт по JSON-ответу (или usage-чанку стрима) в формате OpenAI
+    pub fn record_response(&self, ctx: Option<&UsageContext>, fallback_model: &str, response: &Value) -> bool {
+        let Some((prompt, completion)) = parse_usage(response) else {
+            return false;
+        };
+        let model = response
+            .get("model")
+            .and_then(|m| m.as_str())
+            .filter(|m| !m.is_empty())
+            .unwrap_or(fallback_model);
+        self.record(ctx, model, prompt, completion);
+        true
+    }
+
+    pub fn project(&self, project_id: &str) -> Option<ProjectUsage> {
+        self.0.lock().unwrap().projects.get(project_id).cloned()
+    }
+
+    pub fn restore(&self, project_id: &str, usage: ProjectUsage) {
+        self.0.lock().unwrap().projects.insert(project_id.to_string(), usage);
+    }
+}
+
+// { "usage": { "prompt_tokens": .., "completion_tokens": .. } }; у некоторых серверов input/output_tokens
+pub fn parse_usage(response: &Value) -> Option<(u64, u64)> {
+    let usage = response.get("usage").filter(|u| u.is_object())?;
+    let field = |a: &str, b: &str| {
+        usage
+            .get(a)
+            .or_else(|| usage.get(b))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0)
+    };
+    Some((
+        field("prompt_tokens", "input_tokens"),
+        field("completion_tokens", "output_tokens"),
+    ))
+}
+
+// projectId из metadata проекта (project.json)
+pub fn project_id_of(project_data: &Value) -> String {
+    project_data
+        .get("metadata")
+        .and_then(|m| m.get("projectId"))
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .unwrap_or(DEFAULT_PROJECT)
+        .to_string()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    pub project_id: String,
+    pub usage: ProjectUsage,
+    pub prices: HashMap<String, ModelPrice>,
+}
+
+#[command]
+pub async fn get_usage_report(
+    ledger: State<'_, UsageLedger>,
+    project_id: Option<String>,
+) -> Result<UsageReport, String> {
+    let project_id = project_id.unwrap_or_else(|| DEFAULT_PROJECT.to_string());
+    let inner = ledger.0.lock().map_err(|e| e.to_string())?;
+    Ok(UsageReport {
+        usage: inner.projects.get(&project_id).cloned().unwrap_or_default(),
+        prices: inner.prices.clone(),
+        project_id,
+    })
+}
+
+#[command]
+pub async fn set_model_prices(
+    ledger: State<'_, UsageLedger>,
+    prices: HashMap<String, ModelPrice>,
+) -> Result<(), String> {
+    ledger.0.lock().map_err(|e| e.to_string())?.prices = prices;
+    Ok(())
+}
+
+#[command]
+pub async fn reset_usage(ledger: State<'_, UsageLedger>, project_id: Option<String>) -> Result<(), String> {
+    let project_id = project_id.unwrap_or_else(|| DEFAULT_PROJECT.to_string());
+    ledger.0.lock().map_err(|e| e.to_string())?.projects.remove(&project_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn openai_usage() {
+        let resp = json!({ "usage": { "prompt_tokens": 120, "completion_tokens": 45, "total_tokens": 165 } });
+        assert_eq!(parse_usage(&resp), Some((120, 45)));
+    }
+
+    #[test]
+    fn input_output_tokens_usage() {
+        // Anthropic-совместимые прокси и часть локальных серверов
+        let resp = json!({ "usage": { "input_tokens": 300, "output_tokens": 80 } });
+        assert_eq!(parse_usage(&resp), Some((300, 80)));
+    }
+
+    #[test]
+    fn partial_and_missing_usage() {
+        // стрим: usage-чанк без completion_tokens
+        assert_eq!(parse_usage(&json!({ "usage": { "prompt_tokens": 10 } })), Some((10, 0)));
+        assert_eq!(parse_usage(&json!({ "usage": null })), None);
+        assert_eq!(parse_usage(&json!({ "choices": [] })), None);
+    }
+
+    #[test]
+    fn records_cost_by_page_run_and_model() {
+        let ledger = UsageLedger::default();
+        ledger.0.lock().unwrap().prices.insert(
+            "gpt-x".to_string(),
+            ModelPrice { prompt_per_million: 2.0, completion_per_million: 8.0 },
+        );
+        let ctx = UsageContext {
+            project_id: Some("p1".to_string()),
+            page: Some("001.png".to_string()),
+            run_id: Some("run-1".to_string()),
+        };
+        let resp = json!({ "model": "gpt-x", "usage": { "prompt_tokens": 500_000, "completion_tokens": 250_000 } });
+        assert!(ledger.record_response(Some(&ctx), "fallback", &resp));
+        // без usage ничего не пишем
+        assert!(!ledger.record_response(Some(&ctx), "gpt-x", &json!({})));
+
+        let usage = ledger.project("p1").unwrap();
+        assert_eq!(usage.total.requests, 1);
+        assert!((usage.total.cost - 3.0).abs() < 1e-9);
+        assert_eq!(usage.by_page["001.png"].prompt_tokens, 500_000);
+        assert_eq!(usage.by_run["run-1"].completion_tokens, 250_000);
+        assert!(usage.by_model.contains_key("gpt-x"));
+    }
+
+    #[test]
+    fn fallback_model_and_default_project() {
+        let ledger = UsageLedger::default();
+        let resp = json!({ "model": "", "usage": { "prompt_tokens": 1, "completion_tokens": 2 } });
+        assert!(ledger.record_response(None, "local-model", &resp));
+        let usage = ledger.project(DEFAULT_PROJECT).unwrap();
+        assert!(usage.by_model.contains_key("local-model"));
+        assert_eq!(usage.total.cost, 0.0);
+    }
+}
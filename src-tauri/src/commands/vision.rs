@@ -0,0 +1,115 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::imageops::FilterType;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::commands::BoundingBox;
+
+const DEFAULT_MAX_SIDE: u32 = 1536;
+const DEFAULT_QUALITY: u8 = 85;
+
+// Контекст страницы для vision-моделей: оригинал на диске + пронумерованные строки OCR
+#[derive(Debug, Deserialize)]
+pub struct VisionContext {
+    #[serde(rename = "imagePath")]
+    pub image_path: String,
+    pub lines: Vec<VisionLine>,
+    #[serde(rename = "maxSide")]
+    pub max_side: Option<u32>,
+    pub quality: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VisionLine {
+    pub id: u32,
+    pub text: String,
+    #[serde(rename = "box")]
+    pub bbox: BoundingBox,
+}
+
+pub struct EncodedPage {
+    pub data_url: String,
+    pub width: u32,
+    pub height: u32,
+    // во сколько раз уменьшили оригинал (<= 1.0)
+    pub scale: f64,
+}
+
+// Читает оригинал с диска, уменьшает до max_side по длинной стороне и кодирует в JPEG
+pub fn encode_page(path: &str, max_side: u32, quality: u8) -> Result<EncodedPage, String> {
+    let img = image::open(path).map_err(|e| format!("Failed to open page image: {}", e))?;
+    let (w, h) = (img.width(), img.height());
+    let longest = w.max(h).max(1);
+
+    let img = if longest > max_side {
+        img.resize(max_side, max_side, FilterType::Triangle)
+    } else {
+        img
+    };
+    let scale = img.width() as f64 / w.max(1) as f64;
+
+    let rgb = img.to_rgb8();
+    let mut buf = Vec::new();
+    let mut enc = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf, quality);
+    enc.encode(&rgb, rgb.width(), rgb.height(), image::ColorType::Rgb8)
+        .map_err(|e| format!("Failed to encode page image: {}", e))?;
+
+    Ok(EncodedPage {
+        data_url: format!("data:image/jpeg;base64,{}", STANDARD.encode(&buf)),
+        width: rgb.width(),
+        height: rgb.height(),
+        scale,
+    })
+}
+
+// Текст для пользовательского сообщения: строки с боксами в координатах уменьшенной картинки
+fn numbered_lines(ctx: &VisionContext, page: &EncodedPage) -> String {
+    let mut out = format!(
+        "The page image is attached ({}x{} px). Each line below has the speech bubble box \
+         [x1, y1, x2, y2] in pixels of the attached image.\n",
+        page.width, page.height
+    );
+    for line in &ctx.lines {
+        let b = line.bbox.scaled(page.scale);
+        out.push_str(&format!(
+            "{}. [{}, {}, {}, {}] {}\n",
+            line.id,
+            b.x1.round(),
+            b.y1.round(),
+            b.x2.round(),
+            b.y2.round(),
+            line.text.trim()
+        ));
+    }
+    out
+}
+
+// Заменяет content последнего user-сообщения на мультимодальный (text + image_url)
+pub fn apply_vision_context(payload: &mut Value, ctx: &VisionContext) -> Result<(), String> {
+    let page = encode_page(
+        &ctx.image_path,
+        ctx.max_side.unwrap_or(DEFAULT_MAX_SIDE),
+        ctx.quality.unwrap_or(DEFAULT_QUALITY).clamp(1, 100),
+    )?;
+    let text = numbered_lines(ctx, &page);
+
+    let messages = payload
+        .get_mut("messages")
+        .and_then(|m| m.as_array_mut())
+        .ok_or_else(|| "Payload has no messages".to_string())?;
+
+    let content = serde_json::json!([
+        { "type": "text", "text": text },
+        { "type": "image_url", "image_url": { "url": page.data_url } }
+    ]);
+
+    match messages
+        .iter_mut()
+        .rev()
+        .find(|m| m.get("role").and_then(|r| r.as_str()) == Some("user"))
+    {
+        Some(user) => user["content"] = content,
+        None => messages.push(serde_json::json!({ "role": "user", "content": content })),
+    }
+    Ok(())
+}
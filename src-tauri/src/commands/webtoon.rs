@@ -0,0 +1,427 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::{imageops, imageops::FilterType, DynamicImage, GenericImageView, RgbaImage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{command, AppHandle};
+
+use crate::commands::jobs;
+use crate::commands::regions::load_page_image;
+
+// Как резать склеенную ленту вебтуна
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SliceOptions {
+    // желаемая высота куска, px
+    pub target_height: u32,
+    // гуттер ищем в пределах target_height * (1 ± search)
+    pub search: f64,
+    // строка "пустая", если разброс яркости в ней не больше tolerance
+    pub tolerance: u8,
+    // минимальная высота гуттера, px
+    pub min_gutter: u32,
+}
+
+impl Default for SliceOptions {
+    fn default() -> Self {
+        SliceOptions {
+            target_height: 2000,
+            search: 0.25,
+            tolerance: 10,
+            min_gutter: 4,
+        }
+    }
+}
+
+// Для каждой строки: однотонная ли она (белый/чёрный гуттер между кадрами)
+pub fn flat_rows(img: &RgbaImage, tolerance: u8) -> Vec<bool> {
+    img.rows()
+        .map(|row| {
+            let (mut lo, mut hi) = (u8::MAX, u8::MIN);
+            for p in row {
+                let [r, g, b, _] = p.0;
+                let luma = ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8;
+                lo = lo.min(luma);
+                hi = hi.max(luma);
+            }
+            hi.saturating_sub(lo) <= tolerance
+        })
+        .collect()
+}
+
+// Места разрезов (без 0 и height). Режем по середине гуттера, ближайшего к целевой высоте;
+// через бокс не режем никогда, если есть хоть одна свободная строка в окне.
+pub fn plan_cuts(height: u32, flat: &[bool], boxes: &[(f64, f64)], opts: &SliceOptions) -> Vec<u32> {
+    let target = opts.target_height.max(64) as f64;
+    // опции приходят с фронта как есть: окно поиска не должно схлопываться в start
+    let search = if opts.search.is_finite() { opts.search.clamp(0.0, 0.9) } else { SliceOptions::default().search };
+    let min_gutter = opts.min_gutter.max(1);
+    let blocked = |y: u32| boxes.iter().any(|&(y1, y2)| y1 < y as f64 && (y as f64) < y2);
+    let mut cuts = Vec::new();
+    let mut start = 0u32;
+
+    while (height - start) as f64 > target * (1.0 + search) {
+        let ideal = start + target as u32;
+        let lo = start + (target * (1.0 - search)).max(1.0) as u32;
+        let hi = (start + (target * (1.0 + search)) as u32).min(height - 1);
+        let dist = |y: u32| (y as i64 - ideal as i64).unsigned_abs();
+
+        // середины гуттеров в окне
+        let mut best: Option<u32> = None;
+        let mut run_start: Option<u32> = None;
+        for y in lo..=hi + 1 {
+            let is_flat = y <= hi && flat.get(y as usize).copied().unwrap_or(false);
+            match (is_flat, run_start) {
+                (true, None) => run_start = Some(y),
+                (false, Some(s)) => {
+                    let mid = s + (y - s) / 2;
+                    if y - s >= min_gutter && !blocked(mid) && best.is_none_or(|b| dist(mid) < dist(b)) {
+                        best = Some(mid);
+                    }
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+        // гуттера нет — любая строка вне боксов, иначе режем как есть
+        let cut = best
+            .or_else(|| (lo..=hi).filter(|&y| !blocked(y)).min_by_key(|&y| dist(y)))
+            .unwrap_or(ideal.min(hi));
+        assert!(cut > start, "slice cut must advance");
+        cuts.push(cut);
+        start = cut;
+    }
+    cuts
+}
+
+// Склеивает страницы по вертикали, приводя к ширине первой.
+// Возвращает ленту и (сдвиг по y, масштаб) каждой страницы.
+fn stitch(pages: &[DynamicImage]) -> (RgbaImage, Vec<(u32, f64)>) {
+    let width = pages.first().map(|p| p.width()).unwrap_or(1);
+    let scaled: Vec<(RgbaImage, f64)> = pages
+        .iter()
+        .map(|p| {
+            if p.width() == width {
+                (p.to_rgba8(), 1.0)
+            } else {
+                let k = width as f64 / p.width() as f64;
+                let h = ((p.height() as f64 * k).round() as u32).max(1);
+                (p.resize_exact(width, h, FilterType::Lanczos3).to_rgba8(), k)
+            }
+        })
+        .collect();
+    let height = scaled.iter().map(|(img, _)| img.height()).sum::<u32>().max(1);
+    let mut strip = RgbaImage::new(width, height);
+    let mut placement = Vec::with_capacity(scaled.len());
+    let mut y = 0;
+    for (img, k) in scaled {
+        imageops::replace(&mut strip, &img, 0, y as i64);
+        placement.push((y, k));
+        y += img.height();
+    }
+    (strip, placement)
+}
+
+fn bounds(cuts: &[u32], height: u32) -> Vec<(u32, u32)> {
+    let mut edges = vec![0];
+    edges.extend_from_slice(cuts);
+    edges.push(height);
+    edges.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+fn png_bytes(img: &RgbaImage) -> Result<Vec<u8>, String> {
+    let mut buf = std::io::Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(img.clone())
+        .write_to(&mut buf, image::ImageOutputFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(buf.into_inner())
+}
+
+fn item_box(item: &Value) -> Option<[f64; 4]> {
+    let b = item.get("box")?;
+    Some([b.get("x1")?.as_f64()?, b.get("y1")?.as_f64()?, b.get("x2")?.as_f64()?, b.get("y2")?.as_f64()?])
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripPage {
+    pub path: String,
+    // у temp:// страниц файла нет
+    pub data_url: Option<String>,
+    // DetectedTextItem как есть; нужны только box и id
+    #[serde(default)]
+    pub items: Vec<Value>,
+    pub mask_data_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripSlice {
+    pub name: String,
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub items: Vec<Value>,
+    pub mask_data_url: Option<String>,
+}
+
+fn restitch(pages: Vec<StripPage>, opts: &SliceOptions, out_dir: &Path) -> Result<Vec<StripSlice>, String> {
+    let images = pages
+        .iter()
+        .map(|p| load_page_image(&p.path, p.data_url.as_deref()))
+        .collect::<Result<Vec<_>, String>>()?;
+    let (strip, placement) = stitch(&images);
+    let (width, height) = strip.dimensions();
+
+    // боксы и маски — в координаты ленты
+    let mut items: Vec<(Value, [f64; 4])> = Vec::new();
+    let mut mask: Option<RgbaImage> = None;
+    for ((page, img), &(dy, k)) in pages.iter().zip(&images).zip(&placement) {
+        for item in &page.items {
+            if let Some(b) = item_box(item) {
+                let b = [b[0] * k, b[1] * k + dy as f64, b[2] * k, b[3] * k + dy as f64];
+                items.push((item.clone(), b));
+            }
+        }
+        if let Some(m) = page.mask_data_url.as_deref().filter(|m| !m.is_empty()) {
+            let m = load_page_image(&page.path, Some(m))?
+                .resize_exact(width, ((img.height() as f64 * k).round() as u32).max(1), FilterType::Nearest)
+                .to_rgba8();
+            imageops::overlay(mask.get_or_insert_with(|| RgbaImage::new(width, height)), &m, 0, dy as i64);
+        }
+    }
+
+    let spans: Vec<(f64, f64)> = items.iter().map(|(_, b)| (b[1], b[3])).collect();
+    let cuts = plan_cuts(height, &flat_rows(&strip, opts.tolerance), &spans, opts);
+
+    std::fs::create_dir_all(out_dir).map_err(|e| e.to_string())?;
+    let mut slices = Vec::new();
+    for (n, (y0, y1)) in bounds(&cuts, height).into_iter().enumerate() {
+        let h = y1 - y0;
+        let name = format!("slice_{:03}.png", n + 1);
+        let path = out_dir.join(&name);
+        let piece = strip.view(0, y0, width, h).to_image();
+        std::fs::write(&path, png_bytes(&piece)?).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        // бокс уходит в кусок, где его центр; id перенумеровываем внутри куска
+        let mut slice_items = Vec::new();
+        for (item, b) in &items {
+            let cy = (b[1] + b[3]) / 2.0;
+            if cy < y0 as f64 || cy >= y1 as f64 {
+                continue;
+            }
+            let mut item = item.clone();
+            item["box"] = serde_json::json!({
+                "x1": b[0],
+                "y1": (b[1] - y0 as f64).max(0.0),
+                "x2": b[2],
+                "y2": (b[3] - y0 as f64).min(h as f64),
+            });
+            item["id"] = Value::from(slice_items.len() + 1);
+            // пиксели кропа те же, что и были, — хэши от старого пути не нужны
+            if let Some(obj) = item.as_object_mut() {
+                obj.remove("stageHashes");
+            }
+            slice_items.push(item);
+        }
+
+        let mask_data_url = match &mask {
+            Some(m) => {
+                let piece = m.view(0, y0, width, h).to_image();
+                piece
+                    .pixels()
+                    .any(|p| p.0[3] > 0)
+                    .then(|| png_bytes(&piece).map(|b| format!("data:image/png;base64,{}", STANDARD.encode(b))))
+                    .transpose()?
+            }
+            None => None,
+        };
+
+        slices.push(StripSlice {
+            name,
+            path: path.to_string_lossy().to_string(),
+            width,
+            height: h,
+            items: slice_items,
+            mask_data_url,
+        });
+    }
+    Ok(slices)
+}
+
+// Склеивает нарезку главы в одну ленту и режет заново по гуттерам.
+// Куски пишутся в workspace проекта: <app data>/workspace/<projectId>/strips/<время>/
+#[command]
+pub async fn restitch_strip(
+    app: AppHandle,
+    pages: Vec<StripPage>,
+    options: Option<SliceOptions>,
+    project_id: Option<String>,
+) -> Result<Vec<StripSlice>, String> {
+    if pages.is_empty() {
+        return Ok(Vec::new());
+    }
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let out_dir = jobs::project_dir(&app, project_id.as_deref())
+        .ok_or("No app data directory")?
+        .join("strips")
+        .join(stamp.to_string());
+    let opts = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || restitch(pages, &opts, &out_dir))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Для экспорта: склеить готовые страницы и нарезать под требования площадки
+pub fn reslice_pngs(pages: &[Vec<u8>], opts: &SliceOptions) -> Result<Vec<Vec<u8>>, String> {
+    let images = pages
+        .iter()
+        .map(|b| image::load_from_memory(b).map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, String>>()?;
+    let (strip, _) = stitch(&images);
+    let cuts = plan_cuts(strip.height(), &flat_rows(&strip, opts.tolerance), &[], opts);
+    bounds(&cuts, strip.height())
+        .into_iter()
+        .map(|(y0, y1)| png_bytes(&strip.view(0, y0, strip.width(), y1 - y0).to_image()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    // Страница: в каждой строке левая половина чёрная, правая белая (не гуттер),
+    // кроме строк gutter — они белые целиком
+    fn page(w: u32, h: u32, gutter: std::ops::Range<u32>) -> RgbaImage {
+        RgbaImage::from_fn(w, h, |x, y| if gutter.contains(&y) || x >= w / 2 { WHITE } else { BLACK })
+    }
+
+    fn data_url(img: &RgbaImage) -> String {
+        format!("data:image/png;base64,{}", STANDARD.encode(png_bytes(img).unwrap()))
+    }
+
+    fn opts(target_height: u32) -> SliceOptions {
+        SliceOptions { target_height, ..Default::default() }
+    }
+
+    // гуттеры — полуинтервалы строк [от, до)
+    fn flat_at(height: u32, gutters: &[(u32, u32)]) -> Vec<bool> {
+        (0..height).map(|y| gutters.iter().any(|&(a, b)| (a..b).contains(&y))).collect()
+    }
+
+    #[test]
+    fn short_strip_is_not_cut() {
+        assert!(plan_cuts(2400, &flat_at(2400, &[]), &[], &opts(2000)).is_empty());
+    }
+
+    #[test]
+    fn cuts_in_middle_of_nearest_gutter() {
+        let flat = flat_at(5000, &[(1600, 1620), (1980, 2000), (2300, 2400)]);
+        assert_eq!(plan_cuts(5000, &flat, &[], &opts(2000)), vec![1990, 3990]);
+    }
+
+    #[test]
+    fn thin_gutter_is_ignored() {
+        let flat = flat_at(3000, &[(1990, 1992), (2200, 2220)]);
+        assert_eq!(plan_cuts(3000, &flat, &[], &opts(2000)), vec![2210]);
+    }
+
+    #[test]
+    fn never_cuts_through_box_when_row_is_free() {
+        // гуттера нет; бокс накрывает идеальное место — режем у его края
+        let cuts = plan_cuts(3000, &flat_at(3000, &[]), &[(1900.0, 2100.0)], &opts(2000));
+        assert_eq!(cuts, vec![1900]);
+        // гуттер внутри бокса не годится
+        let flat = flat_at(3000, &[(1990, 2010)]);
+        assert_eq!(plan_cuts(3000, &flat, &[(1900.0, 2100.0)], &opts(2000)), vec![1900]);
+    }
+
+    #[test]
+    fn bad_search_and_min_gutter_still_advance() {
+        let flat = flat_at(10_000, &[]);
+        for search in [-1.0, -5.0, f64::NAN, f64::INFINITY, 3.0] {
+            let o = SliceOptions { search, min_gutter: 0, ..opts(2000) };
+            let cuts = plan_cuts(10_000, &flat, &[], &o);
+            assert!(!cuts.is_empty() && cuts.len() < 10, "search {}: {:?}", search, cuts);
+            assert!(cuts.windows(2).all(|w| w[0] < w[1]));
+            assert!(cuts.iter().all(|&c| c > 0 && c < 10_000));
+        }
+    }
+
+    #[test]
+    fn restitch_remaps_boxes_and_masks() {
+        let out_dir = std::env::temp_dir().join(format!("restitch-{}", std::process::id()));
+        // страница B вдвое уже — растягивается в ленте с k = 2
+        let a = page(100, 300, 280..300);
+        let b = page(50, 150, 0..0);
+        let mut mask = RgbaImage::new(50, 150);
+        for y in 10..20 {
+            for x in 5..10 {
+                mask.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            }
+        }
+        let item = |id: u32, x1: f64, y1: f64, x2: f64, y2: f64| {
+            serde_json::json!({ "id": id, "box": { "x1": x1, "y1": y1, "x2": x2, "y2": y2 }, "stageHashes": { "ocr": "h" } })
+        };
+        let pages = vec![
+            StripPage {
+                path: "a.png".to_string(),
+                data_url: Some(data_url(&a)),
+                // боксы 2 и 3 накрывают всё окно поиска (225, 375) — разрез пройдёт через них
+                items: vec![
+                    item(1, 10.0, 100.0, 20.0, 120.0),
+                    item(2, 30.0, 200.0, 40.0, 376.0),
+                    item(3, 50.0, 220.0, 60.0, 380.0),
+                ],
+                mask_data_url: None,
+            },
+            StripPage {
+                path: "b.png".to_string(),
+                data_url: Some(data_url(&b)),
+                items: vec![item(1, 5.0, 10.0, 10.0, 20.0)],
+                mask_data_url: Some(data_url(&mask)),
+            },
+        ];
+        let slices = restitch(pages, &opts(300), &out_dir).unwrap();
+        let _ = std::fs::remove_dir_all(&out_dir);
+
+        // лента 100x600; свободных строк в окне нет — режем по целевой высоте 300
+        assert_eq!(slices.iter().map(|s| (s.width, s.height)).collect::<Vec<_>>(), vec![(100, 300), (100, 300)]);
+        let boxes = |s: &StripSlice| -> Vec<(u64, [f64; 4])> {
+            s.items.iter().map(|i| (i["id"].as_u64().unwrap(), item_box(i).unwrap())).collect()
+        };
+        // бокс уходит в кусок своего центра и обрезается по его краям
+        assert_eq!(boxes(&slices[0]), vec![(1, [10.0, 100.0, 20.0, 120.0]), (2, [30.0, 200.0, 40.0, 300.0])]);
+        // бокс страницы B: x * 2, y * 2 + 300 - 300; id внутри куска заново с 1
+        assert_eq!(boxes(&slices[1]), vec![(1, [50.0, 0.0, 60.0, 80.0]), (2, [10.0, 20.0, 20.0, 40.0])]);
+        assert!(slices.iter().flat_map(|s| &s.items).all(|i| i.get("stageHashes").is_none()));
+
+        assert!(slices[0].mask_data_url.is_none());
+        let m = load_page_image("mask", slices[1].mask_data_url.as_deref()).unwrap().to_rgba8();
+        assert_eq!(m.dimensions(), (100, 300));
+        assert_eq!(m.get_pixel(15, 30).0[3], 255);
+        assert_eq!(m.get_pixel(15, 60).0[3], 0);
+    }
+
+    #[test]
+    fn reslice_pngs_covers_whole_strip() {
+        let pages = [page(80, 400, 380..400), page(80, 400, 0..0), page(80, 400, 0..0)]
+            .iter()
+            .map(|p| png_bytes(p).unwrap())
+            .collect::<Vec<_>>();
+        let slices = reslice_pngs(&pages, &opts(400)).unwrap();
+        let sizes: Vec<(u32, u32)> = slices
+            .iter()
+            .map(|b| image::load_from_memory(b).unwrap().dimensions())
+            .collect();
+        assert_eq!(sizes.iter().map(|s| s.1).sum::<u32>(), 1200);
+        assert!(sizes.iter().all(|s| s.0 == 80));
+        // первый разрез — по гуттеру
+        assert_eq!(sizes[0].1, 390);
+    }
+}
@@ -5,6 +5,32 @@ mod commands;
 
 fn main() {
     tauri::Builder::default()
+        .manage(commands::usage::UsageLedger::default())
+        .manage(commands::endpoints::EndpointPool::default())
+        .manage(commands::http::HttpClient::default())
+        .manage(commands::jobs::JobManager::default())
+        .manage(commands::cache::ResultCache::default())
+        .manage(commands::pages::PageRegistry::default())
+        .manage(commands::folder::ImportScans::default())
+        // Страницы проектов: mtimg://<project>/<page path>/<original|mask|final|thumb>
+        .register_asynchronous_uri_scheme_protocol(commands::pages::SCHEME, |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                responder.respond(commands::pages::serve(&app, &request));
+            });
+        })
+        .setup(|app| {
+            use tauri::Manager;
+            // События ожидания лимитов (rate-limit-wait) идут через общий HTTP-клиент
+            app.state::<commands::http::HttpClient>().attach(app.handle().clone());
+            // Кэш ответов бэкенда (детекция/OCR/инпейнт) в каталоге кэша приложения
+            if let Ok(dir) = app.path().app_cache_dir() {
+                app.state::<commands::cache::ResultCache>().init(dir.join("results"));
+            }
+            // Перепроверка упавших узлов бэкенда
+            tauri::async_runtime::spawn(commands::endpoints::reprobe_loop(app.handle().clone()));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             // Команды из `commands/mod.rs`
             commands::fetch_models,
@@ -22,7 +48,8 @@ fn main() {
             commands::read_file_b64,
             // Команды из `commands/folder.rs` (с полным путём)
             commands::folder::import_images,
-            commands::folder::import_folder,
+            commands::folder::start_folder_import,
+            commands::folder::cancel_folder_import,
             // Команды из `commands/fonts.rs` (с полным путём)
             commands::fonts::get_system_fonts,
             // Команды из `commands/project.rs` (с полным путём)
@@ -31,7 +58,51 @@ fn main() {
             commands::project::export_images,
             commands::project::export_project,
             commands::project::import_project,
-            commands::project::export_flattened_images
+            commands::project::export_flattened_images,
+            // Команды из `commands/usage.rs` (с полным путём)
+            commands::usage::get_usage_report,
+            commands::usage::set_model_prices,
+            commands::usage::reset_usage,
+            // Команды из `commands/backend.rs` (с полным путём)
+            commands::backend::probe_backend,
+            // Команды из `commands/endpoints.rs` (с полным путём)
+            commands::endpoints::get_endpoint_status,
+            // Команды из `commands/http.rs` (с полным путём)
+            commands::http::set_http_config,
+            commands::http::get_http_config,
+            // Команды из `commands/jobs.rs` (с полным путём)
+            commands::jobs::start_batch_job,
+            commands::jobs::pause_job,
+            commands::jobs::resume_job,
+            commands::jobs::cancel_job,
+            commands::jobs::get_job_status,
+            commands::jobs::list_jobs,
+            commands::jobs::list_resumable_jobs,
+            commands::jobs::resume_batch_job,
+            commands::jobs::discard_job,
+            // Команды из `commands/stages.rs` (с полным путём)
+            commands::stages::stage_input_hashes,
+            commands::stages::find_stale_items,
+            commands::stages::refresh_stale,
+            // Команды из `commands/cache.rs` (с полным путём)
+            commands::cache::get_cache_stats,
+            commands::cache::set_cache_limit,
+            commands::cache::clear_cache,
+            // Команды из `commands/regions.rs` (с полным путём)
+            commands::regions::recognize_regions,
+            // Команды из `commands/webtoon.rs` (с полным путём)
+            commands::webtoon::restitch_strip,
+            // Команды из `commands/reading_order.rs` (с полным путём)
+            commands::reading_order::detect_page,
+            commands::reading_order::order_reading,
+            // Команды из `commands/gutters.rs` (с полным путём)
+            commands::gutters::detect_panels_offline,
+            // Команды из `commands/thumbs.rs` (с полным путём)
+            commands::thumbs::get_thumbnails,
+            // Команды из `commands/pages.rs` (с полным путём)
+            commands::pages::register_pages,
+            commands::pages::set_page_layer,
+            commands::pages::close_project_pages
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,5 +1,5 @@
 // src/App.tsx
-import { useCallback, useEffect, useRef, useState } from "preact/hooks";
+import { useCallback, useEffect, useMemo, useRef, useState } from "preact/hooks";
 import { invoke } from "@tauri-apps/api/core";
 
 import LeftMenuBar from "./components/ui/LeftMenuBar";
@@ -15,6 +15,9 @@ import {
   DetectedTextItem,
   LoadingState,
   BoundingBox,
+  ImageInfo,
+  StripSlice,
+  PageThumb,
   DEFAULT_TEXT_PROPERTIES,
 } from "./types";
 import { useDetection } from "./hooks/useDetection";
@@ -29,6 +32,10 @@ import { useImageLibrary } from "./hooks/useImageLibrary";
 import { useDnDImport } from "./hooks/useDnDImport";
 import { useContextMenu } from "./hooks/useContextMenu";
 import { useInpainting } from "./hooks/useInpainting";
+import { useBackendCapabilities } from "./hooks/useBackendCapabilities";
+import { useStaleStages } from "./hooks/useStaleStages";
+import { stageInputHashes } from "./utils/stages";
+import { isOnDisk, pageUrl } from "./utils/pageUrl";
 import { ProgressState } from "./types/ui";
 import { DEFAULT_BRUSH_SIZE } from "./components/canvas/constants";
 
@@ -40,6 +47,8 @@ async function renderFinalImage(
   if (!baseDataUrl) return "";
   const img = await new Promise<HTMLImageElement>((res, rej) => {
     const im = new Image();
+    // страницы с mtimg:// — иначе canvas «испорчен» и toDataURL упадёт
+    im.crossOrigin = "anonymous";
     im.onload = () => res(im);
     im.onerror = () => rej(new Error("Load image error"));
     im.src = baseDataUrl;
@@ -145,6 +154,26 @@ export default function App() {
   const [keepViewportTick, setKeepViewportTick] = useState(0);
 
   const settings = useSettingsState();
+  // Идентификатор проекта для учёта токенов; сохраняется в metadata проекта
+  const [projectId, setProjectId] = useState(
+    () => `${Date.now()}-${Math.random().toString(16).slice(2)}`
+  );
+  const folderImportOptions = useMemo(() => {
+    const globs = (v: string) =>
+      v
+        .split(",")
+        .map((g) => g.trim())
+        .filter(Boolean);
+    return {
+      recursive: settings.recursiveImport,
+      include: globs(settings.importInclude),
+      exclude: globs(settings.importExclude),
+    };
+  }, [
+    settings.recursiveImport,
+    settings.importInclude,
+    settings.importExclude,
+  ]);
   const { models, selectedModel, setSelectedModel, fetchModels } = useModels(
     settings.translationUrl
   );
@@ -158,8 +187,10 @@ export default function App() {
     setImageSrc,
     handleImportImages,
     handleImportFolder,
+    folderImportActive,
+    cancelFolderImport,
     selectImageAt,
-  } = useImageLibrary(setProgress, false);
+  } = useImageLibrary(projectId, folderImportOptions, setProgress);
 
   const updateDetectedItems = useCallback(
     (
@@ -194,26 +225,63 @@ export default function App() {
     settings.showCanvasText ? detectedItems : null
   );
 
-  const { handleDetect } = useDetection({
+  const { unsupported } = useBackendCapabilities(settings.apiBaseUrl);
+  // недоступные на бэкенде действия выключены, причина — в подсказке;
+  // офлайн-детектор работает без бэкенда (commands/heuristic.rs)
+  const detectBlocked =
+    settings.detectionModel !== "offline_heuristic"
+      ? unsupported("detect_text_areas")
+      : null;
+  const ocrBlocked = unsupported("recognize_images_batch");
+  const inpaintAutoBlocked = unsupported("inpaint_auto_text");
+  const inpaintManualBlocked = unsupported("inpaint_lama");
+
+  const { handleDetect: runDetect } = useDetection({
     imageSrc,
     apiBaseUrl: settings.apiBaseUrl,
     setDetectedItems: updateDetectedItems,
     setIsLoading,
     usePanelDetection: settings.usePanelDetection,
     detectionModel: settings.detectionModel,
+    detectMaxSide: settings.detectMaxSide,
+    tiledDetection: settings.tiledDetection,
+    readingMode: settings.readingMode,
+    panelSource: settings.panelSource,
+    postprocess: settings.cleanupBoxes
+      ? { minArea: settings.minBoxArea }
+      : null,
   });
 
-  const { recognizeAllBubbles } = useOcr({
+  const handleDetect = useCallback(async () => {
+    // горячая клавиша срабатывает и при выключенном пункте меню
+    if (detectBlocked) {
+      console.warn(`Detection: ${detectBlocked}`);
+      return;
+    }
+    await runDetect();
+  }, [detectBlocked, runDetect]);
+
+  const { recognizeAllBubbles: runOcr } = useOcr({
     imageSrc,
+    imagePath: imageList[currentImageIndex]?.path ?? null,
     detectedItems,
     editMode,
     apiBaseUrl: settings.apiBaseUrl,
     setDetectedItems: updateDetectedItems,
     setIsLoading,
     ocrEngine: settings.ocrEngine,
+    cropOptions: settings.ocrCrop,
     easyOcrLangs: "en",
   });
 
+  const recognizeAllBubbles = useCallback(async () => {
+    if (ocrBlocked) {
+      console.warn(`OCR: ${ocrBlocked}`);
+      return;
+    }
+    await runOcr();
+  }, [ocrBlocked, runOcr]);
+
   const { translateAllBubbles, retranslateFromCache } = useTranslation({
     detectedItems,
     editMode,
@@ -224,6 +292,9 @@ export default function App() {
     deeplxUrl: settings.deeplxUrl,
     deeplxApiKey: settings.deeplxApiKey,
     streamTranslation: settings.streamTranslation,
+    visionTranslation: settings.visionTranslation,
+    imagePath: imageList[currentImageIndex]?.path ?? null,
+    usage: { projectId, page: imageList[currentImageIndex]?.name },
     setDetectedItems: updateDetectedItems,
     setIsLoading,
     onStreamUpdate: () => {},
@@ -276,15 +347,48 @@ export default function App() {
 
   const handleInpaintManual = useCallback(
     async (maskDataUrl: string) => {
-      if (isInpainting) return;
-      await inpaintManual(
+      if (isInpainting) return false;
+      if (inpaintManualBlocked) return false;
+      return !!(await inpaintManual(
         maskDataUrl,
         settings.inpaintModel || "lama_large_512px"
-      );
+      ));
     },
-    [isInpainting, inpaintManual, settings.inpaintModel]
+    [isInpainting, inpaintManual, settings.inpaintModel, inpaintManualBlocked]
   );
 
+  const setInpaintHash = useCallback(
+    (hash: string | null) =>
+      setImageList((prev) => {
+        const next = [...prev];
+        const cur = next[currentImageIndex];
+        if (cur) next[currentImageIndex] = { ...cur, inpaintHash: hash };
+        return next;
+      }),
+    [currentImageIndex, setImageList]
+  );
+
+  const { staleReport, refreshStale } = useStaleStages({
+    image: imageList[currentImageIndex],
+    items: detectedItems,
+    apiBaseUrl: settings.apiBaseUrl,
+    ocrEngine: settings.ocrEngine,
+    ocrCrop: settings.ocrCrop,
+    translationUrl: settings.translationUrl,
+    selectedModel,
+    systemPrompt: settings.systemPrompt,
+    enableTwoStepTranslation: settings.enableTwoStepTranslation,
+    deeplxUrl: settings.deeplxUrl,
+    deeplxApiKey: settings.deeplxApiKey,
+    deeplTargetLang: settings.deeplTargetLang,
+    inpaintModel: settings.inpaintModel,
+    projectId,
+    setDetectedItems: updateDetectedItems,
+    onImageUpdate: handleImageUpdate,
+    onInpaintHash: setInpaintHash,
+    setProgress,
+  });
+
   // Снимок маски -> сохранить в текущем ImageInfo -> запустить инпейнт -> очистить маску
   const handleMaskSnapshot = useCallback(
     async (dataUrl: string | null) => {
@@ -295,12 +399,34 @@ export default function App() {
         if (cur) next[currentImageIndex] = { ...cur, maskDataUrl: dataUrl };
         return next;
       });
-      await handleInpaintManual(dataUrl);
+      const cur = imageList[currentImageIndex];
+      // нарисованная маска заменяет отрисованную из боксов в mtimg://.../mask
+      if (cur && isOnDisk(cur.path)) {
+        invoke("set_page_layer", {
+          projectId,
+          path: cur.path,
+          layer: "mask",
+          dataUrl,
+        }).catch((e) => console.error("set_page_layer failed:", e));
+      }
+      const ok = await handleInpaintManual(dataUrl);
+      if (ok && cur) {
+        // запоминаем маску, по которой сделан инпейнт, — для поиска устаревшего
+        const hashes = await stageInputHashes(cur.path, [], dataUrl);
+        setInpaintHash(hashes?.inpaint ?? null);
+      }
       setClearMask((p) => p + 1);
       // по желанию: автоматический выход из maskMode
       // setMaskMode(false);
     },
-    [currentImageIndex, setImageList, handleInpaintManual]
+    [
+      currentImageIndex,
+      imageList,
+      projectId,
+      setImageList,
+      handleInpaintManual,
+      setInpaintHash,
+    ]
   );
 
   const onImageLoaded = useCallback(
@@ -413,8 +539,10 @@ export default function App() {
   const handleClearMask = useCallback(() => setClearMask((p) => p + 1), []);
 
   const handleInpaintAuto = useCallback(() => {
-    if (selectedBoxId && !isInpainting) inpaintAuto();
-  }, [selectedBoxId, isInpainting, inpaintAuto]);
+    if (!selectedBoxId || isInpainting) return;
+    if (inpaintAutoBlocked) return;
+    inpaintAuto();
+  }, [selectedBoxId, isInpainting, inpaintAuto, inpaintAutoBlocked]);
 
   const toggleAddBubble = useCallback(() => {
     if (imageSrc) setAddingBubble((p) => !p);
@@ -437,7 +565,10 @@ export default function App() {
       const finals = [];
       for (let i = 0; i < imageList.length; i++) {
         const img = imageList[i];
-        const dataUrl = await renderFinalImage(img.dataUrl, img.items || []);
+        const base =
+          img.dataUrl ||
+          (isOnDisk(img.path) ? pageUrl(projectId, img.path, "original") : "");
+        const dataUrl = await renderFinalImage(base, img.items || []);
         finals.push({ name: img.name, dataUrl });
         setProgress({
           active: true,
@@ -447,7 +578,13 @@ export default function App() {
         });
       }
 
-      await invoke("export_flattened_images", { images: finals });
+      await invoke("export_flattened_images", {
+        images: finals,
+        reslice:
+          settings.exportSliceHeight > 0
+            ? { targetHeight: settings.exportSliceHeight }
+            : null,
+      });
       alert("Images exported successfully!");
     } catch (e) {
       console.error(e);
@@ -455,16 +592,77 @@ export default function App() {
     } finally {
       setProgress({ active: false, current: 0, total: 0, label: "" });
     }
-  }, [imageList, setProgress]);
+  }, [imageList, projectId, setProgress, settings.exportSliceHeight]);
+
+  // Склеить нарезку вебтуна и нарезать заново по гуттерам (commands/webtoon.rs)
+  const handleRestitchStrip = useCallback(async () => {
+    if (imageList.length < 2) {
+      alert("Load the chapter slices first");
+      return;
+    }
+    try {
+      setProgress({
+        active: true,
+        current: 0,
+        total: 1,
+        label: "Restitching strip...",
+      });
+      const slices = await invoke<StripSlice[]>("restitch_strip", {
+        pages: imageList.map((img) => ({
+          path: img.path,
+          dataUrl: img.path.startsWith("temp://") ? img.dataUrl : null,
+          items: img.items || [],
+          maskDataUrl: img.maskDataUrl ?? null,
+        })),
+        options: { targetHeight: settings.webtoonSliceHeight },
+        projectId,
+      });
+      const thumbs = await invoke<PageThumb[]>("get_thumbnails", {
+        paths: slices.map((s) => s.path),
+      });
+      thumbs.forEach((t, i) => {
+        if (t.error)
+          console.warn(`No thumbnail for ${slices[i].name}: ${t.error}`);
+      });
+      const images: ImageInfo[] = slices.map((s, i) => ({
+        name: s.name,
+        path: s.path,
+        dataUrl: "", // лениво загрузится по пути
+        thumbnail: thumbs[i]?.thumb?.thumbnail ?? "",
+        width: s.width,
+        height: s.height,
+        items: s.items,
+        maskDataUrl: s.maskDataUrl,
+      }));
+      setImageList(images);
+      setCurrentImageIndex(0);
+      setDetectedItems(images[0]?.items || []);
+    } catch (e) {
+      console.error(e);
+      alert(`Restitch failed: ${e}`);
+    } finally {
+      setProgress({ active: false, current: 0, total: 0, label: "" });
+    }
+  }, [
+    imageList,
+    settings.webtoonSliceHeight,
+    projectId,
+    setImageList,
+    setCurrentImageIndex,
+    setDetectedItems,
+    setProgress,
+  ]);
 
   // Экспорт проекта v2 (без финалов)
   const handleExportProject = useCallback(async () => {
     if (!imageList.length) return alert("No images to export");
     try {
       const projectData = {
-        metadata: { version: "2" }, // v2
+        metadata: { version: "2", projectId }, // v2
         images: imageList.map((img) => ({
           name: img.name,
+          // в каких пикселях лежат боксы (поворот по EXIF, sRGB)
+          normalization: img.normalization ?? null,
           items: (img.items || []).map((item) => ({
             box: item.box,
             ocrText: item.ocrText,
@@ -494,7 +692,7 @@ export default function App() {
       console.error("Export failed:", error);
       alert(`Export failed: ${error}`);
     }
-  }, [imageList, settings]);
+  }, [imageList, settings, projectId]);
 
   const handleImportProject = useCallback(async () => {
     try {
@@ -514,6 +712,7 @@ export default function App() {
           setImageSrc(images[0].dataUrl);
           setDetectedItems(images[0].items || []);
         }
+        if (data.metadata?.projectId) setProjectId(data.metadata.projectId);
         if (data.settings?.deeplTargetLang)
           settings.setDeeplTargetLang(data.settings.deeplTargetLang);
         if (data.settings?.cachedIntermediateLang === "EN")
@@ -562,6 +761,8 @@ export default function App() {
       handleDetect,
       recognizeAllBubbles,
       translateAllBubbles,
+      detectBlocked,
+      ocrBlocked,
     });
 
   const handleRemoveImageAt = useCallback(
@@ -604,9 +805,12 @@ export default function App() {
         <LeftMenuBar
           onImportImages={handleImportImages}
           onImportFolder={handleImportFolder}
+          folderImportActive={folderImportActive}
+          onCancelFolderImport={cancelFolderImport}
           onImportProject={handleImportProject}
           onExportProject={handleExportProject}
           onExportImages={handleExportImages} // NEW
+          onRestitchStrip={handleRestitchStrip}
           onShowSettings={() => setShowSettingsModal(true)}
         />
         <TopProgressBar {...progress} />
@@ -614,6 +818,7 @@ export default function App() {
           <div className="left-sidebar">
             <ImageList
               images={imageList}
+              projectId={projectId}
               currentIndex={currentImageIndex}
               onSelect={handleSelectImage}
               onRemoveAt={handleRemoveImageAt}
@@ -661,9 +866,14 @@ export default function App() {
           onToggleMaskMode={toggleMaskMode}
           onInpaintAuto={handleInpaintAuto}
           onInpaintManual={() => setMaskSnapshot((p) => p + 1)}
+          inpaintAutoBlocked={inpaintAutoBlocked}
+          inpaintManualBlocked={inpaintManualBlocked}
           onClearMask={handleClearMask}
           brushSize={brushSize}
           onBrushSizeChange={setBrushSize}
+          staleItemIds={staleReport?.items.map((s) => s.id) ?? []}
+          staleInpaint={staleReport?.inpaint ?? false}
+          onRefreshStale={refreshStale}
         />
       </div>
 
@@ -694,6 +904,7 @@ export default function App() {
                 setSelectedModel={setSelectedModel}
                 models={models}
                 fetchModels={fetchModels}
+                projectId={projectId}
               />
             </div>
           </div>
@@ -19,6 +19,13 @@ interface BottomToolbarProps {
   onToggleEraseMode?: () => void;
   brushSize?: number;
   onBrushSizeChange?: (size: number) => void;
+  // устаревшие этапы текущей страницы (commands/stages.rs)
+  staleItemIds?: number[];
+  staleInpaint?: boolean;
+  onRefreshStale?: () => void;
+  // бэкенд не умеет инпейнт: кнопка выключена, причина — в подсказке
+  inpaintAutoBlocked?: string | null;
+  inpaintManualBlocked?: string | null;
 }
 
 const BottomToolbar: FunctionalComponent<BottomToolbarProps> = ({
@@ -37,8 +44,14 @@ const BottomToolbar: FunctionalComponent<BottomToolbarProps> = ({
   onToggleEraseMode,
   brushSize = 20,
   onBrushSizeChange,
+  staleItemIds = [],
+  staleInpaint = false,
+  onRefreshStale,
+  inpaintAutoBlocked = null,
+  inpaintManualBlocked = null,
 }) => {
   const anyLoading = isLoading.detect || isLoading.ocr || isLoading.translate;
+  const staleCount = staleItemIds.length + (staleInpaint ? 1 : 0);
 
   return (
     <div class="bottom-toolbar">
@@ -123,9 +136,9 @@ const BottomToolbar: FunctionalComponent<BottomToolbarProps> = ({
                 {onInpaintManual && (
                   <button
                     onClick={onInpaintManual}
-                    disabled={!imageSrc || anyLoading}
+                    disabled={!imageSrc || anyLoading || !!inpaintManualBlocked}
                     class="toolbar-btn primary"
-                    title="Inpaint Manual Mask"
+                    title={inpaintManualBlocked ?? "Inpaint Manual Mask"}
                   >
                     <span>Inpaint Manual</span>
                   </button>
@@ -136,13 +149,35 @@ const BottomToolbar: FunctionalComponent<BottomToolbarProps> = ({
           {onInpaintAuto && !maskMode && (
             <button
               onClick={onInpaintAuto}
-              disabled={!imageSrc || anyLoading || !selectedBubbleId}
+              disabled={
+                !imageSrc ||
+                anyLoading ||
+                !selectedBubbleId ||
+                !!inpaintAutoBlocked
+              }
               class="toolbar-btn primary"
-              title="Inpaint Selected Bubble"
+              title={inpaintAutoBlocked ?? "Inpaint Selected Bubble"}
             >
               <span>Inpaint Selected</span>
             </button>
           )}
+          {onRefreshStale && staleCount > 0 && !maskMode && (
+            <button
+              onClick={onRefreshStale}
+              disabled={!imageSrc || anyLoading}
+              class="toolbar-btn outline"
+              title={[
+                staleItemIds.length
+                  ? `Stale bubbles: ${staleItemIds.join(", ")}`
+                  : "",
+                staleInpaint ? "Mask changed since last inpaint" : "",
+              ]
+                .filter(Boolean)
+                .join("\n")}
+            >
+              <span>Refresh ({staleCount} stale)</span>
+            </button>
+          )}
         </div>
       </div>
     </div>
@@ -8,6 +8,8 @@ export type MenuItem =
       label: string;
       onClick?: () => void;
       disabled?: boolean;
+      // подсказка, например почему пункт выключен
+      title?: string;
       shortcut?: string;
       separator?: false;
     };
@@ -80,6 +82,7 @@ export default function ContextMenu({ x, y, visible, items, onClose }: Props) {
             key={`mi-${i}`}
             class="context-item"
             disabled={!!it.disabled}
+            title={it.title}
             onClick={(e) => {
               // Получаем объект события 'e'
               e.stopPropagation(); // <-- ГЛАВНОЕ ИЗМЕНЕНИЕ: Останавливаем всплытие события
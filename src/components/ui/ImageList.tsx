@@ -1,54 +1,27 @@
 // src/components/ui/ImageList.tsx
 import { FunctionalComponent } from "preact";
 import { useState } from "preact/hooks";
-import { convertFileSrc, invoke } from "@tauri-apps/api/core";
 import { ImageInfo } from "../../types";
 import ContextMenu, { MenuItem } from "./ContextMenu";
+import { isOnDisk, pageUrl } from "../../utils/pageUrl";
 
 interface ImageListProps {
   images: ImageInfo[];
+  projectId: string;
   currentIndex: number;
   onSelect: (index: number) => void;
   onRemoveAt: (index: number) => void;
   onClearAll: () => void;
 }
 
-function extToMime(path: string): string {
-  const ext = path.split(".").pop()?.toLowerCase() || "";
-  switch (ext) {
-    case "jpg":
-    case "jpeg":
-      return "image/jpeg";
-    case "png":
-      return "image/png";
-    case "webp":
-      return "image/webp";
-    case "bmp":
-      return "image/bmp";
-    case "gif":
-      return "image/gif";
-    case "tif":
-    case "tiff":
-      return "image/tiff";
-    case "avif":
-      return "image/avif";
-    case "heic":
-      return "image/heic";
-    case "heif":
-      return "image/heif";
-    default:
-      return "application/octet-stream";
-  }
-}
-
 const ImageList: FunctionalComponent<ImageListProps> = ({
   images,
+  projectId,
   currentIndex,
   onSelect,
   onRemoveAt,
   onClearAll,
 }) => {
-  const [fallbackSrc, setFallbackSrc] = useState<Record<string, string>>({});
   const [ctx, setCtx] = useState<{
     visible: boolean;
     x: number;
@@ -56,22 +29,11 @@ const ImageList: FunctionalComponent<ImageListProps> = ({
     items: MenuItem[];
   }>({ visible: false, x: 0, y: 0, items: [] });
 
+  // превью из импорта; иначе Rust отрисует его по mtimg://.../thumb
   const getThumbSrc = (img: ImageInfo) =>
-    fallbackSrc[img.path] ||
     img.thumbnail ||
     img.dataUrl ||
-    convertFileSrc(img.path);
-
-  const handleImgError = async (img: ImageInfo) => {
-    try {
-      const b64 = await invoke<string>("read_file_b64", { path: img.path });
-      const mime = extToMime(img.path);
-      const dataUrl = `data:${mime};base64,${b64}`;
-      setFallbackSrc((m) => ({ ...m, [img.path]: dataUrl }));
-    } catch (e) {
-      console.error("Preview fallback failed:", e);
-    }
-  };
+    (isOnDisk(img.path) ? pageUrl(projectId, img.path, "thumb") : "");
 
   const openMenu = (e: MouseEvent, items: MenuItem[]) => {
     e.preventDefault();
@@ -109,7 +71,6 @@ const ImageList: FunctionalComponent<ImageListProps> = ({
               alt={img.name}
               loading="lazy"
               decoding="async"
-              onError={() => handleImgError(img)}
               style={{ objectFit: "contain" }}
             />
             <span>{img.name}</span>
@@ -6,9 +6,13 @@ import "./LeftMenuBar.css";
 interface LeftMenuBarProps {
   onImportImages: () => void;
   onImportFolder: () => void;
+  // идёт потоковый импорт папки — пункт меню превращается в отмену
+  folderImportActive?: boolean;
+  onCancelFolderImport?: () => void;
   onImportProject: () => void;
   onExportProject: () => void;
   onExportImages: () => void; // NEW
+  onRestitchStrip: () => void;
   onShowSettings: () => void;
 }
 
@@ -19,9 +23,12 @@ interface MenuState {
 const LeftMenuBar: FunctionalComponent<LeftMenuBarProps> = ({
   onImportImages,
   onImportFolder,
+  folderImportActive = false,
+  onCancelFolderImport = () => {},
   onImportProject,
   onExportProject,
   onExportImages,
+  onRestitchStrip,
   onShowSettings,
 }) => {
   const [menuState, setMenuState] = useState<MenuState>({ activeMenu: null });
@@ -60,12 +67,21 @@ const LeftMenuBar: FunctionalComponent<LeftMenuBarProps> = ({
             >
               <FileIcon class="icon" /> Import Images
             </button>
-            <button
-              class="menu-dropdown-item"
-              onClick={() => handleMenuAction(onImportFolder)}
-            >
-              <FolderIcon class="icon" /> Import Folder
-            </button>
+            {folderImportActive ? (
+              <button
+                class="menu-dropdown-item"
+                onClick={() => handleMenuAction(onCancelFolderImport)}
+              >
+                <FolderIcon class="icon" /> Cancel Folder Import
+              </button>
+            ) : (
+              <button
+                class="menu-dropdown-item"
+                onClick={() => handleMenuAction(onImportFolder)}
+              >
+                <FolderIcon class="icon" /> Import Folder
+              </button>
+            )}
             <div class="menu-separator" />
             <button
               class="menu-dropdown-item"
@@ -107,6 +123,12 @@ const LeftMenuBar: FunctionalComponent<LeftMenuBarProps> = ({
               <SettingsIcon class="icon" /> Settings
               <span class="menu-shortcut">Ctrl+,</span>
             </button>
+            <button
+              class="menu-dropdown-item"
+              onClick={() => handleMenuAction(onRestitchStrip)}
+            >
+              Restitch Webtoon Strip
+            </button>
           </div>
         )}
       </div>
@@ -1,4 +1,14 @@
 import { FunctionalComponent } from "preact";
+import { useEffect, useState } from "preact/hooks";
+import { invoke } from "@tauri-apps/api/core";
+import {
+  CacheStats,
+  CropOptions,
+  PanelSource,
+  ReadingMode,
+  TokenUsage,
+  UsageReport,
+} from "../../types";
 
 // Language options with native names - DeepL supported languages
 const LANGUAGE_OPTIONS = [
@@ -49,14 +59,36 @@ interface SettingsProps {
   setUsePanelDetection: (v: boolean) => void;
   detectionModel: string;
   setDetectionModel: (model: string) => void;
+  detectMaxSide: number;
+  setDetectMaxSide: (v: number) => void;
+  tiledDetection: boolean;
+  setTiledDetection: (v: boolean) => void;
+  readingMode: ReadingMode;
+  setReadingMode: (v: ReadingMode) => void;
+  panelSource: PanelSource;
+  setPanelSource: (v: PanelSource) => void;
+  cleanupBoxes: boolean;
+  setCleanupBoxes: (v: boolean) => void;
+  minBoxArea: number;
+  setMinBoxArea: (v: number) => void;
+  recursiveImport: boolean;
+  setRecursiveImport: (v: boolean) => void;
+  importInclude: string;
+  setImportInclude: (v: string) => void;
+  importExclude: string;
+  setImportExclude: (v: string) => void;
   streamTranslation: boolean;
   setStreamTranslation: (v: boolean) => void;
+  visionTranslation: boolean;
+  setVisionTranslation: (v: boolean) => void;
   enableTwoStepTranslation: boolean;
   setEnableTwoStepTranslation: (v: boolean) => void;
   deeplxUrl: string;
   setDeeplxUrl: (url: string) => void;
   ocrEngine: "manga";
   setOcrEngine: (v: "manga") => void;
+  ocrCrop: CropOptions;
+  setOcrCrop: (v: CropOptions) => void;
   showCanvasText: boolean;
   setShowCanvasText: (v: boolean) => void;
   deeplTargetLang: string;
@@ -65,8 +97,33 @@ interface SettingsProps {
   setInpaintModel?: (model: string) => void;
   defaultBrushSize?: number;
   setDefaultBrushSize?: (size: number) => void;
+  modelPrices: string;
+  setModelPrices: (v: string) => void;
+  proxyUrl: string;
+  setProxyUrl: (v: string) => void;
+  noProxy: string;
+  setNoProxy: (v: string) => void;
+  caCertificates: string;
+  setCaCertificates: (v: string) => void;
+  endpointAuth: string;
+  setEndpointAuth: (v: string) => void;
+  rateLimits: string;
+  setRateLimits: (v: string) => void;
+  resultCacheMb: number;
+  setResultCacheMb: (v: number) => void;
+  webtoonSliceHeight: number;
+  setWebtoonSliceHeight: (v: number) => void;
+  exportSliceHeight: number;
+  setExportSliceHeight: (v: number) => void;
+  // проект, по которому показывается расход токенов (commands/usage.rs)
+  projectId: string;
 }
 
+const formatUsage = (u: TokenUsage) =>
+  `${u.promptTokens + u.completionTokens} tokens (${u.promptTokens} in / ${
+    u.completionTokens
+  } out), ${u.requests} request(s), $${u.cost.toFixed(4)}`;
+
 const Settings: FunctionalComponent<SettingsProps> = (p) => {
   const onTextInput = (fn: (v: string) => void) => (e: Event) =>
     fn((e.currentTarget as HTMLInputElement | HTMLTextAreaElement).value);
@@ -74,6 +131,30 @@ const Settings: FunctionalComponent<SettingsProps> = (p) => {
   const onCheck = (fn: (v: boolean) => void) => (e: Event) =>
     fn((e.currentTarget as HTMLInputElement).checked);
 
+  const [cacheStats, setCacheStats] = useState<CacheStats | null>(null);
+  useEffect(() => {
+    invoke<CacheStats>("get_cache_stats")
+      .then(setCacheStats)
+      .catch(() => {});
+  }, [p.resultCacheMb]);
+  const clearCache = () =>
+    invoke<CacheStats>("clear_cache")
+      .then(setCacheStats)
+      .catch((e) => alert(`Failed to clear cache: ${e}`));
+
+  const [usageReport, setUsageReport] = useState<UsageReport | null>(null);
+  const loadUsage = () =>
+    invoke<UsageReport>("get_usage_report", { projectId: p.projectId })
+      .then(setUsageReport)
+      .catch((e) => console.error("get_usage_report failed:", e));
+  useEffect(() => {
+    loadUsage();
+  }, [p.projectId, p.modelPrices]);
+  const resetUsage = () =>
+    invoke("reset_usage", { projectId: p.projectId })
+      .then(loadUsage)
+      .catch((e) => alert(`Failed to reset usage: ${e}`));
+
   return (
     <div class="settings-modal-body">
       <section class="settings-section">
@@ -89,7 +170,9 @@ const Settings: FunctionalComponent<SettingsProps> = (p) => {
             placeholder="http://localhost:8000"
           />
           <small class="hint">
-            Used for detection, OCR and inpaint services.
+            Used for detection, OCR and inpaint services. Several servers can
+            be listed with commas; append *N to give one a weight, e.g.
+            http://gpu1:8000*2, http://gpu2:8000.
           </small>
         </div>
 
@@ -105,9 +188,82 @@ const Settings: FunctionalComponent<SettingsProps> = (p) => {
           >
             <option value="bubbles_yolo">Bubbles YOLO (Default)</option>
             <option value="comic_text_segmenter">Comic Text Segmenter</option>
+            <option value="offline_heuristic">Offline heuristic (no backend)</option>
           </select>
           <small class="hint">
-            Choose the model for detecting text bubbles.
+            Choose the model for detecting text bubbles. The offline heuristic
+            runs locally and finds white bubbles with text; it is less accurate
+            and finds panels by gutters.
+          </small>
+        </div>
+
+        <div class="settings-field">
+          <label for="detect-max-side">Downscale for detection (max side, px)</label>
+          <input
+            id="detect-max-side"
+            type="number"
+            min="0"
+            step="256"
+            class="input"
+            value={p.detectMaxSide}
+            onInput={(e) =>
+              p.setDetectMaxSide(
+                Math.max(0, Number((e.target as HTMLInputElement).value) || 0)
+              )
+            }
+          />
+          <small class="hint">
+            Large pages are resized before upload and boxes are scaled back.
+            OCR and inpainting still use the full-resolution original. 0 sends
+            the page as is.
+          </small>
+        </div>
+
+        <div class="settings-field">
+          <label class="toggle">
+            <input
+              type="checkbox"
+              checked={p.cleanupBoxes}
+              onChange={onCheck(p.setCleanupBoxes)}
+            />
+            Clean up detected boxes
+          </label>
+          <small class="hint">
+            Drops duplicates and tiny screentone hits, joins bubbles split in
+            two and clips boxes to the page before OCR.
+          </small>
+        </div>
+
+        <div class="settings-field">
+          <label for="min-box-area">Minimum box area (px²)</label>
+          <input
+            id="min-box-area"
+            type="number"
+            min="0"
+            step="50"
+            class="input"
+            value={p.minBoxArea}
+            disabled={!p.cleanupBoxes}
+            onInput={(e) =>
+              p.setMinBoxArea(
+                Math.max(0, Number((e.target as HTMLInputElement).value) || 0)
+              )
+            }
+          />
+        </div>
+
+        <div class="settings-field">
+          <label class="toggle">
+            <input
+              type="checkbox"
+              checked={p.tiledDetection}
+              onChange={onCheck(p.setTiledDetection)}
+            />
+            Tiled detection for tall pages
+          </label>
+          <small class="hint">
+            Webtoon strips are split into overlapping tiles; boxes found twice
+            on tile seams are merged.
           </small>
         </div>
 
@@ -125,6 +281,242 @@ const Settings: FunctionalComponent<SettingsProps> = (p) => {
             sorting.
           </small>
         </div>
+
+        <div class="settings-field">
+          <label for="panel-source">Panel Source</label>
+          <select
+            id="panel-source"
+            class="select"
+            value={p.panelSource}
+            disabled={!p.usePanelDetection}
+            onChange={(e) =>
+              p.setPanelSource(
+                (e.currentTarget as HTMLSelectElement).value as PanelSource
+              )
+            }
+          >
+            <option value="backend">Backend model</option>
+            <option value="gutters">Gutter analysis (offline)</option>
+            <option value="check">Backend model, checked against gutters</option>
+          </select>
+          <small class="hint">
+            Gutter analysis splits the page along clean white or black gutters
+            and needs no backend. It is also used when the backend fails.
+          </small>
+        </div>
+
+        <div class="settings-field">
+          <label for="reading-mode">Reading Order</label>
+          <select
+            id="reading-mode"
+            class="select"
+            value={p.readingMode}
+            onChange={(e) =>
+              p.setReadingMode(
+                (e.currentTarget as HTMLSelectElement).value as ReadingMode
+              )
+            }
+          >
+            <option value="rtl">Manga (right to left)</option>
+            <option value="ltr">Comics / manhwa pages (left to right)</option>
+            <option value="webtoon">Webtoon (top to bottom)</option>
+          </select>
+          <small class="hint">
+            Decides how bubbles are numbered for translation. Nested and
+            overlapping panels are read in place.
+          </small>
+        </div>
+      </section>
+      <section class="settings-section">
+        <h3>Network</h3>
+        <div class="settings-field">
+          <label for="proxy-url">Proxy</label>
+          <input
+            id="proxy-url"
+            type="text"
+            class="input"
+            value={p.proxyUrl}
+            onInput={onTextInput(p.setProxyUrl)}
+            placeholder="socks5h://127.0.0.1:1080"
+          />
+          <small class="hint">
+            HTTP(S) or SOCKS proxy for all requests. Leave empty to connect
+            directly.
+          </small>
+        </div>
+        <div class="settings-field">
+          <label for="no-proxy">Bypass proxy for</label>
+          <input
+            id="no-proxy"
+            type="text"
+            class="input"
+            value={p.noProxy}
+            onInput={onTextInput(p.setNoProxy)}
+            disabled={!p.proxyUrl.trim()}
+          />
+        </div>
+        <div class="settings-field">
+          <label htmlFor="ca-certificates">Extra CA certificates</label>
+          <textarea
+            id="ca-certificates"
+            class="textarea"
+            value={p.caCertificates}
+            onInput={onTextInput(p.setCaCertificates)}
+            placeholder="/path/to/company-root.pem"
+          />
+          <small class="hint">One PEM or DER file path per line.</small>
+        </div>
+        <div class="settings-field">
+          <label htmlFor="endpoint-auth">Endpoint authentication</label>
+          <textarea
+            id="endpoint-auth"
+            class="textarea"
+            value={p.endpointAuth}
+            onInput={onTextInput(p.setEndpointAuth)}
+            placeholder='[{ "urlPrefix": "https://ocr.example.com", "bearer": "token", "headers": { "X-Team": "scan" } }]'
+          />
+          <small class="hint">
+            Bearer, basic auth or custom headers per URL prefix. Applied to
+            detection, OCR, inpainting and translation requests.
+          </small>
+        </div>
+        <div class="settings-field">
+          <label htmlFor="rate-limits">Rate limits</label>
+          <textarea
+            id="rate-limits"
+            class="textarea"
+            value={p.rateLimits}
+            onInput={onTextInput(p.setRateLimits)}
+            placeholder='[{ "urlPrefix": "https://api.deeplx.org", "requestsPerMinute": 30, "tokensPerMinute": 20000, "maxConcurrent": 2 }]'
+          />
+          <small class="hint">
+            Per URL prefix. Requests over the limit wait in a queue instead of
+            failing.
+          </small>
+        </div>
+      </section>
+      <section class="settings-section">
+        <h3>Result Cache</h3>
+        <div class="settings-field">
+          <label for="result-cache-mb">Cache size limit (MB)</label>
+          <input
+            id="result-cache-mb"
+            type="number"
+            min="0"
+            class="input"
+            value={p.resultCacheMb}
+            onInput={(e) =>
+              p.setResultCacheMb(Number((e.target as HTMLInputElement).value))
+            }
+          />
+          <small class="hint">
+            Detection, OCR and inpainting results are reused for identical
+            images. 0 disables the cache.
+            {cacheStats &&
+              ` Now: ${cacheStats.entries} entries, ${(
+                cacheStats.bytes /
+                1024 /
+                1024
+              ).toFixed(1)} MB, ${cacheStats.hits} hits / ${
+                cacheStats.misses
+              } misses.`}
+          </small>
+        </div>
+        <div class="settings-field">
+          <button class="btn btn-secondary" onClick={clearCache}>
+            Clear cache
+          </button>
+        </div>
+      </section>
+      <section class="settings-section">
+        <h3>Folder Import</h3>
+        <div class="settings-field">
+          <label class="toggle">
+            <input
+              type="checkbox"
+              checked={p.recursiveImport}
+              onChange={onCheck(p.setRecursiveImport)}
+            />
+            Include subfolders as chapters
+          </label>
+          <small class="hint">
+            A whole volume imports at once: Vol 01/Ch 003/ becomes its own
+            chapter, and pages sort by number (2.png before 10.png).
+          </small>
+        </div>
+
+        <div class="settings-field">
+          <label for="import-include">Include patterns</label>
+          <input
+            id="import-include"
+            type="text"
+            class="input"
+            value={p.importInclude}
+            onInput={onTextInput(p.setImportInclude)}
+            placeholder="Vol 01/**, **/*.png"
+          />
+        </div>
+
+        <div class="settings-field">
+          <label for="import-exclude">Exclude patterns</label>
+          <input
+            id="import-exclude"
+            type="text"
+            class="input"
+            value={p.importExclude}
+            onInput={onTextInput(p.setImportExclude)}
+            placeholder="**/raw/**, **/*_thumb.*"
+          />
+          <small class="hint">
+            Comma-separated globs relative to the chosen folder. Empty includes
+            everything.
+          </small>
+        </div>
+      </section>
+
+      <section class="settings-section">
+        <h3>Webtoon</h3>
+        <div class="settings-field">
+          <label for="webtoon-slice-height">Slice height (px)</label>
+          <input
+            id="webtoon-slice-height"
+            type="number"
+            min="256"
+            step="100"
+            class="input"
+            value={p.webtoonSliceHeight}
+            onInput={(e) =>
+              p.setWebtoonSliceHeight(
+                Number((e.target as HTMLInputElement).value) || 2000
+              )
+            }
+          />
+          <small class="hint">
+            Tools → Restitch Webtoon Strip joins all pages into one strip and
+            cuts it again at blank gutters close to this height. Boxes and
+            masks move with the new slices.
+          </small>
+        </div>
+        <div class="settings-field">
+          <label for="export-slice-height">Export slice height (px)</label>
+          <input
+            id="export-slice-height"
+            type="number"
+            min="0"
+            step="100"
+            class="input"
+            value={p.exportSliceHeight}
+            onInput={(e) =>
+              p.setExportSliceHeight(
+                Math.max(0, Number((e.target as HTMLInputElement).value) || 0)
+              )
+            }
+          />
+          <small class="hint">
+            Re-slice exported images to the height your uploader requires. 0
+            exports pages as they are.
+          </small>
+        </div>
       </section>
       <section class="settings-section">
         <h3>OCR Settings</h3>
@@ -135,6 +527,63 @@ const Settings: FunctionalComponent<SettingsProps> = (p) => {
             Using MangaOCR for Japanese text recognition.
           </small>
         </div>
+        <div class="settings-field">
+          <label for="ocr-crop-padding">Crop padding (px)</label>
+          <input
+            id="ocr-crop-padding"
+            type="number"
+            min="0"
+            class="input"
+            value={p.ocrCrop.padding}
+            onInput={(e) =>
+              p.setOcrCrop({
+                ...p.ocrCrop,
+                padding: Math.max(
+                  0,
+                  Number((e.target as HTMLInputElement).value) || 0
+                ),
+              })
+            }
+          />
+        </div>
+        <div class="settings-field">
+          <label for="ocr-crop-min-side">Upscale crops smaller than (px)</label>
+          <input
+            id="ocr-crop-min-side"
+            type="number"
+            min="0"
+            class="input"
+            value={p.ocrCrop.minSide}
+            onInput={(e) =>
+              p.setOcrCrop({
+                ...p.ocrCrop,
+                minSide: Math.max(
+                  0,
+                  Number((e.target as HTMLInputElement).value) || 0
+                ),
+              })
+            }
+          />
+          <small class="hint">
+            Regions are cropped from the original page in the backend. 0
+            disables upscaling.
+          </small>
+        </div>
+        <div class="settings-field">
+          <label class="toggle">
+            <input
+              type="checkbox"
+              checked={p.ocrCrop.rotateVertical}
+              onChange={(e) =>
+                p.setOcrCrop({
+                  ...p.ocrCrop,
+                  rotateVertical: (e.currentTarget as HTMLInputElement).checked,
+                })
+              }
+            />
+            Rotate vertical text regions before OCR
+          </label>
+        </div>
         <div class="settings-field">
           <label class="toggle">
             <input
@@ -195,6 +644,68 @@ const Settings: FunctionalComponent<SettingsProps> = (p) => {
             Enable Real-time Translation (Streaming)
           </label>
         </div>
+        <div class="settings-field">
+          <label class="toggle">
+            <input
+              type="checkbox"
+              checked={p.visionTranslation}
+              onChange={onCheck(p.setVisionTranslation)}
+            />
+            Send Page Image to the Model (Vision)
+          </label>
+          <small class="hint">
+            Attaches a downscaled page and bubble boxes so vision models can
+            see who is speaking.
+          </small>
+        </div>
+      </section>
+      <section class="settings-section">
+        <h3>Token Prices</h3>
+        <div class="settings-field">
+          <label htmlFor="model-prices">Price per 1M tokens, by model</label>
+          <textarea
+            id="model-prices"
+            class="textarea"
+            value={p.modelPrices}
+            onInput={onTextInput(p.setModelPrices)}
+            placeholder='{ "gpt-4o-mini": { "promptPerMillion": 0.15, "completionPerMillion": 0.6 } }'
+          />
+          <small class="hint">
+            Used to estimate the cost of each page, run and project.
+          </small>
+        </div>
+      </section>
+      <section class="settings-section">
+        <h3>Token Usage</h3>
+        {usageReport && (
+          <div class="settings-field">
+            <label>This project</label>
+            <small class="hint">{formatUsage(usageReport.usage.total)}</small>
+            {Object.entries(usageReport.usage.byRun).map(([run, u]) => (
+              <small key={run} class="hint">
+                Batch run {run}: {formatUsage(u)}
+              </small>
+            ))}
+            {Object.entries(usageReport.usage.byModel).map(([model, u]) => (
+              <small key={model} class="hint">
+                {model}: {formatUsage(u)}
+              </small>
+            ))}
+            {Object.entries(usageReport.usage.byPage).map(([page, u]) => (
+              <small key={page} class="hint">
+                {page}: {formatUsage(u)}
+              </small>
+            ))}
+          </div>
+        )}
+        <div class="settings-field">
+          <button class="btn btn-secondary" onClick={loadUsage}>
+            Refresh
+          </button>
+          <button class="btn btn-secondary" onClick={resetUsage}>
+            Reset usage
+          </button>
+        </div>
       </section>
       <section class="settings-section">
         <h3>System Prompt</h3>
@@ -0,0 +1,49 @@
+// src/hooks/useBackendCapabilities.ts
+import { useCallback, useEffect, useState } from "preact/hooks";
+import { invoke } from "@tauri-apps/api/core";
+import { BackendCapabilities } from "../types";
+
+export function useBackendCapabilities(apiBaseUrl: string) {
+  const [capabilities, setCapabilities] = useState<BackendCapabilities | null>(
+    null
+  );
+
+  const probeBackend = useCallback(async () => {
+    if (!apiBaseUrl.trim()) return null;
+    try {
+      const caps = await invoke<BackendCapabilities>("probe_backend", {
+        apiUrl: apiBaseUrl,
+      });
+      setCapabilities(caps);
+      if (caps.warnings.length) console.warn("Backend probe:", caps.warnings);
+      return caps;
+    } catch (e) {
+      console.error("Backend probe failed:", e);
+      setCapabilities(null);
+      return null;
+    }
+  }, [apiBaseUrl]);
+
+  // Перепроверяем бэкенд при смене URL (с задержкой, пока пользователь печатает)
+  useEffect(() => {
+    const t = setTimeout(probeBackend, 500);
+    return () => clearTimeout(t);
+  }, [probeBackend]);
+
+  // Почему действие недоступно (для подсказки на выключенной кнопке); null — можно запускать
+  const unsupported = useCallback(
+    (endpoint: string): string | null => {
+      if (!capabilities) return null; // ещё не проверили — не мешаем
+      if (!capabilities.reachable) {
+        return `Backend at ${capabilities.apiUrl} is not reachable`;
+      }
+      if (capabilities.endpoints[endpoint] === false) {
+        return `Backend does not provide /${endpoint}`;
+      }
+      return null;
+    },
+    [capabilities]
+  );
+
+  return { capabilities, probeBackend, unsupported };
+}
@@ -11,6 +11,9 @@ type Args = {
   handleDetect: () => void;
   recognizeAllBubbles: () => void;
   translateAllBubbles: () => void;
+  // причина, по которой бэкенд не умеет действие (useBackendCapabilities)
+  detectBlocked?: string | null;
+  ocrBlocked?: string | null;
 };
 
 export function useContextMenu(args: Args) {
@@ -32,14 +35,20 @@ export function useContextMenu(args: Args) {
       label: "Detect Bubbles",
       shortcut: "D",
       onClick: args.handleDetect,
-      disabled: !args.imageSrc || disabledBusy,
+      disabled: !args.imageSrc || disabledBusy || !!args.detectBlocked,
+      title: args.detectBlocked ?? undefined,
       separator: false,
     },
     {
       label: "OCR",
       shortcut: "R",
       onClick: args.recognizeAllBubbles,
-      disabled: !args.imageSrc || !args.detectedItems?.length || disabledBusy,
+      disabled:
+        !args.imageSrc ||
+        !args.detectedItems?.length ||
+        disabledBusy ||
+        !!args.ocrBlocked,
+      title: args.ocrBlocked ?? undefined,
       separator: false,
     },
     {
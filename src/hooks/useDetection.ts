@@ -3,12 +3,13 @@ import { invoke } from "@tauri-apps/api/core";
 import {
   DetectedTextItem,
   LoadingState,
-  PanelDetectionResult,
-  YoloDetectionResult,
-  BoundingBox,
+  DetectedPage,
+  PanelSource,
+  PostprocessOptions,
+  ReadingMode,
   DEFAULT_TEXT_PROPERTIES,
 } from "../types";
-import { sortBubblesByPanels } from "../utils/sorting";
+import { toDataUrl } from "../utils/pageUrl";
 
 type SetItemsUpdater = (
   updater: (prev: DetectedTextItem[] | null) => DetectedTextItem[] | null
@@ -22,6 +23,13 @@ interface UseDetectionArgs {
   setIsLoading: SetLoading;
   usePanelDetection: boolean;
   detectionModel: string;
+  // 0 — без уменьшения
+  detectMaxSide?: number;
+  tiledDetection?: boolean;
+  readingMode?: ReadingMode;
+  panelSource?: PanelSource;
+  // null — боксы как пришли от детектора
+  postprocess?: PostprocessOptions | null;
 }
 
 export function useDetection({
@@ -31,13 +39,18 @@ export function useDetection({
   setIsLoading,
   usePanelDetection,
   detectionModel,
+  detectMaxSide = 0,
+  tiledDetection = false,
+  readingMode = "rtl",
+  panelSource = "backend",
+  postprocess = null,
 }: UseDetectionArgs) {
   const handleDetect = useCallback(async () => {
     if (!imageSrc) return;
 
     try {
       setIsLoading((p) => ({ ...p, detect: true }));
-      const base64Image = imageSrc.split(",")[1];
+      const base64Image = (await toDataUrl(imageSrc)).split(",")[1];
 
       // ИЗМЕНЕНИЕ 6: Формируем единый payload
       const detectionPayload = {
@@ -45,40 +58,44 @@ export function useDetection({
         detection_model: detectionModel,
       };
 
-      let finalSortedBubbles: BoundingBox[] = [];
-
-      if (usePanelDetection) {
+      console.log(
+        `Detecting with model '${detectionModel}', panels ${
+          usePanelDetection ? "ENABLED" : "DISABLED"
+        }, reading order '${readingMode}'.`
+      );
+      // Детекция, панели и порядок чтения — одним вызовом в Rust
+      const page = await invoke<DetectedPage>("detect_page", {
+        apiUrl: apiBaseUrl,
+        payload: detectionPayload,
+        options: {
+          maxSide: detectMaxSide || null,
+          // настройки тайлов по умолчанию — в commands/tiling.rs
+          tiling: tiledDetection ? {} : null,
+          usePanels: usePanelDetection,
+          panelSource,
+          reading: { mode: readingMode },
+          postprocess,
+        },
+      });
+      if (page.report) {
+        const r = page.report;
         console.log(
-          `Detecting with Panel Detection ENABLED and model '${detectionModel}'.`
-        );
-        const [bubbleResult, panelResult] = await Promise.all([
-          // Передаем payload целиком
-          invoke<YoloDetectionResult>("detect_text_areas", {
-            apiUrl: apiBaseUrl,
-            payload: detectionPayload,
-          }),
-          invoke<PanelDetectionResult>("detect_panels", {
-            apiUrl: apiBaseUrl,
-            imageData: base64Image,
-          }),
-        ]);
-        finalSortedBubbles = sortBubblesByPanels(
-          bubbleResult.boxes,
-          panelResult.panels
+          `Box cleanup: ${r.input} -> ${r.output} (clamped ${r.clamped}, filtered ${r.filtered}, suppressed ${r.suppressed}, merged ${r.merged})`,
+          r.changes
         );
-      } else {
+      }
+      if (page.panelCheck) {
+        const c = page.panelCheck;
         console.log(
-          `Detecting with Panel Detection DISABLED and model '${detectionModel}'.`
-        );
-        // Передаем payload целиком
-        const bubbleResult = await invoke<YoloDetectionResult>(
-          "detect_text_areas",
-          { apiUrl: apiBaseUrl, payload: detectionPayload }
+          `Panel check: ${Math.round(c.agreement * 100)}% agreement, ${c.missing.length} missing, ${c.unconfirmed.length} unconfirmed`,
+          c
         );
-        finalSortedBubbles = sortBubblesByPanels(bubbleResult.boxes, []);
+      }
+      if (page.panelFallback) {
+        console.warn(`${page.panelFallback}; panels taken from gutters`);
       }
 
-      const items: DetectedTextItem[] = finalSortedBubbles.map(
+      const items: DetectedTextItem[] = page.boxes.map(
         (box, index) => ({
           id: index + 1,
           box,
@@ -102,6 +119,11 @@ export function useDetection({
     apiBaseUrl,
     usePanelDetection,
     detectionModel,
+    detectMaxSide,
+    tiledDetection,
+    readingMode,
+    panelSource,
+    postprocess,
     setDetectedItems,
     setIsLoading,
   ]);
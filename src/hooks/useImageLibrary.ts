@@ -1,44 +1,34 @@
 // src/hooks/useImageLibrary.ts
-import { useCallback, useEffect, useState } from "preact/hooks";
+import { useCallback, useEffect, useRef, useState } from "preact/hooks";
 import { invoke } from "@tauri-apps/api/core";
-import { ImageInfo } from "../types";
+import { listen } from "@tauri-apps/api/event";
+import {
+  FolderImportOptions,
+  ImageInfo,
+  ImportBatch,
+  ImportProgress,
+  ImportReport,
+  RejectedFile,
+} from "../types";
 import { ProgressState } from "../types/ui";
+import { isOnDisk, pageUrl } from "../utils/pageUrl";
 
-function extToMime(path: string): string {
-  const ext = path.split(".").pop()?.toLowerCase() || "";
-  switch (ext) {
-    case "jpg":
-    case "jpeg":
-      return "image/jpeg";
-    case "png":
-      return "image/png";
-    case "webp":
-      return "image/webp";
-    case "bmp":
-      return "image/bmp";
-    case "gif":
-      return "image/gif";
-    case "tif":
-    case "tiff":
-      return "image/tiff";
-    case "avif":
-      return "image/avif";
-    case "heic":
-      return "image/heic";
-    case "heif":
-      return "image/heif";
-    default:
-      return "application/octet-stream";
-  }
+function reportRejected(rejected: RejectedFile[]) {
+  if (!rejected.length) return;
+  const lines = rejected.map((r) => `${r.path}: ${r.reason}`);
+  alert(`Skipped ${rejected.length} unreadable file(s):\n${lines.join("\n")}`);
 }
 
 export function useImageLibrary(
-  setProgress: (p: ProgressState) => void,
-  suppressProgress: boolean = false
+  projectId: string,
+  folderOptions?: FolderImportOptions,
+  setProgress?: (p: ProgressState) => void
 ) {
   const [imageList, setImageList] = useState<ImageInfo[]>([]);
   const [currentImageIndex, setCurrentImageIndex] = useState(0);
   const [imageSrc, setImageSrc] = useState<string | null>(null);
+  // последняя регистрация страниц: адрес mtimg отдаём только после неё
+  const registered = useRef<Promise<unknown>>(Promise.resolve());
 
   const loadImageByIndex = useCallback(
     async (index: number) => {
@@ -52,41 +42,14 @@ export function useImageLibrary(
         return;
       }
 
-      if (!suppressProgress) {
-        setProgress({
-          active: true,
-          current: 0,
-          total: 1,
-          label: "Loading image...",
-        });
-      }
-      try {
-        const b64 = await invoke<string>("read_file_b64", { path: item.path });
-        const mime = extToMime(item.path);
-        const dataUrl = `data:${mime};base64,${b64}`;
-
-        // Обновляем список: кладём dataUrl внутрь соответствующего элемента
-        setImageList((prev) => {
-          const next = [...prev];
-          // index может немного "уплыть", поэтому сверим по path
-          const idx =
-            next.findIndex((x) => x.path === item.path) !== -1
-              ? next.findIndex((x) => x.path === item.path)
-              : index;
-          if (next[idx]) next[idx] = { ...next[idx], dataUrl };
-          return next;
-        });
-
-        // Активируем
+      // Файл на диске webview грузит сам по mtimg://, без base64 через IPC
+      if (isOnDisk(item.path)) {
+        await registered.current;
         setCurrentImageIndex(index);
-        setImageSrc(dataUrl);
-      } finally {
-        if (!suppressProgress) {
-          setProgress({ active: false, current: 0, total: 0, label: "" });
-        }
+        setImageSrc(pageUrl(projectId, item.path, "original"));
       }
     },
-    [imageList, setProgress, suppressProgress]
+    [imageList, projectId]
   );
 
   const selectImageAt = useCallback(
@@ -96,42 +59,109 @@ export function useImageLibrary(
     [loadImageByIndex]
   );
 
+  // Мерджим с текущим списком; selectFirst — выбрать первую страницу после импорта.
+  // Порядок задаёт Rust (обход папки в естественном порядке): уже открытые страницы
+  // обновляются на месте, новые дописываются в конец как пришли
+  const addImages = useCallback(
+    (picked: ImageInfo[], selectFirst: boolean) => {
+      if (!picked.length) return;
+      setImageList((prev) => {
+        const map = new Map<string, ImageInfo>();
+        for (const it of prev) map.set(it.path, it);
+        for (const it of picked) map.set(it.path, it);
+        return Array.from(map.values());
+      });
+      if (selectFirst) setCurrentImageIndex(0);
+    },
+    []
+  );
+
   const handleImportImages = useCallback(async () => {
-    const picked = await invoke<ImageInfo[]>("import_images");
-    if (!picked?.length) return;
-
-    // Мерджим с текущим списком
-    setImageList((prev) => {
-      const map = new Map<string, ImageInfo>();
-      for (const it of prev) map.set(it.path, it);
-      for (const it of picked) map.set(it.path, it);
-      const next = Array.from(map.values()).sort((a, b) =>
-        a.name.localeCompare(b.name)
+    const report = await invoke<ImportReport>("import_images");
+    reportRejected(report.rejected);
+    if (report.converted) {
+      console.log(
+        `Normalized ${report.converted} page(s): converted, rotated or moved to sRGB`
       );
-      return next;
-    });
+    }
+    addImages(report.images, true);
+  }, [addImages]);
 
-    // Всегда выбираем первое после импорта
-    setCurrentImageIndex(0);
-  }, []);
+  // Папка сканируется в Rust в фоне: страницы приходят пачками (import-batch),
+  // прогресс — import-progress; пока идёт сканирование, его можно отменить
+  const [folderImportId, setFolderImportId] = useState<string | null>(null);
 
   const handleImportFolder = useCallback(async () => {
-    const picked = await invoke<ImageInfo[]>("import_folder");
-    if (!picked?.length) return;
-
-    setImageList((prev) => {
-      const map = new Map<string, ImageInfo>();
-      for (const it of prev) map.set(it.path, it);
-      for (const it of picked) map.set(it.path, it);
-      const next = Array.from(map.values()).sort((a, b) =>
-        a.name.localeCompare(b.name)
-      );
-      return next;
+    const scanId = `scan-${Date.now()}-${Math.random().toString(16).slice(2)}`;
+    const rejected: RejectedFile[] = [];
+    let first = true;
+    let finish = () => {};
+    const finished = new Promise<void>((res) => (finish = () => res()));
+
+    // подписываемся до запуска, чтобы не пропустить первые события
+    const unlistenBatch = await listen<ImportBatch>("import-batch", (ev) => {
+      if (ev.payload.scanId !== scanId) return;
+      rejected.push(...ev.payload.rejected);
+      if (ev.payload.images.length) {
+        addImages(ev.payload.images, first);
+        first = false;
+      }
     });
+    const unlistenProgress = await listen<ImportProgress>(
+      "import-progress",
+      (ev) => {
+        const p = ev.payload;
+        if (p.scanId !== scanId) return;
+        setProgress?.({
+          active: !p.done,
+          current: p.imported + p.rejected,
+          total: Math.max(p.found, 1),
+          label: `Importing folder: ${p.imported} page(s), ${p.scanned} file(s) scanned`,
+        });
+        if (p.done) {
+          if (p.cancelled) console.log(`Folder import cancelled: ${p.folder}`);
+          finish();
+        }
+      }
+    );
+
+    try {
+      const started = await invoke<boolean>("start_folder_import", {
+        scanId,
+        options: folderOptions ?? null,
+      });
+      if (!started) return;
+      setFolderImportId(scanId);
+      await finished;
+      reportRejected(rejected);
+    } catch (e) {
+      alert(`Folder import failed: ${e}`);
+    } finally {
+      unlistenBatch();
+      unlistenProgress();
+      setFolderImportId(null);
+    }
+  }, [addImages, folderOptions, setProgress]);
 
-    // Всегда выбираем первое после импорта
-    setCurrentImageIndex(0);
-  }, []);
+  const cancelFolderImport = useCallback(() => {
+    if (folderImportId) {
+      invoke("cancel_folder_import", { scanId: folderImportId });
+    }
+  }, [folderImportId]);
+
+  // Протокол mtimg отдаёт только страницы открытого проекта — держим список в Rust актуальным
+  useEffect(() => {
+    if (!projectId) return;
+    const pages = imageList
+      .filter((it) => isOnDisk(it.path))
+      .map((it) => ({
+        path: it.path,
+        boxes: (it.items || []).map((d) => d.box),
+      }));
+    registered.current = invoke("register_pages", { projectId, pages }).catch(
+      (e) => console.error("register_pages failed:", e)
+    );
+  }, [imageList, projectId]);
 
   // АВТО-ЗАГРУЗКА по изменению списка:
   // как только список изменился и есть валидный индекс — загрузим это изображение.
@@ -150,6 +180,13 @@ export function useImageLibrary(
     }
   }, [imageList]); // намеренно без зависимостей loadImageByIndex/currentImageIndex тут
 
+  useEffect(() => {
+    if (!projectId) return;
+    return () => {
+      invoke("close_project_pages", { projectId }).catch(() => {});
+    };
+  }, [projectId]);
+
   return {
     imageList,
     setImageList,
@@ -159,6 +196,8 @@ export function useImageLibrary(
     setImageSrc,
     handleImportImages,
     handleImportFolder,
+    folderImportActive: folderImportId !== null,
+    cancelFolderImport,
     loadImageByIndex,
     selectImageAt,
   };
@@ -1,6 +1,7 @@
 import { useState, useCallback } from "preact/hooks";
 import { invoke } from "@tauri-apps/api/core";
 import { DetectedTextItem } from "../types";
+import { toDataUrl } from "../utils/pageUrl";
 
 interface UseInpaintingArgs {
   imageSrc: string | null;
@@ -40,7 +41,10 @@ export function useInpainting({
       setIsInpainting(true);
       onProgress?.({ active: true, label: "Inpainting selected bubble..." });
 
-      const base64Image = imageSrc.replace(/^data:image\/[^;]+;base64,/, "");
+      const base64Image = (await toDataUrl(imageSrc)).replace(
+        /^data:image\/[^;]+;base64,/,
+        ""
+      );
       const boxes = [
         [
           selectedItem.box.x1,
@@ -95,6 +99,7 @@ export function useInpainting({
       const loadImageFromDataUrl = (dataUrl: string) =>
         new Promise<HTMLImageElement>((resolve, reject) => {
           const img = new Image();
+          img.crossOrigin = "anonymous";
           img.onload = () => resolve(img);
           img.onerror = reject;
           img.src = dataUrl;
@@ -276,6 +281,7 @@ export function useInpainting({
 
         const newImageSrc = out.toDataURL("image/png");
         onImageUpdate?.(newImageSrc);
+        return true;
       } catch (error) {
         console.error("Manual inpainting (crop) failed:", error);
         alert(`Inpainting failed: ${error}`);
@@ -302,7 +308,10 @@ export function useInpainting({
           label: "Processing manual mask inpainting...",
         });
 
-        const base64Image = imageSrc.replace(/^data:image\/[^;]+;base64,/, "");
+        const base64Image = (await toDataUrl(imageSrc)).replace(
+          /^data:image\/[^;]+;base64,/,
+          ""
+        );
         const base64Mask = maskDataUrl.replace(
           /^data:image\/[^;]+;base64,/,
           ""
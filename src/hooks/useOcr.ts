@@ -1,10 +1,12 @@
 import { useCallback } from "preact/hooks";
 import { invoke } from "@tauri-apps/api/core";
 import {
+  CropOptions,
   DetectedTextItem,
   LoadingState,
   RecognizeBatchResponse,
 } from "../types";
+import { stageHashFor, stageInputHashes } from "../utils/stages";
 
 type SetItemsUpdater = (
   updater: (prev: DetectedTextItem[] | null) => DetectedTextItem[] | null
@@ -13,22 +15,27 @@ type SetLoading = (updater: (prev: LoadingState) => LoadingState) => void;
 
 interface UseOcrArgs {
   imageSrc: string | null;
+  // путь страницы — для хэшей входов этапов
+  imagePath?: string | null;
   detectedItems: DetectedTextItem[] | null;
   editMode: boolean;
   apiBaseUrl: string;
   setDetectedItems: SetItemsUpdater;
   setIsLoading: SetLoading;
   ocrEngine: "manga";
+  cropOptions?: CropOptions;
   easyOcrLangs: string;
 }
 
 export function useOcr({
   imageSrc,
+  imagePath = null,
   detectedItems,
   apiBaseUrl,
   setDetectedItems,
   setIsLoading,
   ocrEngine,
+  cropOptions,
 }: UseOcrArgs) {
   const recognizeAllBubbles = useCallback(async () => {
     if (!imageSrc || !detectedItems || !detectedItems.length) {
@@ -39,55 +46,28 @@ export function useOcr({
     try {
       setIsLoading((p) => ({ ...p, ocr: true }));
 
-      const cropImageRegion = async (
-        imageDataUrl: string,
-        box: { x1: number; y1: number; x2: number; y2: number }
-      ): Promise<string> =>
-        new Promise((resolve, reject) => {
-          const img = new Image();
-          img.onload = () => {
-            const canvas = document.createElement("canvas");
-            const ctx = canvas.getContext("2d");
-            if (!ctx) {
-              reject(new Error("2D context error"));
-              return;
-            }
-            const w = box.x2 - box.x1,
-              h = box.y2 - box.y1;
-            canvas.width = w;
-            canvas.height = h;
-            ctx.drawImage(img, box.x1, box.y1, w, h, 0, 0, w, h);
-            resolve(canvas.toDataURL("image/jpeg"));
-          };
-          img.onerror = () => reject(new Error("Load image error"));
-          img.src = imageDataUrl;
-        });
-
-      const croppedImages = await Promise.all(
-        detectedItems.map((item) => cropImageRegion(imageSrc, item.box))
+      // Кропы режет Rust из оригинала страницы (commands/regions.rs):
+      // в IPC идут только боксы, без base64 каждого кропа.
+      // У temp:// страниц файла нет — отдаём саму картинку.
+      const onDisk = !!imagePath && !imagePath.startsWith("temp://");
+      console.log(
+        `Invoking tauri command: 'recognize_regions' with ${detectedItems.length} boxes.`
       );
-      const base64Images = croppedImages.map((d) => d.split(",")[1]);
 
-      // Формируем единый payload, который будет передан в Rust
-      const payload = {
-        images_data: base64Images,
+      // Кропы считаются от этих боксов — их хэш и запишем как вход OCR
+      const hashes = await stageInputHashes(imagePath, detectedItems, null, {
         engine: ocrEngine,
-        langs: undefined, // MangaOCR не использует этот параметр
-        auto_rotate: false,
-      };
+        crop: cropOptions,
+      });
 
-      console.log(
-        `Invoking tauri command: 'recognize_images_batch' with ${base64Images.length} images.`
-      );
-
-      // Передаем payload целиком
-      const data = await invoke<RecognizeBatchResponse>(
-        "recognize_images_batch",
-        {
-          apiUrl: apiBaseUrl,
-          payload: payload,
-        }
-      );
+      const data = await invoke<RecognizeBatchResponse>("recognize_regions", {
+        apiUrl: apiBaseUrl,
+        path: imagePath || "",
+        dataUrl: onDisk ? null : imageSrc,
+        boxes: detectedItems.map((item) => item.box),
+        engine: ocrEngine,
+        options: cropOptions,
+      });
 
       if (!data?.results) throw new Error("Invalid OCR response");
 
@@ -95,6 +75,10 @@ export function useOcr({
         (prev || []).map((item, i) => ({
           ...item,
           ocrText: data.results[i] || null,
+          stageHashes: {
+            ...item.stageHashes,
+            ocr: stageHashFor(hashes, item.id, "ocr") ?? null,
+          },
         }))
       );
       console.log("OCR successful, items updated.");
@@ -106,11 +90,13 @@ export function useOcr({
     }
   }, [
     imageSrc,
+    imagePath,
     detectedItems,
     apiBaseUrl,
     setDetectedItems,
     setIsLoading,
     ocrEngine,
+    cropOptions,
   ]);
 
   return { recognizeAllBubbles };
@@ -3,23 +3,34 @@ import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import {
   BoundingBox,
+  CropOptions,
+  DetectedPage,
   DetectedTextItem,
-  PanelDetectionResult,
+  PanelSource,
+  PostprocessOptions,
+  ReadingMode,
   RecognizeBatchResponse,
-  YoloDetectionResult,
   ImageInfo,
+  JobPage,
+  JobProgress,
+  JobStatus,
+  ResumableJobs,
   DEFAULT_TEXT_PROPERTIES,
 } from "../types";
 import { ProgressState } from "../types/ui";
-import { sortBubblesByPanels } from "../utils/sorting";
 import { parseNumberedLinesToPairs } from "../utils/llm";
-import { runInPool } from "../utils/pool";
+import { toDataUrl } from "../utils/pageUrl";
 
 type SetState<T> = (value: T | ((prev: T) => T)) => void;
 
 type Args = {
   apiBaseUrl: string;
   usePanelDetection: boolean;
+  detectMaxSide?: number;
+  tiledDetection?: boolean;
+  readingMode?: ReadingMode;
+  panelSource?: PanelSource;
+  postprocess?: PostprocessOptions | null;
   translationUrl: string;
   selectedModel: string;
   systemPrompt: string;
@@ -27,6 +38,9 @@ type Args = {
   deeplxUrl: string;
   deeplxApiKey: string;
   deeplTargetLang: string;
+  ocrCrop?: CropOptions;
+  // для журнала задач и учёта токенов
+  projectId?: string;
 
   setDetectedItems: SetState<DetectedTextItem[] | null>;
   setImageList: SetState<ImageInfo[]>;
@@ -40,50 +54,17 @@ function uuid(): string {
   return `${Date.now()}-${Math.random().toString(16).slice(2)}`;
 }
 
-function extToMime(path: string): string {
-  const ext = path.split(".").pop()?.toLowerCase() || "";
-  switch (ext) {
-    case "jpg":
-    case "jpeg":
-      return "image/jpeg";
-    case "png":
-      return "image/png";
-    case "webp":
-      return "image/webp";
-    case "bmp":
-      return "image/bmp";
-    case "gif":
-      return "image/gif";
-    case "tif":
-    case "tiff":
-      return "image/tiff";
-    case "avif":
-      return "image/avif";
-    case "heic":
-      return "image/heic";
-    case "heif":
-      return "image/heif";
-    default:
-      return "application/octet-stream";
-  }
-}
-
-async function ensureDataUrl(item: ImageInfo): Promise<string> {
-  if (item.dataUrl) return item.dataUrl;
-
-  if (item.path.startsWith("temp://")) {
-    if (item.dataUrl) return item.dataUrl;
-    throw new Error("No data URL available for temp image");
-  }
-
-  const b64 = await invoke<string>("read_file_b64", { path: item.path });
-  const mime = extToMime(item.path);
-  return `data:${mime};base64,${b64}`;
-}
+// id задачи, запущенной из этого окна
+let activeJobId: string | null = null;
 
 export function useProcessAll({
   apiBaseUrl,
   usePanelDetection,
+  detectMaxSide = 0,
+  tiledDetection = false,
+  readingMode = "rtl",
+  panelSource = "backend",
+  postprocess = null,
   translationUrl,
   selectedModel,
   systemPrompt,
@@ -91,65 +72,45 @@ export function useProcessAll({
   deeplxUrl,
   deeplxApiKey,
   deeplTargetLang,
+  ocrCrop,
+  projectId,
   setDetectedItems,
   setImageList,
   setProgress,
   setBatchActive,
 }: Args) {
-  async function detectForImage(dataUrl: string): Promise<BoundingBox[]> {
-    const base64Image = dataUrl.split(",")[1];
-    if (usePanelDetection) {
-      const [bubbleResult, panelResult] = await Promise.all([
-        invoke<YoloDetectionResult>("detect_text_areas", {
-          apiUrl: apiBaseUrl,
-          imageData: base64Image,
-        }),
-        invoke<PanelDetectionResult>("detect_panels", {
-          apiUrl: apiBaseUrl,
-          imageData: base64Image,
-        }),
-      ]);
-      return sortBubblesByPanels(bubbleResult.boxes, panelResult.panels || []);
-    }
-    const bubbleResult = await invoke<YoloDetectionResult>(
-      "detect_text_areas",
-      { apiUrl: apiBaseUrl, imageData: base64Image }
-    );
-    return sortBubblesByPanels(bubbleResult.boxes, []);
-  }
-
-  async function cropRegion(dataUrl: string, b: BoundingBox): Promise<string> {
-    return new Promise((resolve, reject) => {
-      const img = new Image();
-      img.onload = () => {
-        const w = b.x2 - b.x1,
-          h = b.y2 - b.y1;
-        const c = document.createElement("canvas");
-        c.width = w;
-        c.height = h;
-        const ctx = c.getContext("2d");
-        if (!ctx) return reject(new Error("2D context error"));
-        ctx.drawImage(img, b.x1, b.y1, w, h, 0, 0, w, h);
-        resolve(c.toDataURL("image/jpeg"));
-      };
-      img.onerror = () => reject(new Error("Load image error"));
-      img.src = dataUrl;
+  async function detectForImage(src: string): Promise<BoundingBox[]> {
+    const dataUrl = await toDataUrl(src);
+    const page = await invoke<DetectedPage>("detect_page", {
+      apiUrl: apiBaseUrl,
+      payload: { image_data: dataUrl.split(",")[1] },
+      options: {
+        maxSide: detectMaxSide || null,
+        tiling: tiledDetection ? {} : null,
+        usePanels: usePanelDetection,
+        panelSource,
+        reading: { mode: readingMode },
+        postprocess,
+      },
     });
+    return page.boxes;
   }
 
-  async function ocrForImage(dataUrl: string, boxes: BoundingBox[]) {
-    const crops = await Promise.all(boxes.map((b) => cropRegion(dataUrl, b)));
-    const base64Images = crops.map((d) => d.split(",")[1]);
-    const data = await invoke<RecognizeBatchResponse>(
-      "recognize_images_batch",
-      {
-        apiUrl: apiBaseUrl,
-        imagesData: base64Images,
-        engine: "manga",
-        langs: undefined,
-        autoRotate: false,
-      }
-    );
+  // Кропы режет Rust из оригинала (commands/regions.rs)
+  async function ocrForImage(
+    path: string,
+    dataUrl: string,
+    boxes: BoundingBox[]
+  ) {
+    const onDisk = !path.startsWith("temp://");
+    const data = await invoke<RecognizeBatchResponse>("recognize_regions", {
+      apiUrl: apiBaseUrl,
+      path,
+      dataUrl: onDisk ? null : dataUrl,
+      boxes,
+      engine: "manga",
+      options: ocrCrop,
+    });
     return data.results || [];
   }
 
@@ -324,7 +285,7 @@ export function useProcessAll({
 
       setProgress({ active: true, current: 1, total: 3, label: "OCR" });
 
-      const ocrTexts = await ocrForImage(imageSrc, boxes);
+      const ocrTexts = await ocrForImage(current.path, imageSrc, boxes);
       const withOcr = items.map((it, i) => ({
         ...it,
         ocrText: ocrTexts[i] || null,
@@ -353,69 +314,73 @@ export function useProcessAll({
     }
   }
 
-  async function processAllImagesAll(imageList: ImageInfo[]) {
-    if (!imageList.length) return;
-    setBatchActive(true);
+  function itemsFromJob(page: JobPage): DetectedTextItem[] {
+    return page.items.map((it, i) => ({
+      ...it,
+      id: i + 1,
+      textProperties: DEFAULT_TEXT_PROPERTIES,
+    }));
+  }
 
-    const total = imageList.length * 3;
-    let done = 0;
-    setProgress({ active: true, current: 0, total, label: "Detecting" });
+  function progressLabel(p: JobProgress): string {
+    if (p.state === "paused") return "Paused";
+    const eta =
+      p.etaSeconds != null ? ` · ~${Math.ceil(p.etaSeconds)}s left` : "";
+    const failed = p.failed ? ` · ${p.failed} failed` : "";
+    const journal = p.journalError ? " · progress is not being saved" : "";
+    // ожидание лимита провайдера (commands/ratelimit.rs) запросом этой задачи
+    const w = p.limitWait;
+    const secs = w?.waitMs ? ` ${Math.ceil(w.waitMs / 1000)}s` : "";
+    const limit = w ? ` · waiting for ${w.urlPrefix} (${w.reason}${secs})` : "";
+    return `Detected ${p.detected}/${p.total}, OCR ${p.recognized}/${p.total}, translated ${p.translated}/${p.total}${failed}${eta}${journal}${limit}`;
+  }
 
-    const CONC = 1; // последовательная обработка
+  // Следит за задачей по "job-progress" до завершения или отмены
+  async function trackJob(start: () => Promise<string>, pageCount: number) {
+    setBatchActive(true);
 
-    const results = await runInPool(imageList, CONC, async (img) => {
-      const dataUrl = await ensureDataUrl(img);
+    const total = pageCount * 3;
+    setProgress({ active: true, current: 0, total, label: "Starting" });
 
-      const boxes = await detectForImage(dataUrl);
-      const items: DetectedTextItem[] = boxes.map((box, i) => ({
-        id: i + 1,
-        box,
-        ocrText: null,
-        translation: null,
-        cachedIntermediateText: null,
-        cachedIntermediateLang: null,
-        textProperties: DEFAULT_TEXT_PROPERTIES,
-      }));
+    let jobId: string | null = null;
+    const finished = new Promise<void>(async (resolve) => {
+      const unlisten = await listen<JobProgress>("job-progress", (ev) => {
+        const p = ev.payload;
+        if (!p || (jobId && p.jobId !== jobId)) return;
 
-      setImageList((prev) =>
-        prev.map((im) => (im.path === img.path ? { ...im, items } : im))
-      );
-      setProgress({
-        active: true,
-        current: ++done,
-        total,
-        label: "OCR",
-      });
+        if (p.page && p.page.items.length) {
+          const items = itemsFromJob(p.page);
+          const path = p.page.path;
+          setImageList((prev) =>
+            prev.map((im) => (im.path === path ? { ...im, items } : im))
+          );
+        }
+        setProgress({
+          active: true,
+          current: p.detected + p.recognized + p.translated,
+          total,
+          label: progressLabel(p),
+        });
 
-      const ocrTexts = await ocrForImage(dataUrl, boxes);
-      const withOcr = items.map((it, i) => ({
-        ...it,
-        ocrText: ocrTexts[i] || null,
-      }));
-      setImageList((prev) =>
-        prev.map((im) =>
-          im.path === img.path ? { ...im, items: withOcr } : im
-        )
-      );
-      setProgress({
-        active: true,
-        current: ++done,
-        total,
-        label: "Translating",
+        if (p.state === "completed" || p.state === "cancelled") {
+          unlisten();
+          resolve();
+        }
       });
 
-      return { path: img.path, ocrTexts };
+      try {
+        jobId = await start();
+        activeJobId = jobId;
+      } catch (e: any) {
+        console.error(e);
+        alert(`Process failed: ${e?.message || e}`);
+        unlisten();
+        resolve();
+      }
     });
 
-    await runInPool(results, CONC, async (r) => {
-      await startTranslateStreamForImage(r.path, r.ocrTexts);
-      setProgress({
-        active: true,
-        current: ++done,
-        total,
-        label: "Done",
-      });
-    });
+    await finished;
+    activeJobId = null;
 
     // Не переключаем текущую страницу автоматически
 
@@ -426,5 +391,120 @@ export function useProcessAll({
     setBatchActive(false);
   }
 
-  return { processCurrentAll, processAllImagesAll };
+  // Вся партия живёт в Rust (commands/jobs.rs): этапы идут конвейером,
+  // результаты журналируются на диск, а UI только слушает "job-progress"
+  async function processAllImagesAll(imageList: ImageInfo[]) {
+    const pages = imageList
+      .filter((img) => !img.path.startsWith("temp://"))
+      .map((img) => ({ path: img.path, name: img.name }));
+    if (!pages.length) return;
+
+    await trackJob(
+      () =>
+        invoke<string>("start_batch_job", {
+          pages,
+          options: {
+            apiUrl: apiBaseUrl,
+            usePanelDetection,
+            panelSource,
+            detectMaxSide: detectMaxSide || null,
+            tiling: tiledDetection ? {} : null,
+            reading: { mode: readingMode },
+            postprocess,
+            ocrEngine: "manga",
+            crop: ocrCrop,
+            translationUrl,
+            model: selectedModel,
+            systemPrompt,
+            temperature: 0.2,
+            maxTokens: 1500,
+            deeplx: enableTwoStepTranslation
+              ? {
+                  url: deeplxUrl,
+                  apiKey: deeplxApiKey || null,
+                  targetLang: deeplTargetLang || "RU",
+                }
+              : null,
+            projectId,
+          },
+        }),
+      pages.length
+    );
+  }
+
+  // При запуске: незаконченные задачи из журнала (падение приложения посреди партии)
+  async function offerResumeJobs() {
+    const { jobs, broken } = await invoke<ResumableJobs>("list_resumable_jobs");
+    for (const b of broken) {
+      console.warn(`Skipping broken job journal ${b.path}: ${b.error}`);
+    }
+    for (const j of jobs) {
+      const failed = j.failed ? `, ${j.failed} failed` : "";
+      const ok = confirm(
+        `An unfinished batch was found (${j.done}/${j.total} pages done${failed}). Resume it?`
+      );
+      if (!ok) {
+        await invoke("discard_job", { jobId: j.jobId });
+        continue;
+      }
+      // страниц может не быть в списке после перезапуска — добавляем, картинки грузятся лениво
+      setImageList((prev) => {
+        const known = new Set(prev.map((im) => im.path));
+        const missing = j.pages
+          .filter((p) => !known.has(p.path))
+          .map((p) => ({ ...p, dataUrl: "", thumbnail: "" }));
+        return missing.length ? [...prev, ...missing] : prev;
+      });
+      await trackJob(
+        () =>
+          invoke<string>("resume_batch_job", {
+            jobId: j.jobId,
+            // ключ DeepLX в журнале не хранится
+            deeplxApiKey: deeplxApiKey || null,
+          }),
+        j.total
+      );
+      // одна задача за раз
+      break;
+    }
+  }
+
+  const pauseBatch = () =>
+    activeJobId && invoke("pause_job", { jobId: activeJobId });
+  const resumeBatch = () =>
+    activeJobId && invoke("resume_job", { jobId: activeJobId });
+  const cancelBatch = () =>
+    activeJobId && invoke("cancel_job", { jobId: activeJobId });
+
+  // После перезагрузки webview задача продолжает работать в Rust — забираем результаты
+  async function restoreJobs() {
+    const jobs = await invoke<JobProgress[]>("list_jobs");
+    for (const j of jobs) {
+      const status = await invoke<JobStatus>("get_job_status", {
+        jobId: j.jobId,
+      });
+      const byPath = new Map(status.pages.map((p) => [p.path, p]));
+      setImageList((prev) =>
+        prev.map((im) => {
+          const page = byPath.get(im.path);
+          return page && page.items.length
+            ? { ...im, items: itemsFromJob(page) }
+            : im;
+        })
+      );
+      if (status.state === "running" || status.state === "paused") {
+        activeJobId = status.jobId;
+      }
+    }
+  }
+
+  return {
+    processCurrentAll,
+    processAllImagesAll,
+    pauseBatch,
+    resumeBatch,
+    cancelBatch,
+    restoreJobs,
+    offerResumeJobs,
+  };
 }
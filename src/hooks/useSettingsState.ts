@@ -1,5 +1,15 @@
 // src/hooks/useSettingsState.ts
 import { useEffect, useState } from "preact/hooks";
+import { invoke } from "@tauri-apps/api/core";
+import {
+  CropOptions,
+  EndpointAuth,
+  HttpConfig,
+  ModelPrice,
+  RateLimit,
+  PanelSource,
+  ReadingMode,
+} from "../types";
 
 const DEFAULT_SYSTEM_PROMPT = `You are an expert manga translator.
 Translate each numbered Japanese line into natural English.
@@ -9,6 +19,12 @@ Example:
 1. こんにちは -> 1. Hello
 2. さようなら -> 2. Goodbye`;
 
+const DEFAULT_OCR_CROP: CropOptions = {
+  padding: 4,
+  rotateVertical: false,
+  minSide: 32,
+};
+
 export function useSettingsState() {
   const [apiBaseUrl, setApiBaseUrl] = useState(
     () => localStorage.getItem("apiBaseUrl") || "http://localhost:8000"
@@ -26,9 +42,44 @@ export function useSettingsState() {
   const [detectionModel, setDetectionModel] = useState(
     () => localStorage.getItem("detectionModel") || "bubbles_yolo"
   );
+  // Детекция на уменьшенной копии страницы (большая сторона, px; 0 — оригинал)
+  const [detectMaxSide, setDetectMaxSide] = useState(() =>
+    parseInt(localStorage.getItem("detectMaxSide") || "0", 10)
+  );
+  // Длинные страницы (вебтуны) детектировать по перекрывающимся тайлам
+  const [tiledDetection, setTiledDetection] = useState(
+    () => localStorage.getItem("tiledDetection") !== "false"
+  );
+  const [panelSource, setPanelSource] = useState<PanelSource>(
+    () => (localStorage.getItem("panelSource") as PanelSource) || "backend"
+  );
+  // Чистка боксов после детекции: дубли, мусор, разорванные баблы
+  const [cleanupBoxes, setCleanupBoxes] = useState(
+    () => localStorage.getItem("cleanupBoxes") !== "false"
+  );
+  const [minBoxArea, setMinBoxArea] = useState(() =>
+    parseInt(localStorage.getItem("minBoxArea") || "150", 10)
+  );
+  // Импорт папки: подпапки как главы, include/exclude — glob через запятую
+  const [recursiveImport, setRecursiveImport] = useState(
+    () => localStorage.getItem("recursiveImport") !== "false"
+  );
+  const [importInclude, setImportInclude] = useState(
+    () => localStorage.getItem("importInclude") || ""
+  );
+  const [importExclude, setImportExclude] = useState(
+    () => localStorage.getItem("importExclude") || ""
+  );
+  // Порядок чтения баблов: манга (RTL), комиксы (LTR), вебтун (сверху вниз)
+  const [readingMode, setReadingMode] = useState<ReadingMode>(
+    () => (localStorage.getItem("readingMode") as ReadingMode) || "rtl"
+  );
   const [streamTranslation, setStreamTranslation] = useState(
     () => localStorage.getItem("streamTranslation") === "true"
   );
+  const [visionTranslation, setVisionTranslation] = useState(
+    () => localStorage.getItem("visionTranslation") === "true"
+  );
   const [enableTwoStepTranslation, setEnableTwoStepTranslation] = useState(
     () => localStorage.getItem("enableTwoStepTranslation") === "true"
   );
@@ -41,12 +92,56 @@ export function useSettingsState() {
     () => localStorage.getItem("deeplTargetLang") || "RU"
   );
   const [ocrEngine, setOcrEngine] = useState<"manga">(() => "manga");
+  // Кропы для OCR режет Rust: отступ, поворот вертикальных, апскейл мелких
+  const [ocrCrop, setOcrCrop] = useState<CropOptions>(() => {
+    try {
+      return {
+        ...DEFAULT_OCR_CROP,
+        ...JSON.parse(localStorage.getItem("ocrCrop") || "{}"),
+      };
+    } catch {
+      return DEFAULT_OCR_CROP;
+    }
+  });
   const [showCanvasText, setShowCanvasText] = useState(
     () => localStorage.getItem("showCanvasText") !== "false"
   );
   const [inpaintModel, setInpaintModel] = useState(
     () => localStorage.getItem("inpaintModel") || "lama_large_512px"
   );
+  // JSON: { "model-id": { "promptPerMillion": 0.15, "completionPerMillion": 0.6 } }
+  const [modelPrices, setModelPrices] = useState(
+    () => localStorage.getItem("modelPrices") || "{}"
+  );
+  const [proxyUrl, setProxyUrl] = useState(
+    () => localStorage.getItem("proxyUrl") || ""
+  );
+  const [noProxy, setNoProxy] = useState(
+    () => localStorage.getItem("noProxy") || "localhost,127.0.0.1"
+  );
+  // Пути к дополнительным CA-сертификатам, по одному на строку
+  const [caCertificates, setCaCertificates] = useState(
+    () => localStorage.getItem("caCertificates") || ""
+  );
+  // JSON: [{ "urlPrefix": "https://ocr.example", "bearer": "...", "headers": {} }]
+  const [endpointAuth, setEndpointAuth] = useState(
+    () => localStorage.getItem("endpointAuth") || "[]"
+  );
+  // JSON: [{ "urlPrefix": "https://api.deeplx.org", "requestsPerMinute": 30, "maxConcurrent": 2 }]
+  const [rateLimits, setRateLimits] = useState(
+    () => localStorage.getItem("rateLimits") || "[]"
+  );
+  // Лимит дискового кэша ответов бэкенда, МБ (0 — выключен)
+  const [resultCacheMb, setResultCacheMb] = useState(() =>
+    parseInt(localStorage.getItem("resultCacheMb") || "512", 10)
+  );
+  // Вебтуны: высота кусков при перенарезке и при экспорте (0 — экспорт как есть)
+  const [webtoonSliceHeight, setWebtoonSliceHeight] = useState(() =>
+    parseInt(localStorage.getItem("webtoonSliceHeight") || "2000", 10)
+  );
+  const [exportSliceHeight, setExportSliceHeight] = useState(() =>
+    parseInt(localStorage.getItem("exportSliceHeight") || "0", 10)
+  );
   const [defaultBrushSize, setDefaultBrushSize] = useState(() =>
     parseInt(localStorage.getItem("defaultBrushSize") || "20", 10)
   );
@@ -69,10 +164,50 @@ export function useSettingsState() {
     () => localStorage.setItem("detectionModel", detectionModel),
     [detectionModel]
   );
+  useEffect(
+    () => localStorage.setItem("detectMaxSide", String(detectMaxSide)),
+    [detectMaxSide]
+  );
+  useEffect(
+    () => localStorage.setItem("tiledDetection", String(tiledDetection)),
+    [tiledDetection]
+  );
+  useEffect(
+    () => localStorage.setItem("panelSource", panelSource),
+    [panelSource]
+  );
+  useEffect(
+    () => localStorage.setItem("cleanupBoxes", String(cleanupBoxes)),
+    [cleanupBoxes]
+  );
+  useEffect(
+    () => localStorage.setItem("minBoxArea", String(minBoxArea)),
+    [minBoxArea]
+  );
+  useEffect(
+    () => localStorage.setItem("recursiveImport", String(recursiveImport)),
+    [recursiveImport]
+  );
+  useEffect(
+    () => localStorage.setItem("importInclude", importInclude),
+    [importInclude]
+  );
+  useEffect(
+    () => localStorage.setItem("importExclude", importExclude),
+    [importExclude]
+  );
+  useEffect(
+    () => localStorage.setItem("readingMode", readingMode),
+    [readingMode]
+  );
   useEffect(
     () => localStorage.setItem("streamTranslation", String(streamTranslation)),
     [streamTranslation]
   );
+  useEffect(
+    () => localStorage.setItem("visionTranslation", String(visionTranslation)),
+    [visionTranslation]
+  );
   useEffect(
     () =>
       localStorage.setItem(
@@ -86,6 +221,10 @@ export function useSettingsState() {
     () => localStorage.setItem("deeplTargetLang", deeplTargetLang),
     [deeplTargetLang]
   );
+  useEffect(
+    () => localStorage.setItem("ocrCrop", JSON.stringify(ocrCrop)),
+    [ocrCrop]
+  );
   useEffect(
     () => localStorage.setItem("showCanvasText", String(showCanvasText)),
     [showCanvasText]
@@ -98,6 +237,55 @@ export function useSettingsState() {
     () => localStorage.setItem("defaultBrushSize", String(defaultBrushSize)),
     [defaultBrushSize]
   );
+  useEffect(() => {
+    localStorage.setItem("webtoonSliceHeight", String(webtoonSliceHeight));
+    localStorage.setItem("exportSliceHeight", String(exportSliceHeight));
+  }, [webtoonSliceHeight, exportSliceHeight]);
+  useEffect(() => {
+    localStorage.setItem("proxyUrl", proxyUrl);
+    localStorage.setItem("noProxy", noProxy);
+    localStorage.setItem("caCertificates", caCertificates);
+    localStorage.setItem("endpointAuth", endpointAuth);
+    localStorage.setItem("rateLimits", rateLimits);
+    let auth: EndpointAuth[];
+    let limits: RateLimit[];
+    try {
+      auth = JSON.parse(endpointAuth);
+      limits = JSON.parse(rateLimits);
+    } catch {
+      return; // JSON ещё редактируется
+    }
+    const config: HttpConfig = {
+      proxy: proxyUrl.trim() ? { url: proxyUrl.trim(), noProxy } : null,
+      caCertificates: caCertificates
+        .split(/\r?\n/)
+        .map((l) => l.trim())
+        .filter(Boolean),
+      auth: Array.isArray(auth) ? auth : [],
+      limits: Array.isArray(limits) ? limits : [],
+    };
+    invoke("set_http_config", { config }).catch((e) =>
+      console.error("Failed to apply network settings:", e)
+    );
+  }, [proxyUrl, noProxy, caCertificates, endpointAuth, rateLimits]);
+  useEffect(() => {
+    localStorage.setItem("resultCacheMb", String(resultCacheMb));
+    invoke("set_cache_limit", {
+      maxMb: Math.max(0, Math.floor(resultCacheMb || 0)),
+    }).catch((e) => console.error("Failed to set cache limit:", e));
+  }, [resultCacheMb]);
+  useEffect(() => {
+    localStorage.setItem("modelPrices", modelPrices);
+    let prices: Record<string, ModelPrice>;
+    try {
+      prices = JSON.parse(modelPrices);
+    } catch {
+      return; // пока пользователь печатает — JSON может быть невалидным
+    }
+    invoke("set_model_prices", { prices }).catch((e) =>
+      console.error("Failed to set model prices:", e)
+    );
+  }, [modelPrices]);
 
   return {
     apiBaseUrl,
@@ -110,8 +298,28 @@ export function useSettingsState() {
     setUsePanelDetection,
     detectionModel,
     setDetectionModel, // ИЗМЕНЕНО: Экспортируем новое состояние
+    detectMaxSide,
+    setDetectMaxSide,
+    tiledDetection,
+    setTiledDetection,
+    readingMode,
+    setReadingMode,
+    panelSource,
+    setPanelSource,
+    cleanupBoxes,
+    setCleanupBoxes,
+    minBoxArea,
+    setMinBoxArea,
+    recursiveImport,
+    setRecursiveImport,
+    importInclude,
+    setImportInclude,
+    importExclude,
+    setImportExclude,
     streamTranslation,
     setStreamTranslation,
+    visionTranslation,
+    setVisionTranslation,
     enableTwoStepTranslation,
     setEnableTwoStepTranslation,
     deeplxUrl,
@@ -121,11 +329,31 @@ export function useSettingsState() {
     setDeeplTargetLang,
     ocrEngine,
     setOcrEngine,
+    ocrCrop,
+    setOcrCrop,
     showCanvasText,
     setShowCanvasText,
     inpaintModel,
     setInpaintModel,
     defaultBrushSize,
     setDefaultBrushSize,
+    modelPrices,
+    setModelPrices,
+    proxyUrl,
+    setProxyUrl,
+    noProxy,
+    setNoProxy,
+    caCertificates,
+    setCaCertificates,
+    endpointAuth,
+    setEndpointAuth,
+    rateLimits,
+    setRateLimits,
+    resultCacheMb,
+    setResultCacheMb,
+    webtoonSliceHeight,
+    setWebtoonSliceHeight,
+    exportSliceHeight,
+    setExportSliceHeight,
   };
 }
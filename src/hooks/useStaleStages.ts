@@ -0,0 +1,133 @@
+// src/hooks/useStaleStages.ts
+import { useCallback, useEffect, useState } from "preact/hooks";
+import { invoke } from "@tauri-apps/api/core";
+import {
+  CropOptions,
+  DetectedTextItem,
+  ImageInfo,
+  RefreshResult,
+  StaleReport,
+} from "../types";
+import { ProgressState } from "../types/ui";
+
+type SetState<T> = (value: T | ((prev: T) => T)) => void;
+
+type Args = {
+  image: ImageInfo | undefined;
+  items: DetectedTextItem[] | null;
+  apiBaseUrl: string;
+  ocrEngine: string;
+  ocrCrop?: CropOptions;
+  translationUrl: string;
+  selectedModel: string;
+  systemPrompt: string;
+  enableTwoStepTranslation: boolean;
+  deeplxUrl: string;
+  deeplxApiKey: string;
+  deeplTargetLang: string;
+  inpaintModel?: string;
+  projectId?: string;
+  setDetectedItems: (
+    updater: (prev: DetectedTextItem[] | null) => DetectedTextItem[] | null
+  ) => void;
+  onImageUpdate: (dataUrl: string) => void;
+  onInpaintHash: (hash: string | null) => void;
+  setProgress: SetState<ProgressState>;
+};
+
+// Страница в виде, который понимает commands/stages.rs
+function toStagePage(image: ImageInfo, items: DetectedTextItem[]) {
+  return {
+    path: image.path,
+    name: image.name,
+    // у temp:// страниц нет файла — оригинал только в dataUrl
+    dataUrl: image.path.startsWith("temp://") ? image.dataUrl : null,
+    items,
+    maskDataUrl: image.maskDataUrl ?? null,
+    inpaintHash: image.inpaintHash ?? null,
+  };
+}
+
+// Устаревшие этапы текущей страницы и их выборочный перезапуск
+export function useStaleStages(args: Args) {
+  const { image, items } = args;
+  const [report, setReport] = useState<StaleReport | null>(null);
+
+  useEffect(() => {
+    if (!image || !items?.length) {
+      setReport(null);
+      return;
+    }
+    let cancelled = false;
+    invoke<StaleReport>("find_stale_items", {
+      page: toStagePage(image, items),
+      ocr: { engine: args.ocrEngine, crop: args.ocrCrop },
+    })
+      .then((r) => !cancelled && setReport(r))
+      .catch((e) => console.warn("find_stale_items failed:", e));
+    return () => {
+      cancelled = true;
+    };
+  }, [
+    image?.path,
+    image?.maskDataUrl,
+    image?.inpaintHash,
+    items,
+    args.ocrEngine,
+    args.ocrCrop,
+  ]);
+
+  const refreshStale = useCallback(async () => {
+    if (!image || !items?.length) return;
+    args.setProgress({
+      active: true,
+      current: 0,
+      total: 1,
+      label: "Refreshing stale items",
+    });
+    try {
+      const result = await invoke<RefreshResult>("refresh_stale", {
+        page: toStagePage(image, items),
+        options: {
+          apiUrl: args.apiBaseUrl,
+          ocrEngine: args.ocrEngine,
+          crop: args.ocrCrop,
+          translationUrl: args.translationUrl,
+          model: args.selectedModel,
+          systemPrompt: args.systemPrompt,
+          temperature: 0.2,
+          maxTokens: 1500,
+          deeplx: args.enableTwoStepTranslation
+            ? {
+                url: args.deeplxUrl,
+                apiKey: args.deeplxApiKey || null,
+                targetLang: args.deeplTargetLang || "RU",
+              }
+            : null,
+          projectId: args.projectId,
+        },
+        inpaintModel: args.inpaintModel,
+      });
+      const byId = new Map(result.items.map((it) => [it.id, it]));
+      args.setDetectedItems((prev) =>
+        (prev || []).map((it) => {
+          const fresh = byId.get(it.id);
+          // textProperties живут только на фронте
+          return fresh ? { ...fresh, textProperties: it.textProperties } : it;
+        })
+      );
+      if (result.imageData) {
+        args.onImageUpdate(`data:image/png;base64,${result.imageData}`);
+      }
+      args.onInpaintHash(result.inpaintHash);
+      setReport(result.report);
+    } catch (e: any) {
+      console.error("Refresh failed:", e);
+      alert(`Refresh failed: ${e?.message || e}`);
+    } finally {
+      args.setProgress({ active: false, current: 0, total: 0, label: "" });
+    }
+  }, [image, items, args]);
+
+  return { staleReport: report, refreshStale };
+}
@@ -2,8 +2,15 @@
 import { useCallback } from "preact/hooks";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
-import { DeepLXResponse, DetectedTextItem, LoadingState } from "../types";
+import {
+  DeepLXResponse,
+  DetectedTextItem,
+  LoadingState,
+  UsageContext,
+  VisionContext,
+} from "../types";
 import { parseNumberedLinesToPairs, chatCompletion } from "../utils/llm";
+import { stageHashFor, stageInputHashes } from "../utils/stages";
 
 type SetItemsUpdater = (
   updater: (prev: DetectedTextItem[] | null) => DetectedTextItem[] | null
@@ -20,6 +27,9 @@ interface UseTranslationArgs {
   deeplxUrl: string;
   deeplxApiKey: string;
   streamTranslation?: boolean;
+  visionTranslation?: boolean;
+  imagePath?: string | null;
+  usage?: UsageContext;
   setDetectedItems: SetItemsUpdater;
   setIsLoading: SetLoading;
   onStreamUpdate: (content: string) => void;
@@ -40,6 +50,9 @@ export function useTranslation({
   deeplxUrl,
   deeplxApiKey,
   streamTranslation = false,
+  visionTranslation = false,
+  imagePath = null,
+  usage,
   setDetectedItems,
   setIsLoading,
   onStreamUpdate,
@@ -54,9 +67,20 @@ export function useTranslation({
     if (!itemsToTranslate.length) return;
 
     setIsLoading((p) => ({ ...p, translate: true }));
+    // Переводим именно этот OCR-текст — его хэш и есть вход перевода
+    const hashes = await stageInputHashes(imagePath, itemsToTranslate);
     // Сбрасываем предыдущий перевод
     setDetectedItems((prev) =>
-      (prev || []).map((item) => ({ ...item, translation: null }))
+      (prev || []).map((item) => ({
+        ...item,
+        translation: null,
+        stageHashes: {
+          ...item.stageHashes,
+          translation:
+            stageHashFor(hashes, item.id, "translation") ??
+            item.stageHashes?.translation,
+        },
+      }))
     );
 
     try {
@@ -64,6 +88,19 @@ export function useTranslation({
         .map((i) => `${i.id}. ${i.ocrText}`)
         .join("\n");
 
+      // Vision-режим: картинку страницы Rust читает сам, из оригинала на диске
+      const vision: VisionContext | undefined =
+        visionTranslation && imagePath && !imagePath.startsWith("temp://")
+          ? {
+              imagePath,
+              lines: itemsToTranslate.map((i) => ({
+                id: i.id,
+                text: i.ocrText || "",
+                box: i.box,
+              })),
+            }
+          : undefined;
+
       // --- ЛОГИКА ДЛЯ СТРИМИНГА ---
       if (streamTranslation) {
         const streamId = uuid();
@@ -180,6 +217,8 @@ export function useTranslation({
             max_tokens: 1500,
           },
           streamId,
+          vision,
+          usage,
         });
         return;
       }
@@ -189,7 +228,10 @@ export function useTranslation({
         translationUrl,
         selectedModel,
         systemPrompt,
-        numberedJP
+        numberedJP,
+        0.2,
+        vision,
+        usage
       );
       const pairsEN = parseNumberedLinesToPairs(rawEN);
       if (!pairsEN.length) throw new Error("Could not parse LLM response.");
@@ -251,6 +293,9 @@ export function useTranslation({
     deeplxUrl,
     deeplxApiKey,
     streamTranslation,
+    visionTranslation,
+    imagePath,
+    usage,
     setDetectedItems,
     setIsLoading,
     onStreamUpdate,
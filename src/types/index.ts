@@ -37,6 +37,36 @@ export interface DetectedTextItem {
   cachedIntermediateText: string | null;
   cachedIntermediateLang: string | null;
   textProperties?: TextProperties;
+  // хэши входов, из которых получены OCR и перевод (commands/stages.rs)
+  stageHashes?: StageHashes;
+}
+
+export interface StageHashes {
+  ocr?: string | null;
+  translation?: string | null;
+}
+
+export interface StaleReport {
+  items: { id: number; stages: ("ocr" | "translation")[] }[];
+  inpaint: boolean;
+}
+
+// Чем делают OCR — входит в хэш кропа (commands/stages.rs OcrInput)
+export interface OcrInput {
+  engine?: string;
+  crop?: CropOptions;
+}
+
+export interface InputHashes {
+  items: { id: number; ocr: string; translation: string | null }[];
+  inpaint: string | null;
+}
+
+export interface RefreshResult {
+  items: DetectedTextItem[];
+  imageData: string | null;
+  inpaintHash: string | null;
+  report: StaleReport;
 }
 
 export interface LoadingState {
@@ -64,12 +94,70 @@ export interface TextItem {
 export interface ImageInfo {
   name: string;
   path: string;
+  // подпапка импорта ("Vol 01/Ch 003"), если страница из главы
+  chapter?: string | null;
+  // исходный файл, если path — пересохранённая в PNG копия (commands/formats.rs)
+  originalPath?: string | null;
+  // что сделано при импорте: боксы, маски и экспорт считаются в пикселях path
+  normalization?: Normalization | null;
   dataUrl: string;
   thumbnail: string;
+  // размеры оригинала, если Rust их уже знает (превью из commands/thumbs.rs)
+  width?: number | null;
+  height?: number | null;
   // результаты обработки для страницы (детект/ocr/перевод)
   items?: DetectedTextItem[] | null;
   maskDataUrl?: string | null;
   finalDataUrl?: string | null;
+  // хэш маски, по которой сделан последний инпейнт
+  inpaintHash?: string | null;
+}
+
+// Нормализация страницы при импорте (commands/normalize.rs)
+export interface Normalization {
+  convertedFrom: "tiff" | "avif" | "heic" | null;
+  // применённая EXIF-ориентация, 1 — без поворота
+  orientation: number;
+  // ICC-профиль исходника, если цвета переведены в sRGB
+  iccProfile: string | null;
+}
+
+// Итог импорта: нечитаемые файлы не попадают в список, а перечислены здесь
+export interface RejectedFile {
+  path: string;
+  reason: string;
+}
+
+export interface ImportReport {
+  images: ImageInfo[];
+  converted: number;
+  rejected: RejectedFile[];
+}
+
+// Потоковый импорт папки (commands/folder.rs): события import-batch и import-progress
+export interface ImportBatch {
+  scanId: string;
+  images: ImageInfo[];
+  rejected: RejectedFile[];
+}
+
+export interface ImportProgress {
+  scanId: string;
+  folder: string;
+  // просмотрено файлов / из них похожих на страницы
+  scanned: number;
+  found: number;
+  imported: number;
+  rejected: number;
+  done: boolean;
+  cancelled: boolean;
+}
+
+// commands/folder.rs: обход подпапок и glob-шаблоны относительно выбранной папки
+export interface FolderImportOptions {
+  recursive: boolean;
+  include: string[];
+  exclude: string[];
 }
 
 // Типы для API
@@ -84,10 +172,260 @@ export interface DeepLXResponse {
 export interface RecognizeBatchResponse {
   results: string[];
 }
+
+// Нарезка ленты вебтуна по гуттерам (commands/webtoon.rs)
+export interface SliceOptions {
+  targetHeight: number;
+  search?: number;
+  tolerance?: number;
+  minGutter?: number;
+}
+
+// Кусок после restitch_strip: боксы и маска уже в его координатах
+export interface StripSlice {
+  name: string;
+  path: string;
+  width: number;
+  height: number;
+  items: DetectedTextItem[];
+  maskDataUrl: string | null;
+}
+
+// Подготовка кропов для OCR на стороне Rust (commands/regions.rs)
+export interface CropOptions {
+  padding: number;
+  rotateVertical: boolean;
+  verticalRatio?: number;
+  minSide: number;
+  maxUpscale?: number;
+}
 export interface YoloDetectionResult {
   boxes: BoundingBox[];
 }
 
+// Превью страницы (commands/thumbs.rs)
+export interface Thumb {
+  width: number;
+  height: number;
+  thumbnail: string;
+}
+
+// get_thumbnails: превью или почему его нет
+export interface PageThumb {
+  thumb: Thumb | null;
+  error: string | null;
+}
+
+// Порядок чтения (commands/reading_order.rs)
+export type ReadingMode = "rtl" | "ltr" | "webtoon";
+
+export interface ReadingOptions {
+  mode: ReadingMode;
+  rowOverlap?: number;
+  nestedCoverage?: number;
+}
+
+// Чистка боксов детектора (commands/postprocess.rs); пропущенные поля — по умолчанию
+export interface PostprocessOptions {
+  clamp?: boolean;
+  minArea?: number;
+  minSide?: number;
+  maxAspect?: number;
+  iouThreshold?: number;
+  containment?: number;
+  merge?: boolean;
+  mergeGap?: number;
+  mergeAlignment?: number;
+  mergeFill?: number;
+}
+
+export interface PostprocessChange {
+  kind: "clamped" | "filtered" | "suppressed" | "merged";
+  before: BoundingBox[];
+  after: BoundingBox | null;
+  reason: string;
+}
+
+export interface PostprocessReport {
+  input: number;
+  output: number;
+  clamped: number;
+  filtered: number;
+  suppressed: number;
+  merged: number;
+  changes: PostprocessChange[];
+}
+
+// Откуда брать панели: модель бэкенда, гуттеры (офлайн) или модель со сверкой
+export type PanelSource = "backend" | "gutters" | "check";
+
+// Сверка панелей модели с гуттерами (commands/gutters.rs)
+export interface PanelCheck {
+  matched: number;
+  missing: [number, number, number, number][];
+  unconfirmed: [number, number, number, number][];
+  agreement: number;
+}
+
+// Ответ detect_page: боксы уже в порядке чтения
+export interface DetectedPage {
+  boxes: BoundingBox[];
+  panels: [number, number, number, number][];
+  report: PostprocessReport | null;
+  panelCheck: PanelCheck | null;
+  // почему панели взяли по гуттерам, а не от модели
+  panelFallback: string | null;
+}
+
+// Контекст страницы для vision-моделей (картинка читается в Rust с диска)
+export interface VisionContext {
+  imagePath: string;
+  lines: { id: number; text: string; box: BoundingBox }[];
+  maxSide?: number;
+}
+
+// Куда относить токены запроса: проект / страница / запуск
+export interface UsageContext {
+  projectId: string;
+  page?: string;
+  runId?: string;
+}
+
+export interface TokenUsage {
+  promptTokens: number;
+  completionTokens: number;
+  requests: number;
+  cost: number;
+}
+
+export interface ModelPrice {
+  promptPerMillion: number;
+  completionPerMillion: number;
+}
+
+export interface UsageReport {
+  projectId: string;
+  usage: {
+    total: TokenUsage;
+    byPage: Record<string, TokenUsage>;
+    byRun: Record<string, TokenUsage>;
+    byModel: Record<string, TokenUsage>;
+  };
+  prices: Record<string, ModelPrice>;
+}
+
+// Ответ probe_backend: что умеет сервер детекции/OCR/инпейнта
+export interface BackendCapabilities {
+  apiUrl: string;
+  reachable: boolean;
+  latencyMs: number | null;
+  version: string | null;
+  endpoints: Record<string, boolean>;
+  models: { detection: string[]; ocr: string[]; inpaint: string[] };
+  modelsKnown: boolean;
+  // как Rust шлёт картинки на эндпоинт: JSON с base64 или бинарно
+  transports: Record<string, "json" | "multipart" | "raw">;
+  warnings: string[];
+}
+
+// Сетевые настройки всех запросов из Rust (set_http_config)
+export interface EndpointAuth {
+  urlPrefix: string;
+  bearer?: string | null;
+  basic?: { username: string; password?: string | null } | null;
+  headers?: Record<string, string>;
+}
+
+export interface HttpConfig {
+  proxy: {
+    url: string;
+    username?: string | null;
+    password?: string | null;
+    noProxy?: string | null;
+  } | null;
+  caCertificates: string[];
+  auth: EndpointAuth[];
+  limits: RateLimit[];
+}
+
+export interface RateLimit {
+  urlPrefix: string;
+  requestsPerMinute?: number | null;
+  tokensPerMinute?: number | null;
+  maxConcurrent?: number | null;
+}
+
+// commands/cache.rs
+export interface CacheStats {
+  entries: number;
+  bytes: number;
+  maxBytes: number;
+  hits: number;
+  misses: number;
+}
+
+// Ожидание лимита: JobProgress.limitWait или событие "rate-limit-wait" вне задач
+export interface LimitWait {
+  url: string;
+  urlPrefix: string;
+  reason: "concurrency" | "requests" | "tokens";
+  waitMs: number;
+}
+
+// Пакетная обработка в Rust (commands/jobs.rs)
+export type JobState = "running" | "paused" | "cancelled" | "completed";
+export type JobStage = "detect" | "ocr" | "translate";
+
+export interface JobPage {
+  path: string;
+  name: string;
+  status: "pending" | "running" | "done" | "failed";
+  completed: JobStage | null;
+  running: JobStage | null;
+  error: string | null;
+  items: Omit<DetectedTextItem, "id" | "textProperties">[];
+  detectReport?: PostprocessReport | null;
+  panelCheck?: PanelCheck | null;
+  panelFallback?: string | null;
+}
+
+export interface JobProgress {
+  jobId: string;
+  state: JobState;
+  total: number;
+  detected: number;
+  recognized: number;
+  translated: number;
+  failed: number;
+  elapsedMs: number;
+  etaSeconds: number | null;
+  page: JobPage | null;
+  // ошибка записи журнала задачи (commands/jobs.rs)
+  journalError: string | null;
+  // запрос задачи ждёт лимита провайдера
+  limitWait: LimitWait | null;
+}
+
+export interface JobStatus extends JobProgress {
+  pages: JobPage[];
+}
+
+export interface ResumableJob {
+  jobId: string;
+  projectId: string;
+  total: number;
+  done: number;
+  failed: number;
+  updatedAt: number;
+  pages: { path: string; name: string }[];
+}
+
+// Ответ list_resumable_jobs: битые журналы — с причиной, продолжить их нельзя
+export interface ResumableJobs {
+  jobs: ResumableJob[];
+  broken: { path: string; error: string }[];
+}
+
 export interface AppSettings {
   ocrEngine: "manga";
   easyOcrLangs: string;
@@ -97,6 +435,7 @@ export interface AppSettings {
 // Project export/import types
 export interface ProjectMetadata {
   version: string;
+  projectId?: string;
 }
 
 export interface ProjectImageData {
@@ -1,5 +1,6 @@
 // src/utils/llm.ts
 import { invoke } from "@tauri-apps/api/core";
+import { UsageContext, VisionContext } from "../types";
 
 export function stripCodeFences(s: string): string {
   return s
@@ -85,7 +86,9 @@ export async function chatCompletion(
   model: string,
   system: string,
   user: string,
-  temperature = 0.2
+  temperature = 0.2,
+  vision?: VisionContext,
+  usage?: UsageContext
 ): Promise<string> {
   const apiUrl = `${apiBaseUrl.replace(/\/$/, "")}/v1/chat/completions`;
   const resp = await invoke<any>("translate_text", {
@@ -100,6 +103,8 @@ export async function chatCompletion(
       temperature,
       max_tokens: 1500,
     },
+    vision,
+    usage,
   });
   return resp?.choices?.[0]?.message?.content || "";
 }
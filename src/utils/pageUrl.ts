@@ -0,0 +1,30 @@
+// src/utils/pageUrl.ts
+import { convertFileSrc } from "@tauri-apps/api/core";
+
+// Слои страницы, которые отдаёт протокол mtimg (commands/pages.rs)
+export type PageLayer = "original" | "mask" | "final" | "thumb";
+
+// Адрес страницы открытого проекта; страница должна быть зарегистрирована через register_pages
+export function pageUrl(projectId: string, path: string, layer: PageLayer): string {
+  return convertFileSrc(`${projectId}/${path}/${layer}`, "mtimg");
+}
+
+// Страницы без файла на диске (склейка вебтуна и т.п.) живут только в dataUrl
+export function isOnDisk(path: string): boolean {
+  return !path.startsWith("temp://");
+}
+
+// data URL для команд, которым нужен base64 (детект, инпейнт); data URL возвращается как есть
+export async function toDataUrl(src: string): Promise<string> {
+  if (src.startsWith("data:")) return src;
+  const blob = await fetch(src).then((r) => {
+    if (!r.ok) throw new Error(`Failed to load ${src}: ${r.status}`);
+    return r.blob();
+  });
+  return new Promise((resolve, reject) => {
+    const reader = new FileReader();
+    reader.onload = () => resolve(reader.result as string);
+    reader.onerror = () => reject(reader.error);
+    reader.readAsDataURL(blob);
+  });
+}
@@ -0,0 +1,33 @@
+// src/utils/stages.ts
+import { invoke } from "@tauri-apps/api/core";
+import { DetectedTextItem, InputHashes, OcrInput } from "../types";
+
+// Хэши входов этапов для страницы (commands/stages.rs). Ошибка не должна ломать
+// сам OCR/перевод — тогда хэш просто не запишется.
+export async function stageInputHashes(
+  path: string | null | undefined,
+  items: DetectedTextItem[],
+  maskDataUrl?: string | null,
+  ocr?: OcrInput
+): Promise<InputHashes | null> {
+  if (!path) return null;
+  try {
+    return await invoke<InputHashes>("stage_input_hashes", {
+      path,
+      items,
+      maskDataUrl: maskDataUrl ?? null,
+      ocr: ocr ?? null,
+    });
+  } catch (e) {
+    console.warn("stage_input_hashes failed:", e);
+    return null;
+  }
+}
+
+export function stageHashFor(
+  hashes: InputHashes | null,
+  id: number,
+  stage: "ocr" | "translation"
+): string | null | undefined {
+  return hashes?.items.find((h) => h.id === id)?.[stage];
+}